@@ -0,0 +1,63 @@
+//! Measures how lexing and parsing a set of source units with `driver::parse_all` scales with the
+//! number of threads. Run with `cargo bench`.
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use libcyan::diagnostic::AnyDiagnostic;
+use libcyan::driver::parse_all;
+use libcyan::source_unit::SourceMap;
+use libcyan::StrInterner;
+
+const UNIT_COUNT: usize = 256;
+const PROCS_PER_UNIT: usize = 200;
+const RUNS: usize = 5;
+
+/// Returns the path and text of each unit. Identifiers are unique to each unit, so that the
+/// threads intern distinct strings, as they would for distinct files.
+fn sources() -> Vec<(PathBuf, Vec<u8>)> {
+    return (0..UNIT_COUNT).map(|unit| {
+        let mut text = format!("struct Pair{unit}<A, B> {{ first: A, second: B }}\n\n");
+        for i in 0..PROCS_PER_UNIT {
+            text.push_str(&format!("\
+                // Returns the {i}th value.\n\
+                proc f{unit}_{i}(a: Pair{unit}<int, [int; 4]>, b: &mut List<int>): int {{\n    \
+                    let x{i}: int = {i};\n    \
+                    let y = x{i} == b;\n    \
+                    y;\n\
+                }}\n\n"));
+        }
+        (PathBuf::from(format!("unit{unit}.cyan")), text.into_bytes())
+    }).collect();
+}
+
+/// Returns the shortest of `RUNS` times taken to parse `sources` on `threads` threads.
+fn measure(sources: &[(PathBuf, Vec<u8>)], threads: NonZeroUsize) -> Duration {
+    return (0..RUNS).map(|_| {
+        let str_interner = StrInterner::default();
+        let mut source_map = SourceMap::new(&str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let sources = sources.to_vec();
+        let start = Instant::now();
+        parse_all(sources, threads, &mut source_map, &mut diagnostics);
+        let elapsed = start.elapsed();
+        assert!(diagnostics.is_empty());
+        elapsed
+    }).min().unwrap();
+}
+
+fn main() {
+    let sources = sources();
+    let bytes: usize = sources.iter().map(|(_, text)| text.len()).sum();
+    println!("{} units, {:.1} MiB", sources.len(), bytes as f64 / (1024.0 * 1024.0));
+
+    let parallelism = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let mut baseline: Option<Duration> = None;
+    for threads in std::iter::successors(Some(1), |threads| Some(threads * 2)) {
+        if threads > parallelism { break; }
+        let elapsed = measure(&sources, NonZeroUsize::new(threads).unwrap());
+        let baseline = *baseline.get_or_insert(elapsed);
+        println!("{threads:>3} threads: {:>8.2} ms  {:>5.2}x", elapsed.as_secs_f64() * 1000.0,
+            baseline.as_secs_f64() / elapsed.as_secs_f64());
+    }
+}
@@ -1,6 +1,8 @@
-use crate::source_unit::SourceUnitId;
+use crate::source_unit::{SourceMap, SourceUnitId};
 use crate::util::inline_vec::InlineVec;
+use crate::tok::class::describe_kinds;
 use crate::tok::tokbuf;
+use crate::tok::tok::{StaticTok, TokKind};
 
 // -- Diagnostic ---------------------------------------------------------------------------------
 
@@ -18,7 +20,11 @@ pub struct DiagnosticView {
 
 pub enum DiagnosticViewElement {
     SourceQuote(SourceQuote),
-    StaticMessage(&'static str)
+    StaticMessage(&'static str),
+    /// Lists the kinds of tokens which would have been accepted in place of the quoted token.
+    ExpectedToks(&'static [TokKind]),
+    /// States how many arguments were expected and how many were found.
+    ArgCount { expected: u32, found: u32 }
 }
 
 pub struct SourceQuote {
@@ -27,18 +33,123 @@ pub struct SourceQuote {
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DiagnosticSeverity { Err, Warn }
 
+impl DiagnosticView {
+    pub fn severity(&self) -> DiagnosticSeverity { return self.severity; }
+    pub fn title(&self) -> &'static str { return self.title; }
+    pub fn elements(&self) -> &[DiagnosticViewElement] { return self.elements.as_slice(); }
+
+    /// Renders the diagnostic as text, quoting the source lines of the indicated tokens.
+    /// `source_map` must hold the source units which the diagnostic refers to.
+    ///
+    /// ```txt
+    /// error: Missing token
+    ///  --> main.cyan:1:11
+    ///   | proc main(: int {}
+    ///   |           ^
+    ///   = expected identifier
+    /// ```
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let severity = match self.severity {
+            DiagnosticSeverity::Err => "error",
+            DiagnosticSeverity::Warn => "warning",
+        };
+        let mut out = format!("{}: {}\n", severity, self.title);
+        for element in self.elements() {
+            match element {
+                DiagnosticViewElement::SourceQuote(quote) => {
+                    for key in quote.indicated_toks() {
+                        let location = source_map.locate(quote.source_unit, *key);
+                        let unit = source_map.get(quote.source_unit);
+                        let line_text = unit.line_text(location.line - 1);
+                        out.push_str(&format!(" --> {}\n", location));
+                        out.push_str(&format!("  | {}\n", String::from_utf8_lossy(line_text)));
+                        out.push_str(&format!("  | {}^\n", " ".repeat(location.column - 1)));
+                    }
+                },
+                DiagnosticViewElement::StaticMessage(message) =>
+                    out.push_str(&format!("  = {}\n", message)),
+                DiagnosticViewElement::ExpectedToks(expected) =>
+                    out.push_str(&format!("  = expected {}\n", describe_kinds(expected))),
+                DiagnosticViewElement::ArgCount { expected, found } =>
+                    out.push_str(&format!("  = expected {} type argument(s), found {}\n",
+                        expected, found)),
+            }
+        }
+        return out;
+    }
+}
+
+impl SourceQuote {
+    pub fn source_unit(&self) -> SourceUnitId { return self.source_unit; }
+    pub fn indicated_toks(&self) -> &[tokbuf::Key] { return self.indicated_toks.as_slice(); }
+}
+
 // -- AnyDiagnostic ------------------------------------------------------------------------------
 
 pub enum AnyDiagnostic {
-    MissingTok(MissingTok)
+    MissingTok(MissingTok),
+    WrongTypeArgCount(WrongTypeArgCount),
+    UnclosedDelimiter(UnclosedDelimiter),
+    NestingTooDeep(NestingTooDeep),
+    UnresolvedImport(UnresolvedImport),
+    UnsupportedItem(UnsupportedItem)
 }
 
 impl AnyDiagnostic {
+    pub fn source_unit(&self) -> SourceUnitId {
+        return match self {
+            AnyDiagnostic::MissingTok(diag) => diag.source_unit,
+            AnyDiagnostic::WrongTypeArgCount(diag) => diag.source_unit,
+            AnyDiagnostic::UnclosedDelimiter(diag) => diag.source_unit,
+            AnyDiagnostic::NestingTooDeep(diag) => diag.source_unit,
+            AnyDiagnostic::UnresolvedImport(diag) => diag.source_unit,
+            AnyDiagnostic::UnsupportedItem(diag) => diag.source_unit,
+        };
+    }
+
+    /// Returns the key of the token which the diagnostic is about. This is the opening delimiter
+    /// of an unclosed group, and otherwise the token at which the diagnostic was reported.
+    pub fn subject(&self) -> tokbuf::Key {
+        return match self {
+            AnyDiagnostic::MissingTok(diag) => diag.at,
+            AnyDiagnostic::WrongTypeArgCount(diag) => diag.at,
+            AnyDiagnostic::UnclosedDelimiter(diag) => diag.open,
+            AnyDiagnostic::NestingTooDeep(diag) => diag.at,
+            AnyDiagnostic::UnresolvedImport(diag) => diag.at,
+            AnyDiagnostic::UnsupportedItem(diag) => diag.at,
+        };
+    }
+
+    /// Moves the keys of the diagnostic to those of the same tokens after `edit`, see
+    /// [`TokBufEdit::rebase`]. Returns `None` if the diagnostic refers to a replaced token.
+    ///
+    /// [`TokBufEdit::rebase`]: tokbuf::TokBufEdit::rebase
+    pub fn rebase(mut self, edit: &tokbuf::TokBufEdit) -> Option<AnyDiagnostic> {
+        match &mut self {
+            AnyDiagnostic::MissingTok(diag) => diag.at = edit.rebase(diag.at)?,
+            AnyDiagnostic::WrongTypeArgCount(diag) => diag.at = edit.rebase(diag.at)?,
+            AnyDiagnostic::UnclosedDelimiter(diag) => {
+                diag.open = edit.rebase(diag.open)?;
+                diag.at = edit.rebase(diag.at)?;
+            },
+            AnyDiagnostic::NestingTooDeep(diag) => diag.at = edit.rebase(diag.at)?,
+            AnyDiagnostic::UnresolvedImport(diag) => diag.at = edit.rebase(diag.at)?,
+            AnyDiagnostic::UnsupportedItem(diag) => diag.at = edit.rebase(diag.at)?,
+        }
+        return Some(self);
+    }
+
     pub fn view(&self) -> DiagnosticView {
         match self {
             AnyDiagnostic::MissingTok(diag) => diag.view(),
+            AnyDiagnostic::WrongTypeArgCount(diag) => diag.view(),
+            AnyDiagnostic::UnclosedDelimiter(diag) => diag.view(),
+            AnyDiagnostic::NestingTooDeep(diag) => diag.view(),
+            AnyDiagnostic::UnresolvedImport(diag) => diag.view(),
+            AnyDiagnostic::UnsupportedItem(diag) => diag.view(),
         }
     }
 }
@@ -48,7 +159,10 @@ impl AnyDiagnostic {
 pub struct MissingTok {
     source_unit: SourceUnitId,
 
-    // TODO: `expected_tok: ?,`
+    /// The members of the token class the parser expected, see [`TokClass::MEMBERS`].
+    ///
+    /// [`TokClass::MEMBERS`]: crate::tok::class::TokClass::MEMBERS
+    expected: &'static [TokKind],
 
     // The key of the next token in the source buffer.
     // The parser expected `expected_tok` to be at this key but it was not there.
@@ -65,15 +179,214 @@ impl Diagnostic for MissingTok {
                 DiagnosticViewElement::SourceQuote(SourceQuote { 
                     source_unit: self.source_unit, 
                     indicated_toks: InlineVec::from_array([self.at]),
-                })
+                }),
+                DiagnosticViewElement::ExpectedToks(self.expected)
             ]),
         }
     }
 }
 
 impl MissingTok {
-    pub fn new(source_unit: SourceUnitId, tok: tokbuf::Key) -> Self {
-        return Self { source_unit, at: tok };
+    pub fn new(source_unit: SourceUnitId, tok: tokbuf::Key, expected: &'static [TokKind]) -> Self {
+        return Self { source_unit, expected, at: tok };
+    }
+
+    pub fn source_unit(&self) -> SourceUnitId { return self.source_unit; }
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+    pub fn expected(&self) -> &'static [TokKind] { return self.expected; }
+}
+
+
+// -- WrongTypeArgCount --------------------------------------------------------------------------
+
+pub struct WrongTypeArgCount {
+    source_unit: SourceUnitId,
+
+    /// The key of the identifier of the named type whose arguments are wrong in number.
+    at: tokbuf::Key,
+
+    expected: u32,
+    found: u32
+}
+
+impl Diagnostic for WrongTypeArgCount {
+    fn view(&self) -> DiagnosticView {
+        DiagnosticView {
+            severity: DiagnosticSeverity::Err,
+            title: "Wrong number of type arguments",
+            elements: InlineVec::from_array([
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.at]),
+                }),
+                DiagnosticViewElement::ArgCount { expected: self.expected, found: self.found }
+            ]),
+        }
+    }
+}
+
+impl WrongTypeArgCount {
+    pub fn new(source_unit: SourceUnitId, at: tokbuf::Key, expected: u32, found: u32) -> Self {
+        return Self { source_unit, at, expected, found };
+    }
+
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+    pub fn expected(&self) -> u32 { return self.expected; }
+    pub fn found(&self) -> u32 { return self.found; }
+}
+
+// -- UnclosedDelimiter --------------------------------------------------------------------------
+
+pub struct UnclosedDelimiter {
+    source_unit: SourceUnitId,
+
+    /// The key of the opening delimiter which is never closed.
+    open: tokbuf::Key,
+    delimiter: StaticTok,
+
+    /// The key of the token at which the group was expected to be closed. This is either a
+    /// closing delimiter of an enclosing group, or the end of the buffer.
+    at: tokbuf::Key
+}
+
+impl Diagnostic for UnclosedDelimiter {
+    fn view(&self) -> DiagnosticView {
+        let title = match self.delimiter {
+            StaticTok::OpenParen => "Unclosed `(`",
+            StaticTok::OpenSquare => "Unclosed `[`",
+            _ => "Unclosed `{`",
+        };
+        DiagnosticView {
+            severity: DiagnosticSeverity::Err,
+            title,
+            elements: InlineVec::from_array([
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.open]),
+                }),
+                DiagnosticViewElement::StaticMessage("opened here, but not closed before"),
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.at]),
+                }),
+            ]),
+        }
+    }
+}
+
+impl UnclosedDelimiter {
+    /// Panics if `delimiter` is not `(`, `[`, or `{`.
+    pub fn new(source_unit: SourceUnitId, open: tokbuf::Key, delimiter: StaticTok,
+        at: tokbuf::Key) -> Self
+    {
+        assert!(matches!(delimiter,
+            StaticTok::OpenParen | StaticTok::OpenSquare | StaticTok::OpenCurly));
+        return Self { source_unit, open, delimiter, at };
     }
+
+    pub fn open(&self) -> tokbuf::Key { return self.open; }
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+}
+
+// -- NestingTooDeep -----------------------------------------------------------------------------
+
+pub struct NestingTooDeep {
+    source_unit: SourceUnitId,
+
+    /// The key of the token which begins the first production nested too deeply.
+    at: tokbuf::Key,
+    max_depth: u32
+}
+
+impl Diagnostic for NestingTooDeep {
+    fn view(&self) -> DiagnosticView {
+        DiagnosticView {
+            severity: DiagnosticSeverity::Err,
+            title: "Nesting too deep",
+            elements: InlineVec::from_array([
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.at]),
+                }),
+                DiagnosticViewElement::StaticMessage(
+                    "types and expressions are nested more deeply than the parser allows"),
+            ]),
+        }
+    }
+}
+
+impl NestingTooDeep {
+    pub fn new(source_unit: SourceUnitId, at: tokbuf::Key, max_depth: u32) -> Self {
+        return Self { source_unit, at, max_depth };
+    }
+
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+    pub fn max_depth(&self) -> u32 { return self.max_depth; }
+}
+
+// -- UnresolvedImport ---------------------------------------------------------------------------
+
+pub struct UnresolvedImport {
+    source_unit: SourceUnitId,
+
+    /// The key of the path of the import.
+    at: tokbuf::Key
+}
+
+impl Diagnostic for UnresolvedImport {
+    fn view(&self) -> DiagnosticView {
+        DiagnosticView {
+            severity: DiagnosticSeverity::Err,
+            title: "Unresolved import",
+            elements: InlineVec::from_array([
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.at]),
+                }),
+                DiagnosticViewElement::StaticMessage("the imported source file could not be read"),
+            ]),
+        }
+    }
+}
+
+impl UnresolvedImport {
+    pub fn new(source_unit: SourceUnitId, at: tokbuf::Key) -> Self {
+        return Self { source_unit, at };
+    }
+
+    pub fn source_unit(&self) -> SourceUnitId { return self.source_unit; }
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+}
+
+// -- UnsupportedItem ----------------------------------------------------------------------------
+
+pub struct UnsupportedItem {
+    source_unit: SourceUnitId,
+
+    /// The key of the declarator which begins the item.
+    at: tokbuf::Key
 }
 
+impl Diagnostic for UnsupportedItem {
+    fn view(&self) -> DiagnosticView {
+        DiagnosticView {
+            severity: DiagnosticSeverity::Err,
+            title: "Unsupported item",
+            elements: InlineVec::from_array([
+                DiagnosticViewElement::SourceQuote(SourceQuote {
+                    source_unit: self.source_unit,
+                    indicated_toks: InlineVec::from_array([self.at]),
+                }),
+                DiagnosticViewElement::StaticMessage("items of this kind are not supported yet"),
+            ]),
+        }
+    }
+}
+
+impl UnsupportedItem {
+    pub fn new(source_unit: SourceUnitId, at: tokbuf::Key) -> Self {
+        return Self { source_unit, at };
+    }
+
+    pub fn at(&self) -> tokbuf::Key { return self.at; }
+}
@@ -0,0 +1,197 @@
+//! Loading of a project: the source unit at a root path, and every source unit which it imports,
+//! directly or transitively.
+//!
+//! The path of an import is relative to the directory of the importing unit. Paths are
+//! normalized, see [`fs::normalize`], so that a unit imported along different paths is loaded
+//! once. An import which cannot be read is reported as an [`UnresolvedImport`].
+//!
+//! [`UnresolvedImport`]: crate::diagnostic::UnresolvedImport
+//!
+//! Independent units can also be lexed and parsed concurrently, see [`parse_all`].
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use crate::diagnostic::{self, AnyDiagnostic};
+use crate::fs::{self, FileSystem};
+use crate::parse::ast::AnyTopLevelItem;
+use crate::source_unit::{SourceMap, SourceUnit, SourceUnitId};
+use crate::tok::tokbuf::Key;
+
+pub struct Project {
+    pub root: SourceUnitId,
+
+    /// The units imported by each unit of the project, in the order of the imports. An import
+    /// which could not be resolved is omitted.
+    imports: HashMap<SourceUnitId, Vec<SourceUnitId>>
+}
+
+impl Project {
+    /// Returns the units of the project, ordered by id.
+    pub fn units(&self) -> impl Iterator<Item = SourceUnitId> + '_ {
+        let mut units: Vec<SourceUnitId> = self.imports.keys().copied().collect();
+        units.sort_unstable();
+        return units.into_iter();
+    }
+
+    /// Panics if `unit` is not part of the project.
+    pub fn imports_of(&self, unit: SourceUnitId) -> &[SourceUnitId] {
+        return &self.imports[&unit];
+    }
+}
+
+/// Loads the unit at `root`, and every unit it imports, from `fs` into `source_map`. Fails only
+/// if the root itself cannot be read.
+pub fn load_project(fs: &dyn FileSystem, root: &Path, source_map: &mut SourceMap,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> io::Result<Project>
+{
+    let root = source_map.load(fs, &fs::normalize(root), diagnostics)?;
+    let mut imports: HashMap<SourceUnitId, Vec<SourceUnitId>> = HashMap::new();
+    let mut pending: VecDeque<SourceUnitId> = VecDeque::from([root]);
+    while let Some(unit) = pending.pop_front() {
+        if imports.contains_key(&unit) { continue; }
+        let mut imported: Vec<SourceUnitId> = Vec::new();
+        for (path, at) in import_paths(source_map, unit) {
+            match source_map.load(fs, &path, diagnostics) {
+                Ok(import) => {
+                    imported.push(import);
+                    pending.push_back(import);
+                },
+                Err(_) => {
+                    let diagnostic = diagnostic::UnresolvedImport::new(unit, at);
+                    diagnostics.push(AnyDiagnostic::UnresolvedImport(diagnostic));
+                }
+            }
+        }
+        imports.insert(unit, imported);
+    }
+    return Ok(Project { root, imports });
+}
+
+/// Lexes and parses each of `sources`, the path and text of a unit, on up to `threads` threads,
+/// and adds the units to `source_map`. Returns the id of each unit, in the order of `sources`.
+///
+/// Each thread takes the next unit not yet taken whenever it finishes one, so that a few large
+/// units do not hold up the rest. The ids of the units, and the order of the diagnostics appended
+/// to `diagnostics`, are the same as if the units were added one after another with
+/// [`SourceMap::add`], regardless of the number of threads.
+pub fn parse_all(sources: Vec<(PathBuf, Vec<u8>)>, threads: NonZeroUsize,
+    source_map: &mut SourceMap, diagnostics: &mut Vec<AnyDiagnostic>) -> Vec<SourceUnitId>
+{
+    let first_id = source_map.next_id();
+    let str_interner = source_map.str_interner();
+    let thread_count = usize::min(threads.get(), sources.len());
+    let queue = Mutex::new(sources.into_iter().enumerate());
+    let mut parsed: Vec<(usize, SourceUnit, Vec<AnyDiagnostic>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..thread_count).map(|_| scope.spawn(|| {
+            let mut parsed: Vec<(usize, SourceUnit, Vec<AnyDiagnostic>)> = Vec::new();
+            loop {
+                // The queue is unlocked at the end of this statement, before the unit is parsed.
+                let Some((idx, (path, text))) = queue.lock().unwrap().next() else { break; };
+                let id = first_id + SourceUnitId::try_from(idx).unwrap();
+                let mut unit_diagnostics: Vec<AnyDiagnostic> = Vec::new();
+                let unit = SourceUnit::new(id, path, text, str_interner, &mut unit_diagnostics);
+                parsed.push((idx, unit, unit_diagnostics));
+            }
+            return parsed;
+        })).collect();
+        return workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect();
+    });
+    parsed.sort_unstable_by_key(|(idx, _, _)| *idx);
+    return parsed.into_iter().map(|(_, unit, unit_diagnostics)| {
+        diagnostics.extend(unit_diagnostics);
+        source_map.insert(unit)
+    }).collect();
+}
+
+/// Returns the normalized path of every import of `unit`, along with the key of its path literal.
+fn import_paths(source_map: &SourceMap, unit: SourceUnitId) -> Vec<(PathBuf, Key)> {
+    let source_unit = source_map.get(unit);
+    let dir = source_unit.path().parent().unwrap_or(Path::new(""));
+    let ast = source_unit.ast();
+    return ast.root.items(ast).filter_map(|item| match item {
+        AnyTopLevelItem::Import(import) => Some(import),
+        _ => None
+    }).map(|import| {
+        let literal = import.path.view(source_unit.tokbuf());
+        let path = String::from_utf8_lossy(literal.content()).into_owned();
+        (fs::normalize(&dir.join(path)), import.path.key())
+    }).collect();
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_driver {
+    use std::num::NonZeroUsize;
+    use std::path::{Path, PathBuf};
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::fs::MemoryFileSystem;
+    use crate::source_unit::SourceMap;
+    use crate::util::str_interner::StrInterner;
+    use super::{load_project, parse_all};
+
+    #[test]
+    fn test_load_project() {
+        let mut fs = MemoryFileSystem::new();
+        fs.insert("app/main.cyan", "import \"shapes/point.cyan\";\nimport \"util.cyan\";\n");
+        fs.insert("app/shapes/point.cyan", "import \"../util.cyan\";\nstruct Point {}\n");
+        fs.insert("app/util.cyan", "import \"missing.cyan\";\nstruct Util {}\n");
+
+        let str_interner = StrInterner::default();
+        let mut source_map = SourceMap::new(&str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let project = load_project(&fs, Path::new("./app/main.cyan"), &mut source_map,
+            &mut diagnostics).unwrap();
+
+        // `util.cyan` is imported along two paths, but loaded once.
+        let paths: Vec<&Path> = project.units().map(|unit| source_map.get(unit).path()).collect();
+        assert_eq!(paths, [Path::new("app/main.cyan"), Path::new("app/shapes/point.cyan"),
+            Path::new("app/util.cyan")]);
+        assert_eq!(project.imports_of(project.root), [1, 2]);
+        assert_eq!(project.imports_of(1), [2]);
+        assert_eq!(project.imports_of(2), []);
+
+        let [AnyDiagnostic::UnresolvedImport(unresolved)] = diagnostics.as_slice() else {
+            panic!();
+        };
+        let location = source_map.locate(unresolved.source_unit(), unresolved.at());
+        assert_eq!(location.to_string(), "app/util.cyan:1:8");
+
+        let error = load_project(&fs, Path::new("app/lib.cyan"), &mut source_map,
+            &mut diagnostics);
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let sources: Vec<(PathBuf, Vec<u8>)> = (0..16).map(|i| {
+            let text = format!("struct S{i} {{}}\nproc f{i}(: int): int {{}}\n");
+            (PathBuf::from(format!("{i}.cyan")), text.into_bytes())
+        }).collect();
+        let str_interner = StrInterner::default();
+        let mut sequential = SourceMap::new(&str_interner);
+        let mut expected: Vec<AnyDiagnostic> = Vec::new();
+        for (path, text) in sources.clone() { sequential.add(path, text, &mut expected); }
+        let expected: Vec<String> = expected.iter()
+            .map(|diagnostic| diagnostic.view().render(&sequential))
+            .collect();
+        assert_eq!(expected.len(), 16);
+
+        for threads in [1, 3, 16, 64] {
+            let mut source_map = SourceMap::new(&str_interner);
+            let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+            let ids = parse_all(sources.clone(), NonZeroUsize::new(threads).unwrap(),
+                &mut source_map, &mut diagnostics);
+            assert_eq!(ids, (0..16).collect::<Vec<_>>());
+            assert_eq!(source_map.find(Path::new("7.cyan")), Some(7));
+            let rendered: Vec<String> = diagnostics.iter()
+                .map(|diagnostic| diagnostic.view().render(&source_map))
+                .collect();
+            assert_eq!(rendered, expected);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! The compiler's access to source files.
+//!
+//! All file access goes through a [`FileSystem`], so that a whole project can be compiled from
+//! the real disk or from an in-memory map of paths to text, as in tests.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub trait FileSystem {
+    /// Returns the contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+/// Reads files from the disk of the host.
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> { return std::fs::read(path); }
+}
+
+/// Reads files from a map of paths to text. Paths are normalized with [`normalize`] both when
+/// inserted and when read.
+#[derive(Default)]
+pub struct MemoryFileSystem {
+    files: HashMap<PathBuf, Vec<u8>>
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self { return Self::default(); }
+
+    /// Adds the file at `path`, or replaces its text if it already exists.
+    pub fn insert(&mut self, path: impl AsRef<Path>, text: impl Into<Vec<u8>>) {
+        self.files.insert(normalize(path.as_ref()), text.into());
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        return self.files.get(&normalize(path)).cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()));
+    }
+}
+
+/// Removes the `.` components of `path`, and the `..` components which follow a normal
+/// component along with that component, without consulting any file system. Paths which name
+/// the same file this way are equal once normalized.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => { normal.pop(); },
+                Some(Component::RootDir) => {},
+                _ => normal.push(".."),
+            },
+            component => normal.push(component),
+        }
+    }
+    return normal;
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_fs {
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use super::{normalize, FileSystem, MemoryFileSystem};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("a/./b/../c.cyan")), PathBuf::from("a/c.cyan"));
+        assert_eq!(normalize(Path::new("../a/../../b")), PathBuf::from("../../b"));
+        assert_eq!(normalize(Path::new("/../a")), PathBuf::from("/a"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut fs = MemoryFileSystem::new();
+        fs.insert("src/main.cyan", "proc main(): int {}");
+        assert_eq!(fs.read(Path::new("./src/lib/../main.cyan")).unwrap(), b"proc main(): int {}");
+        let error = fs.read(Path::new("src/lib.cyan")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
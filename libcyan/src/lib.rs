@@ -2,6 +2,12 @@
 
 pub mod source_unit;
 pub mod diagnostic;
+pub mod driver;
+pub mod fs;
 pub mod parse;
+pub mod query;
+pub mod sema;
 pub mod tok;
 mod util;
+
+pub use util::str_interner::StrInterner;
@@ -1,143 +1,491 @@
-///! This module defines the AST for the Cyan language.
-///!
-///! When implementing a new AST `Node`, the following must be true...
-///! - The type **must** have `align_of` `AST_ALIGN`!
-///! - The type **must** be included in `MAX_NODE_SIZE` in `calc_ast_size_upperbound`!
+//! This module defines the AST for the Cyan language.
+//!
+//! Nodes live in a [`BumpAllocator`] owned by the [`Ast`] and refer to one another through
+//! [`AstRef`]s. Node types may have any size and alignment but **must not** need to be dropped.
+//!
+//! The AST of a source unit with syntax errors is as complete as the parser could make it. A
+//! closing delimiter or terminating `;` which is `None` was missing from the source text, and
+//! an [`ErrorNode`] stands in for an expression, type, or statement which could not be parsed.
 
 use crate::tok;
-use crate::tok::class::{delims, BinaryOperator, Ident, Literal, TokRef};
-use crate::util::bump_allocator::{self, BumpAllocator, LLNode};
-use crate::util::misc::max_of_usizes;
+use crate::tok::class::{delims, AnyTok, BinaryOperator, Ident, Literal, StringLiteral, TokRef};
+use crate::tok::tokbuf::Key;
+use crate::util::bump_allocator::{self, BumpAllocator, LLIter, LLNode};
+use crate::util::side_table::{DenseKey, SideTable};
 
 // -- Support ------------------------------------------------------------------------------------
 
-pub const AST_ALIGN: usize = 4;
-
 pub type AstRef<T> = bump_allocator::Handle<T>;
 
-/// Calculates an upperbound for the size in bytes of the AST given the size of the token buffer.
+/// Identifies a node within its [`Ast`]. The parser numbers nodes densely, beginning at zero,
+/// in the order in which they begin in the source text. The only exception is an infix expression,
+/// which is numbered after its left operand.
 ///
-/// This function is used to allocate the memory for the AST all at once, immediately prior
-/// to syntactic analysis and AST construction. 
-pub fn calc_ast_size_upperbound(tok_count: usize) -> usize {
-    const MAX_NODE_SIZE: usize = max_of_usizes([
-        size_of::<ExprNode>(),
-        size_of::<ParameterNode>(),
-        size_of::<StatementNode>(),
-        size_of::<TopLevelItemNode>(),
-        size_of::<TypeArgumentNode>()
-    ]);
-    return tok_count * MAX_NODE_SIZE;
+/// Later passes annotate nodes by storing their results in a [`SideTable`] keyed by `NodeId`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub(crate) u32);
+
+impl DenseKey for NodeId {
+    fn index(self) -> usize { return usize::try_from(self.0).unwrap(); }
+    fn from_index(index: usize) -> Self { return NodeId(u32::try_from(index).unwrap()); }
 }
 
-pub struct Ast { pub mem: BumpAllocator<AST_ALIGN>, pub root: Root }
+pub struct Ast {
+    pub(crate) mem: BumpAllocator,
+    pub root: Root,
+
+    /// The number of `NodeId`s assigned by the parser. Every node's id is less than this.
+    pub(crate) node_count: u32,
+
+    /// The number of `NodeId`s assigned by the last parse from scratch, see [`reparse`].
+    ///
+    /// [`reparse`]: crate::parse::incremental::reparse
+    pub(crate) parsed_node_count: u32,
+
+    /// The keys of the declarators of the top-level items which begin within a group of paired
+    /// delimiters, in order. Reparsing neither restarts nor resumes at such an item.
+    pub(crate) enclosed_items: Vec<Key>
+}
+
+impl Ast {
+    /// Returns the node referred to by `node`.
+    ///
+    /// Panics if `node` refers to a node in a different [`Ast`].
+    pub fn get<T: 'static>(&self, node: AstRef<T>) -> &T { return self.mem.get(node); }
+
+    /// Returns the node referred to by `node`.
+    ///
+    /// Panics if `node` refers to a node in a different [`Ast`].
+    pub fn get_mut<T: 'static>(&mut self, node: AstRef<T>) -> &mut T {
+        return self.mem.get_mut(node);
+    }
+
+    /// Returns an iterator over the values of the list beginning at `first`.
+    pub fn iter<T: 'static>(&self, first: Option<AstRef<LLNode<T>>>) -> AstListIter<'_, T> {
+        return self.mem.iter_ll(first);
+    }
+
+    /// Returns the number of `NodeId`s assigned to nodes of this AST.
+    ///
+    /// Ids of nodes discarded during error recovery, or replaced by [`reparse`], are not reused,
+    /// so some ids below this number may not belong to any node.
+    ///
+    /// [`reparse`]: crate::parse::incremental::reparse
+    pub fn node_count(&self) -> usize { return usize::try_from(self.node_count).unwrap(); }
+
+    /// Returns an empty side table with room for every node of this AST.
+    pub fn side_table<V>(&self) -> SideTable<NodeId, V> {
+        return SideTable::with_capacity(self.node_count());
+    }
+}
+
+pub type AstListIter<'a, T> = LLIter<'a, T>;
 
 // -- Root --------------------------------------------------------------------------------------
 
+#[derive(Clone, Copy)]
 pub struct Root {
     pub ll_head: Option<AstRef<LLNode<AnyTopLevelItem>>>
 }
 
+impl Root {
+    pub fn items<'a>(&self, ast: &'a Ast) -> AstListIter<'a, AnyTopLevelItem> {
+        return ast.iter(self.ll_head);
+    }
+}
+
+#[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum AnyTopLevelItem {
     Proc(ProcDefinition),
-    LineComment(LineComment)
+    Struct(StructDefinition),
+    Import(ImportDeclaration)
+}
+
+impl AnyTopLevelItem {
+    pub fn id(&self) -> NodeId {
+        return match self {
+            AnyTopLevelItem::Proc(proc_def) => proc_def.id,
+            AnyTopLevelItem::Struct(struct_def) => struct_def.id,
+            AnyTopLevelItem::Import(import) => import.id,
+        };
+    }
+
+    /// Returns the key of the declarator with which the item begins.
+    pub fn declarator_key(&self) -> Key {
+        return match self {
+            AnyTopLevelItem::Proc(proc_def) => proc_def.proc_keyword.key(),
+            AnyTopLevelItem::Struct(struct_def) => struct_def.struct_keyword.key(),
+            AnyTopLevelItem::Import(import) => import.import_keyword.key(),
+        };
+    }
 }
 
 pub type TopLevelItemNode = LLNode<AnyTopLevelItem>;
 
+// -- Errors -------------------------------------------------------------------------------------
+
+/// A placeholder for an expression, type, or statement which could not be parsed. The parser
+/// reports a diagnostic wherever it places one.
+///
+/// The span of an error node runs from `at` through `last`, or covers `at` alone.
+#[derive(Clone, Copy)]
+pub struct ErrorNode {
+    pub id: NodeId,
+
+    /// The position at which the node begins. For a missing expression or type, this is where
+    /// the error was reported, which is the end of the buffer if no tokens remained.
+    pub at: TokRef<AnyTok>,
+
+    /// The last token which was consumed or skipped in place of the node, if any.
+    pub last: Option<TokRef<AnyTok>>
+}
+
 // -- Expressions --------------------------------------------------------------------------------
 
+#[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum ExprNode {
     Ident(IdentExpr),
     Infix(InfixExpr),
-    Literal(LiteralExpr)
+    Literal(LiteralExpr),
+    Error(ErrorNode)
 }
 
+impl ExprNode {
+    pub fn id(&self) -> NodeId {
+        return match self {
+            ExprNode::Ident(expr) => expr.id,
+            ExprNode::Infix(expr) => expr.id,
+            ExprNode::Literal(expr) => expr.id,
+            ExprNode::Error(expr) => expr.id,
+        };
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct IdentExpr {
-    ident: TokRef<Ident>
+    pub id: NodeId,
+    pub ident: TokRef<Ident>
 }
 
+#[derive(Clone, Copy)]
 pub struct InfixExpr {
-    left_operand: AstRef<ExprNode>,
-    operator: TokRef<BinaryOperator>,
-    right_operand: AstRef<ExprNode>
+    pub id: NodeId,
+    pub left_operand: AstRef<ExprNode>,
+    pub operator: TokRef<BinaryOperator>,
+    pub right_operand: AstRef<ExprNode>
 }
 
+#[derive(Clone, Copy)]
 pub struct LiteralExpr {
-    tok: TokRef<Literal>
+    pub id: NodeId,
+    pub tok: TokRef<Literal>
 }
 
 // -- Types -------------------------------------------------------------------------------------
 
+#[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum Type {
-    NamedType(NamedType)
+    NamedType(NamedType),
+    Reference(ReferenceType),
+    Array(ArrayType),
+    Slice(SliceType),
+    Tuple(TupleType),
+    Proc(ProcType),
+    Error(ErrorNode)
+}
+
+impl Type {
+    pub fn id(&self) -> NodeId {
+        return match self {
+            Type::NamedType(named_type) => named_type.id,
+            Type::Reference(reference) => reference.id,
+            Type::Array(array) => array.id,
+            Type::Slice(slice) => slice.id,
+            Type::Tuple(tuple) => tuple.id,
+            Type::Proc(proc_type) => proc_type.id,
+            Type::Error(error) => error.id,
+        };
+    }
 }
 
-pub struct NamedType { 
+#[derive(Clone, Copy)]
+pub struct NamedType {
+    pub id: NodeId,
     pub ident: TokRef<Ident>,
     pub arguments: Option<TypeArguments>
 }
 
+#[derive(Clone, Copy)]
 pub struct TypeArguments {
+    pub id: NodeId,
     pub open_angle: TokRef<delims::LessThan>,
     pub first: Option<AstRef<TypeArgumentNode>>,
-    pub close_angle: TokRef<delims::GreaterThan>,
+    pub close_angle: Option<TokRef<delims::GreaterThan>>,
+}
+
+impl TypeArguments {
+    pub fn iter<'a>(&self, ast: &'a Ast) -> AstListIter<'a, TypeArgument> {
+        return ast.iter(self.first);
+    }
 }
 
-pub struct TypeArgument { pub ty: Type, pub comma: Option<TokRef<delims::Comma>> }
+#[derive(Clone, Copy)]
+pub struct TypeArgument {
+    pub id: NodeId,
+    pub ty: Type,
+    pub comma: Option<TokRef<delims::Comma>>
+}
 
 pub type TypeArgumentNode = LLNode<TypeArgument>;
 
+/// `&T` or `&mut T`.
+#[derive(Clone, Copy)]
+pub struct ReferenceType {
+    pub id: NodeId,
+    pub ampersand: TokRef<delims::Ampersand>,
+    pub mut_keyword: Option<TokRef<delims::Mut>>,
+    pub referent: AstRef<Type>
+}
+
+/// `[T; N]`, an array of exactly `N` elements.
+#[derive(Clone, Copy)]
+pub struct ArrayType {
+    pub id: NodeId,
+    pub open_square: TokRef<delims::OpenSquare>,
+    pub element: AstRef<Type>,
+    pub semicolon: TokRef<delims::Semicolon>,
+    pub length: TokRef<tok::class::IntLiteral>,
+    pub close_square: Option<TokRef<delims::CloseSquare>>
+}
+
+/// `[T]`, a sequence of elements whose length is not part of the type.
+#[derive(Clone, Copy)]
+pub struct SliceType {
+    pub id: NodeId,
+    pub open_square: TokRef<delims::OpenSquare>,
+    pub element: AstRef<Type>,
+    pub close_square: Option<TokRef<delims::CloseSquare>>
+}
+
+/// `(A, B)`. Any parenthesized list of types is a tuple type, including `()` and `(A)`.
+///
+/// The elements are stored as [`TypeArgument`]s, which are types optionally followed by a comma.
+#[derive(Clone, Copy)]
+pub struct TupleType {
+    pub id: NodeId,
+    pub open_paren: TokRef<delims::OpenParen>,
+    pub first: Option<AstRef<TypeArgumentNode>>,
+    pub close_paren: Option<TokRef<delims::CloseParen>>
+}
+
+impl TupleType {
+    pub fn elements<'a>(&self, ast: &'a Ast) -> AstListIter<'a, TypeArgument> {
+        return ast.iter(self.first);
+    }
+}
+
+/// `proc(A, B): C`, the type of a procedure.
+///
+/// The parameter types are stored as [`TypeArgument`]s, as in [`TupleType`].
+#[derive(Clone, Copy)]
+pub struct ProcType {
+    pub id: NodeId,
+    pub proc_keyword: TokRef<delims::Proc>,
+    pub open_paren: TokRef<delims::OpenParen>,
+    pub first: Option<AstRef<TypeArgumentNode>>,
+    pub close_paren: Option<TokRef<delims::CloseParen>>,
+    pub return_type_separator: TokRef<delims::Colon>,
+    pub return_type: AstRef<Type>
+}
+
+impl ProcType {
+    pub fn parameters<'a>(&self, ast: &'a Ast) -> AstListIter<'a, TypeArgument> {
+        return ast.iter(self.first);
+    }
+}
+
+// -- Generics -----------------------------------------------------------------------------------
+
+/// The type parameters declared by a generic item, for instance `<T, U: Bound>`.
+#[derive(Clone, Copy)]
+pub struct GenericParameters {
+    pub id: NodeId,
+    pub open_angle: TokRef<delims::LessThan>,
+    pub first: Option<AstRef<GenericParameterNode>>,
+    pub close_angle: Option<TokRef<delims::GreaterThan>>,
+}
+
+impl GenericParameters {
+    pub fn iter<'a>(&self, ast: &'a Ast) -> AstListIter<'a, GenericParameter> {
+        return ast.iter(self.first);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct GenericParameter {
+    pub id: NodeId,
+    pub ident: TokRef<Ident>,
+    pub bound: Option<GenericBound>,
+    pub comma: Option<TokRef<delims::Comma>>
+}
+
+pub type GenericParameterNode = LLNode<GenericParameter>;
+
+/// Constrains the types which may be substituted for a generic parameter, `: Bound`.
+#[derive(Clone, Copy)]
+pub struct GenericBound {
+    pub id: NodeId,
+    pub colon: TokRef<delims::Colon>,
+    pub ty: Type
+}
+
 // -- Procedure Definition ----------------------------------------------------------------------
 
+#[derive(Clone, Copy)]
 pub struct ProcDefinition {
+    pub id: NodeId,
     pub proc_keyword: TokRef<delims::Proc>,
     pub ident: TokRef<Ident>,
+    pub generics: Option<GenericParameters>,
     pub parameters: Parameters,
-    pub return_type_separator: TokRef<delims::Colon>,
+    pub return_type_separator: Option<TokRef<delims::Colon>>,
     pub return_type: Type,
     pub body: ImperativeBlock
 }
 
-pub struct Parameters {    
+#[derive(Clone, Copy)]
+pub struct Parameters {
+    pub id: NodeId,
     pub open_paren: TokRef<delims::OpenParen>,
-    pub close_paren: TokRef<delims::CloseParen>,
+    pub close_paren: Option<TokRef<delims::CloseParen>>,
     pub first: Option<AstRef<ParameterNode>>
 }
 
+impl Parameters {
+    pub fn iter<'a>(&self, ast: &'a Ast) -> AstListIter<'a, Parameter> {
+        return ast.iter(self.first);
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Parameter {
+    pub id: NodeId,
     pub ident: TokRef<Ident>,
-    pub colon: TokRef<delims::Colon>,
+    pub colon: Option<TokRef<delims::Colon>>,
     pub ty: Type,
     pub comma: Option<TokRef<delims::Comma>>,    
 }
 
 pub type ParameterNode = LLNode<Parameter>;
 
+// -- Import Declaration -------------------------------------------------------------------------
+
+/// Brings the items of another source unit into scope. The path is relative to the directory of
+/// the importing source unit.
+#[derive(Clone, Copy)]
+pub struct ImportDeclaration {
+    pub id: NodeId,
+    pub import_keyword: TokRef<delims::Import>,
+    pub path: TokRef<StringLiteral>,
+    pub semicolon: Option<TokRef<delims::Semicolon>>
+}
+
+// -- Struct Definition --------------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+pub struct StructDefinition {
+    pub id: NodeId,
+    pub struct_keyword: TokRef<delims::Struct>,
+    pub ident: TokRef<Ident>,
+    pub generics: Option<GenericParameters>,
+    pub open_curly: Option<TokRef<delims::OpenCurly>>,
+    pub first: Option<AstRef<FieldNode>>,
+    pub close_curly: Option<TokRef<delims::CloseCurly>>
+}
+
+impl StructDefinition {
+    pub fn fields<'a>(&self, ast: &'a Ast) -> AstListIter<'a, Field> {
+        return ast.iter(self.first);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Field {
+    pub id: NodeId,
+    pub ident: TokRef<Ident>,
+    pub colon: Option<TokRef<delims::Colon>>,
+    pub ty: Type,
+    pub comma: Option<TokRef<delims::Comma>>
+}
+
+pub type FieldNode = LLNode<Field>;
+
 // -- Procedure Invocation -----------------------------------------------------------------------
 
 
 // -- Statements ---------------------------------------------------------------------------------
 
+/// A block missing both of its braces references no token if it has no statements either, and
+/// then has no [`Span`](crate::parse::span::Span) of its own.
+#[derive(Clone, Copy)]
 pub struct ImperativeBlock {
-    pub open_curly: TokRef<delims::OpenCurly>,
-    pub close_curly: TokRef<delims::CloseCurly>,
+    pub id: NodeId,
+    pub open_curly: Option<TokRef<delims::OpenCurly>>,
+    pub close_curly: Option<TokRef<delims::CloseCurly>>,
     pub first: Option<AstRef<StatementNode>>,
 }
 
+impl ImperativeBlock {
+    pub fn statements<'a>(&self, ast: &'a Ast) -> AstListIter<'a, AnyStatement> {
+        return ast.iter(self.first);
+    }
+}
+
+#[derive(Clone, Copy)]
 #[repr(u8)]
 pub enum AnyStatement {
-    LineComment
+    Let(LetStatement),
+    Expr(ExprStatement),
+    Error(ErrorNode)
+}
+
+impl AnyStatement {
+    pub fn id(&self) -> NodeId {
+        return match self {
+            AnyStatement::Let(statement) => statement.id,
+            AnyStatement::Expr(statement) => statement.id,
+            AnyStatement::Error(error) => error.id,
+        };
+    }
 }
 
 pub type StatementNode = LLNode<AnyStatement>;
 
-// -- Line Comment -------------------------------------------------------------------------------
+/// `let x: T = value;`, where the type annotation is optional.
+#[derive(Clone, Copy)]
+pub struct LetStatement {
+    pub id: NodeId,
+    pub let_keyword: TokRef<delims::Let>,
+    pub ident: TokRef<Ident>,
+    pub annotation: Option<TypeAnnotation>,
+    pub eq: TokRef<delims::Eq>,
+    pub value: ExprNode,
+    pub semicolon: Option<TokRef<delims::Semicolon>>
+}
 
-pub struct LineComment {
-    pub tok: TokRef<tok::class::LineComment>
+#[derive(Clone, Copy)]
+pub struct TypeAnnotation {
+    pub colon: TokRef<delims::Colon>,
+    pub ty: Type
 }
 
+/// An expression evaluated for its effects, `expr;`.
+#[derive(Clone, Copy)]
+pub struct ExprStatement {
+    pub id: NodeId,
+    pub expr: ExprNode,
+    pub semicolon: Option<TokRef<delims::Semicolon>>
+}
@@ -0,0 +1,839 @@
+//! A lossless concrete syntax tree (CST) for tooling which rewrites source text.
+//!
+//! Unlike the AST, the CST holds every token in the [`TokBuf`], including trivia and tokens
+//! skipped during error recovery. Each token is owned by exactly one node, so concatenating the
+//! source text of the tokens of the root reproduces the source text exactly.
+//!
+//! The tree has two layers in the style of Roslyn and rust-analyzer.
+//! - The *green* tree, [`GreenNode`], is immutable and knows only the kind and length of each
+//!   node. Green nodes may be shared between trees.
+//! - The *red* tree, [`SyntaxNode`], is a cursor over the green tree which is created on demand
+//!   and knows the absolute byte offset and parent of each node.
+//!
+//! The CST is built by [`parse_cst`] from the events the parser records while it runs, see
+//! [`Event`]. [`Cst::to_ast`] converts the CST into an [`Ast`].
+//!
+//! Tokens are referenced by their [`Key`], so a CST is only meaningful together with the
+//! [`TokBuf`] which it was built from.
+//!
+//! [`parse_cst`]: crate::parse::parse::parse_cst
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+use crate::parse::ast::{self, Ast, AstRef, NodeId};
+use crate::tok::class::{self, delims, AnyTok, TokClass, TokRef, Trivia};
+use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+use crate::tok::tree::TokTrees;
+use crate::util::bump_allocator::{BumpAllocator, LLBuilder};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    Root,
+    ProcDefinition,
+    StructDefinition,
+    ImportDeclaration,
+    Field,
+    GenericParameters,
+    GenericParameter,
+    GenericBound,
+    Parameters,
+    Parameter,
+    NamedType,
+    ReferenceType,
+    ArrayType,
+    SliceType,
+    TupleType,
+    ProcType,
+    TypeArguments,
+    TypeArgument,
+    ImperativeBlock,
+    LetStatement,
+    ExprStatement,
+    IdentExpr,
+    LiteralExpr,
+    InfixExpr,
+
+    /// Placeholders for a type, expression, or statement which could not be parsed. These may
+    /// contain tokens which the parser skipped.
+    ErrorType,
+    ErrorExpr,
+    ErrorStatement,
+
+    /// Tokens which the parser skipped while recovering from a syntax error.
+    Error
+}
+
+impl SyntaxKind {
+    /// Returns whether nodes of this kind are types.
+    pub fn is_type(self) -> bool {
+        return matches!(self, SyntaxKind::NamedType | SyntaxKind::ReferenceType
+            | SyntaxKind::ArrayType | SyntaxKind::SliceType | SyntaxKind::TupleType
+            | SyntaxKind::ProcType | SyntaxKind::ErrorType);
+    }
+
+    /// Returns whether nodes of this kind are expressions.
+    pub fn is_expr(self) -> bool {
+        return matches!(self, SyntaxKind::IdentExpr | SyntaxKind::LiteralExpr
+            | SyntaxKind::InfixExpr | SyntaxKind::ErrorExpr);
+    }
+}
+
+// -- Green Tree ---------------------------------------------------------------------------------
+
+pub struct GreenNode {
+    kind: SyntaxKind,
+
+    /// The length in bytes of the source text of this node.
+    text_len: u32,
+    children: Vec<GreenElement>
+}
+
+#[derive(Clone)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(GreenToken)
+}
+
+#[derive(Clone, Copy)]
+pub struct GreenToken {
+    key: Key,
+
+    /// The length in bytes of the source text of this token.
+    text_len: u32
+}
+
+impl GreenNode {
+    pub fn kind(&self) -> SyntaxKind { return self.kind; }
+    pub fn text_len(&self) -> usize { return usize::try_from(self.text_len).unwrap(); }
+    pub fn children(&self) -> &[GreenElement] { return &self.children; }
+}
+
+impl GreenElement {
+    pub fn text_len(&self) -> usize {
+        return match self {
+            GreenElement::Node(node) => node.text_len(),
+            GreenElement::Token(tok) => tok.text_len(),
+        };
+    }
+}
+
+impl GreenToken {
+    pub fn key(&self) -> Key { return self.key; }
+    pub fn text_len(&self) -> usize { return usize::try_from(self.text_len).unwrap(); }
+}
+
+// -- Red Tree -----------------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<SyntaxNodeData>);
+
+struct SyntaxNodeData {
+    green: Arc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken)
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: GreenToken,
+    offset: usize,
+    parent: SyntaxNode
+}
+
+impl SyntaxNode {
+    fn new_root(green: Arc<GreenNode>) -> Self {
+        return SyntaxNode(Rc::new(SyntaxNodeData { green, offset: 0, parent: None }));
+    }
+
+    pub fn kind(&self) -> SyntaxKind { return self.0.green.kind; }
+    pub fn green(&self) -> &Arc<GreenNode> { return &self.0.green; }
+    pub fn parent(&self) -> Option<&SyntaxNode> { return self.0.parent.as_ref(); }
+
+    /// Returns the range of bytes in the source text covered by this node, including trivia.
+    pub fn text_range(&self) -> Range<usize> {
+        return self.0.offset..(self.0.offset + self.0.green.text_len());
+    }
+
+    /// Returns the children of this node in source order.
+    pub fn children(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        return self.0.green.children.iter().map(move |child| {
+            let child_offset = offset;
+            offset += child.text_len();
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(
+                    SyntaxNodeData { green: green.clone(), offset: child_offset,
+                        parent: Some(self.clone()) }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: *green, offset: child_offset, parent: self.clone() }),
+            }
+        });
+    }
+
+    /// Returns the child nodes of this node in source order.
+    pub fn child_nodes(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        return self.children().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        });
+    }
+
+    /// Appends the source text of this node, including trivia, to `out`.
+    pub fn write_text(&self, tokbuf: &TokBuf, out: &mut Vec<u8>) {
+        write_green_text(&self.0.green, tokbuf, out);
+    }
+}
+
+impl SyntaxToken {
+    pub fn key(&self) -> Key { return self.green.key; }
+    pub fn parent(&self) -> &SyntaxNode { return &self.parent; }
+
+    pub fn text_range(&self) -> Range<usize> {
+        return self.offset..(self.offset + self.green.text_len());
+    }
+}
+
+fn write_green_text(green: &GreenNode, tokbuf: &TokBuf, out: &mut Vec<u8>) {
+    for child in &green.children {
+        match child {
+            GreenElement::Node(node) => write_green_text(node, tokbuf, out),
+            GreenElement::Token(tok) => tokbuf.get(tok.key)
+                .expect("CST does not point into this TokBuf")
+                .write_source_text(out),
+        }
+    }
+}
+
+// -- Cst ----------------------------------------------------------------------------------------
+
+pub struct Cst { root: Arc<GreenNode> }
+
+impl Cst {
+    pub fn root(&self) -> SyntaxNode { return SyntaxNode::new_root(self.root.clone()); }
+
+    /// Converts this CST into an AST. `tokbuf` must be the buffer which this CST was built from.
+    ///
+    /// Nodes which are incomplete because of a syntax error are omitted, just as the parser
+    /// omits them from the AST it produces.
+    pub fn to_ast(&self, tokbuf: &TokBuf) -> Ast {
+        let mut error_positions: HashMap<*const GreenNode, Key> = HashMap::new();
+        let mut pending = Vec::new();
+        locate_errors(&self.root, tokbuf, &mut pending, &mut error_positions);
+        for error in pending { error_positions.insert(error, tokbuf.key_range().end); }
+        let mut lower = Lower { tokbuf, mem: BumpAllocator::new(), next_node_id: 0,
+            error_positions };
+        let mut items: LLBuilder<ast::AnyTopLevelItem> = LLBuilder::new();
+        for child in &self.root.children {
+            let GreenElement::Node(node) = child else { continue; };
+            let item = match node.kind {
+                SyntaxKind::ProcDefinition => lower.proc_def(node).map(ast::AnyTopLevelItem::Proc),
+                SyntaxKind::StructDefinition => {
+                    lower.struct_def(node).map(ast::AnyTopLevelItem::Struct)
+                },
+                SyntaxKind::ImportDeclaration => {
+                    lower.import(node).map(ast::AnyTopLevelItem::Import)
+                },
+                _ => None
+            };
+            let Some(item) = item else { continue; };
+            items.push(&mut lower.mem, item);
+        }
+        lower.mem.shrink_to_fit();
+        let root = ast::Root { ll_head: items.head() };
+        // The diagnostics of the delimiters were reported when this CST was parsed.
+        let trees = TokTrees::build(tokbuf, 0, &mut Vec::new());
+        let mut ast = Ast { mem: lower.mem, root, node_count: lower.next_node_id,
+            parsed_node_count: lower.next_node_id, enclosed_items: Vec::new() };
+        ast.enclosed_items = ast.root.items(&ast).map(|item| item.declarator_key())
+            .filter(|key| trees.is_enclosed(*key))
+            .collect();
+        return ast;
+    }
+}
+
+// -- Construction -------------------------------------------------------------------------------
+
+/// An event recorded by the parser. The position of each event is the key of the next token
+/// at the time the event was recorded.
+///
+/// Events are recorded in the order the parser encounters them and are balanced, that is, every
+/// `Start` is followed eventually by a matching `Finish`.
+pub(crate) enum Event {
+    /// Begins a node. A node which turns out to be the first child of a node begun after it
+    /// links to the `Start` of its parent, at the index `forward_parent`, rather than the parent
+    /// being inserted before it. The parent's `Start` may link to a parent of its own.
+    Start { kind: SyntaxKind, at: Key, forward_parent: Option<usize> },
+    Finish(Key),
+
+    /// A `Start` which was already processed through a forward parent link while building.
+    Tombstone
+}
+
+/// Builds a CST containing every token in `tokbuf` from the `events` recorded while parsing it.
+///
+/// Trivia between two nodes is owned by their parent. That is, trivia preceding the first token
+/// of a node or following its last token is placed outside of the node.
+pub(crate) fn build(tokbuf: &TokBuf, mut events: Vec<Event>) -> Cst {
+    let mut toks: Vec<(Key, bool)> = Vec::with_capacity(tokbuf.len());
+    let mut cursor = TokCursor::new(tokbuf);
+    while let Some(tok) = cursor.read_tok() {
+        toks.push((cursor.at(), Trivia::r#match(&tok).is_some()));
+        cursor.advance();
+    }
+    let index_of = |key: Key| toks.partition_point(|(tok_key, _)| *tok_key < key);
+
+    let mut builder = Builder { tokbuf, pos: 0, stack: vec![(SyntaxKind::Root, Vec::new())] };
+    let mut starts: Vec<(SyntaxKind, Key)> = Vec::new();
+    for event_idx in 0..events.len() {
+        match std::mem::replace(&mut events[event_idx], Event::Tombstone) {
+            Event::Start { kind, at, mut forward_parent } => {
+                // The forward parents are begun first, outermost first.
+                starts.push((kind, at));
+                while let Some(parent_idx) = forward_parent {
+                    let Event::Start { kind, at, forward_parent: next } =
+                        std::mem::replace(&mut events[parent_idx], Event::Tombstone)
+                    else { unreachable!("forward parent is not a Start event"); };
+                    starts.push((kind, at));
+                    forward_parent = next;
+                }
+                for (kind, at) in starts.drain(..).rev() {
+                    builder.push_toks_until(index_of(at), &toks);
+                    while builder.pos < toks.len() && toks[builder.pos].1 {
+                        builder.push_tok(&toks);
+                    }
+                    builder.stack.push((kind, Vec::new()));
+                }
+            },
+            Event::Finish(key) => {
+                let mut end = index_of(key);
+                while end > builder.pos && toks[end - 1].1 { end -= 1; }
+                builder.push_toks_until(end, &toks);
+                let node = builder.finish_node();
+                builder.stack.last_mut().unwrap().1.push(GreenElement::Node(node));
+            },
+            Event::Tombstone => {},
+        }
+    }
+    builder.push_toks_until(toks.len(), &toks);
+    assert_eq!(builder.stack.len(), 1, "parser events are unbalanced");
+    return Cst { root: builder.finish_node() };
+}
+
+struct Builder<'a, 'b> {
+    tokbuf: &'a TokBuf<'b>,
+
+    /// The index of the next token to be placed in the tree.
+    pos: usize,
+
+    /// The kind and children of each node under construction, outermost first.
+    stack: Vec<(SyntaxKind, Vec<GreenElement>)>
+}
+
+impl Builder<'_, '_> {
+    fn push_tok(&mut self, toks: &[(Key, bool)]) {
+        let key = toks[self.pos].0;
+        let text_len = self.tokbuf.byte_range(key).unwrap().len();
+        let green = GreenToken { key, text_len: u32::try_from(text_len).unwrap() };
+        self.stack.last_mut().unwrap().1.push(GreenElement::Token(green));
+        self.pos += 1;
+    }
+
+    fn push_toks_until(&mut self, end: usize, toks: &[(Key, bool)]) {
+        while self.pos < end { self.push_tok(toks); }
+    }
+
+    fn finish_node(&mut self) -> Arc<GreenNode> {
+        let (kind, children) = self.stack.pop().unwrap();
+        let text_len = children.iter().map(GreenElement::text_len).sum::<usize>();
+        return Arc::new(GreenNode { kind, text_len: u32::try_from(text_len).unwrap(), children });
+    }
+}
+
+// -- Lowering -----------------------------------------------------------------------------------
+
+/// Converts green nodes into AST nodes. Each method returns `None` if the node is incomplete.
+struct Lower<'a, 'b> {
+    tokbuf: &'a TokBuf<'b>,
+    mem: BumpAllocator,
+
+    /// The `NodeId` which will be assigned to the next node, in the same order as the parser.
+    next_node_id: u32,
+
+    /// The first token which is not trivia at or after the beginning of each error node, see
+    /// [`locate_errors`].
+    error_positions: HashMap<*const GreenNode, Key>
+}
+
+/// Records the position of each error node under `node`, which is where the parser placed it:
+/// the first token which is not trivia at or after the beginning of the node. `pending` holds
+/// the error nodes whose position is not yet known.
+fn locate_errors(node: &GreenNode, tokbuf: &TokBuf, pending: &mut Vec<*const GreenNode>,
+    positions: &mut HashMap<*const GreenNode, Key>)
+{
+    for child in &node.children {
+        match child {
+            GreenElement::Node(child) => {
+                if matches!(child.kind, SyntaxKind::ErrorType | SyntaxKind::ErrorExpr
+                    | SyntaxKind::ErrorStatement)
+                {
+                    pending.push(Arc::as_ptr(child));
+                }
+                locate_errors(child, tokbuf, pending, positions);
+            },
+            GreenElement::Token(tok) => {
+                if TokRef::<Trivia>::new(tokbuf, tok.key).is_some() { continue; }
+                for error in pending.drain(..) { positions.insert(error, tok.key); }
+            },
+        }
+    }
+}
+
+/// Returns the last token under `node` which is not trivia, if any.
+fn last_tok(node: &GreenNode, tokbuf: &TokBuf) -> Option<Key> {
+    return node.children.iter().rev().find_map(|child| match child {
+        GreenElement::Node(child) => last_tok(child, tokbuf),
+        GreenElement::Token(tok) => {
+            TokRef::<Trivia>::new(tokbuf, tok.key).is_none().then_some(tok.key)
+        },
+    });
+}
+
+/// Iterates over the children of a green node which are not trivia.
+struct Children<'a, 'b> {
+    tokbuf: &'a TokBuf<'b>,
+    children: std::iter::Peekable<std::slice::Iter<'a, GreenElement>>
+}
+
+impl<'a, 'b> Children<'a, 'b> {
+    fn new(tokbuf: &'a TokBuf<'b>, node: &'a GreenNode) -> Self {
+        return Self { tokbuf, children: node.children.iter().peekable() };
+    }
+
+    /// Skips trivia, and the tokens which the parser skipped, neither of which are in the AST.
+    fn skip_trivia(&mut self) {
+        while let Some(child) = self.children.peek() {
+            let skip = match child {
+                GreenElement::Token(tok) => TokRef::<Trivia>::new(self.tokbuf, tok.key).is_some(),
+                GreenElement::Node(node) => node.kind == SyntaxKind::Error,
+            };
+            if !skip { return; }
+            self.children.next();
+        }
+    }
+
+    /// Consumes the next child if it is a token of class `C`.
+    fn tok<C: TokClass>(&mut self) -> Option<TokRef<C>> {
+        self.skip_trivia();
+        let Some(GreenElement::Token(tok)) = self.children.peek() else { return None; };
+        let tok_ref = TokRef::new(self.tokbuf, tok.key)?;
+        self.children.next();
+        return Some(tok_ref);
+    }
+
+    /// Consumes the next child if it is a node of the given kind.
+    fn node(&mut self, kind: SyntaxKind) -> Option<&'a GreenNode> {
+        self.skip_trivia();
+        let Some(GreenElement::Node(node)) = self.children.peek() else { return None; };
+        if node.kind != kind { return None; }
+        self.children.next();
+        return Some(node);
+    }
+
+    /// Consumes the next child if it is a type node of any kind.
+    fn type_node(&mut self) -> Option<&'a GreenNode> {
+        return self.node_where(SyntaxKind::is_type);
+    }
+
+    /// Consumes the next child if it is an expression node of any kind.
+    fn expr_node(&mut self) -> Option<&'a GreenNode> {
+        return self.node_where(SyntaxKind::is_expr);
+    }
+
+    /// Consumes the next child if it is a node whose kind satisfies `predicate`.
+    fn node_where(&mut self, predicate: impl FnOnce(SyntaxKind) -> bool)
+    -> Option<&'a GreenNode>
+    {
+        self.skip_trivia();
+        let Some(GreenElement::Node(node)) = self.children.peek() else { return None; };
+        if !predicate(node.kind) { return None; }
+        self.children.next();
+        return Some(node);
+    }
+}
+
+impl Lower<'_, '_> {
+    fn node_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_node_id);
+        self.next_node_id += 1;
+        return id;
+    }
+
+    fn error_node(&self, id: NodeId, node: &GreenNode) -> ast::ErrorNode {
+        let mut cursor = TokCursor::new(self.tokbuf);
+        cursor.seek(self.error_positions[&std::ptr::from_ref(node)]);
+        let at = cursor.position();
+        let last = last_tok(node, self.tokbuf).and_then(|key| TokRef::new(self.tokbuf, key));
+        return ast::ErrorNode { id, at, last };
+    }
+
+    fn proc_def(&mut self, node: &GreenNode) -> Option<ast::ProcDefinition> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let proc_keyword = children.tok::<delims::Proc>()?;
+        let ident = children.tok::<class::Ident>()?;
+        let generics = self.optional_generic_parameters(&mut children)?;
+        let parameters = self.parameters(children.node(SyntaxKind::Parameters)?)?;
+        let return_type_separator = children.tok::<delims::Colon>();
+        let return_type = self.ty(children.type_node()?)?;
+        let body = self.imperative_block(children.node(SyntaxKind::ImperativeBlock)?)?;
+        return Some(ast::ProcDefinition { id, proc_keyword, ident, generics, parameters,
+            return_type_separator, return_type, body });
+    }
+
+    fn struct_def(&mut self, node: &GreenNode) -> Option<ast::StructDefinition> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let struct_keyword = children.tok::<delims::Struct>()?;
+        let ident = children.tok::<class::Ident>()?;
+        let generics = self.optional_generic_parameters(&mut children)?;
+        let open_curly = children.tok::<delims::OpenCurly>();
+        let mut fields: LLBuilder<ast::Field> = LLBuilder::new();
+        while let Some(field) = children.node(SyntaxKind::Field) {
+            let field_id = self.node_id();
+            let mut field_children = Children::new(self.tokbuf, field);
+            let ident = field_children.tok::<class::Ident>()?;
+            let colon = field_children.tok::<delims::Colon>();
+            let ty = self.ty(field_children.type_node()?)?;
+            let comma = field_children.tok::<delims::Comma>();
+            fields.push(&mut self.mem, ast::Field { id: field_id, ident, colon, ty, comma });
+        }
+        let close_curly = children.tok::<delims::CloseCurly>();
+        return Some(ast::StructDefinition { id, struct_keyword, ident, generics, open_curly,
+            first: fields.head(), close_curly });
+    }
+
+    fn import(&mut self, node: &GreenNode) -> Option<ast::ImportDeclaration> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let import_keyword = children.tok::<delims::Import>()?;
+        let path = children.tok::<class::StringLiteral>()?;
+        let semicolon = children.tok::<delims::Semicolon>();
+        return Some(ast::ImportDeclaration { id, import_keyword, path, semicolon });
+    }
+
+    /// Lowers the generic parameter list among `children`, if there is one. Returns `None` only
+    /// if there is a generic parameter list and it is incomplete.
+    fn optional_generic_parameters(&mut self, children: &mut Children)
+    -> Option<Option<ast::GenericParameters>>
+    {
+        let Some(node) = children.node(SyntaxKind::GenericParameters) else { return Some(None); };
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let open_angle = children.tok::<delims::LessThan>()?;
+        let mut params: LLBuilder<ast::GenericParameter> = LLBuilder::new();
+        while let Some(param) = children.node(SyntaxKind::GenericParameter) {
+            let param_id = self.node_id();
+            let mut param_children = Children::new(self.tokbuf, param);
+            let ident = param_children.tok::<class::Ident>()?;
+            let mut bound: Option<ast::GenericBound> = None;
+            if let Some(bound_node) = param_children.node(SyntaxKind::GenericBound) {
+                let bound_id = self.node_id();
+                let mut bound_children = Children::new(self.tokbuf, bound_node);
+                let colon = bound_children.tok::<delims::Colon>()?;
+                let ty = self.ty(bound_children.type_node()?)?;
+                bound = Some(ast::GenericBound { id: bound_id, colon, ty });
+            }
+            let comma = param_children.tok::<delims::Comma>();
+            params.push(&mut self.mem,
+                ast::GenericParameter { id: param_id, ident, bound, comma });
+        }
+        let close_angle = children.tok::<delims::GreaterThan>();
+        return Some(Some(ast::GenericParameters { id, open_angle, first: params.head(),
+            close_angle }));
+    }
+
+    fn parameters(&mut self, node: &GreenNode) -> Option<ast::Parameters> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let open_paren = children.tok::<delims::OpenParen>()?;
+        let mut params: LLBuilder<ast::Parameter> = LLBuilder::new();
+        while let Some(param) = children.node(SyntaxKind::Parameter) {
+            let param_id = self.node_id();
+            let mut param_children = Children::new(self.tokbuf, param);
+            let ident = param_children.tok::<class::Ident>()?;
+            let colon = param_children.tok::<delims::Colon>();
+            let ty = self.ty(param_children.type_node()?)?;
+            let comma = param_children.tok::<delims::Comma>();
+            params.push(&mut self.mem, ast::Parameter { id: param_id, ident, colon, ty, comma });
+        }
+        let close_paren = children.tok::<delims::CloseParen>();
+        return Some(ast::Parameters { id, open_paren, close_paren, first: params.head() });
+    }
+
+    fn ty(&mut self, node: &GreenNode) -> Option<ast::Type> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        return Some(match node.kind {
+            SyntaxKind::NamedType => {
+                let ident = children.tok::<class::Ident>()?;
+                let mut arguments: Option<ast::TypeArguments> = None;
+                if let Some(arguments_node) = children.node(SyntaxKind::TypeArguments) {
+                    arguments = Some(self.type_arguments(arguments_node)?);
+                }
+                ast::Type::NamedType(ast::NamedType { id, ident, arguments })
+            },
+            SyntaxKind::ReferenceType => {
+                let ampersand = children.tok::<delims::Ampersand>()?;
+                let mut_keyword = children.tok::<delims::Mut>();
+                let referent = self.ty(children.type_node()?)?;
+                let referent = self.mem.bump(referent);
+                ast::Type::Reference(ast::ReferenceType { id, ampersand, mut_keyword, referent })
+            },
+            SyntaxKind::ArrayType => {
+                let open_square = children.tok::<delims::OpenSquare>()?;
+                let element = self.ty(children.type_node()?)?;
+                let element = self.mem.bump(element);
+                let semicolon = children.tok::<delims::Semicolon>()?;
+                let length = children.tok::<class::IntLiteral>()?;
+                let close_square = children.tok::<delims::CloseSquare>();
+                ast::Type::Array(ast::ArrayType { id, open_square, element, semicolon, length,
+                    close_square })
+            },
+            SyntaxKind::SliceType => {
+                let open_square = children.tok::<delims::OpenSquare>()?;
+                let element = self.ty(children.type_node()?)?;
+                let element = self.mem.bump(element);
+                let close_square = children.tok::<delims::CloseSquare>();
+                ast::Type::Slice(ast::SliceType { id, open_square, element, close_square })
+            },
+            SyntaxKind::TupleType => {
+                let open_paren = children.tok::<delims::OpenParen>()?;
+                let first = self.type_list(&mut children)?;
+                let close_paren = children.tok::<delims::CloseParen>();
+                ast::Type::Tuple(ast::TupleType { id, open_paren, first, close_paren })
+            },
+            SyntaxKind::ProcType => {
+                let proc_keyword = children.tok::<delims::Proc>()?;
+                let open_paren = children.tok::<delims::OpenParen>()?;
+                let first = self.type_list(&mut children)?;
+                let close_paren = children.tok::<delims::CloseParen>();
+                let return_type_separator = children.tok::<delims::Colon>()?;
+                let return_type = self.ty(children.type_node()?)?;
+                let return_type = self.mem.bump(return_type);
+                ast::Type::Proc(ast::ProcType { id, proc_keyword, open_paren, first, close_paren,
+                    return_type_separator, return_type })
+            },
+            SyntaxKind::ErrorType => ast::Type::Error(self.error_node(id, node)),
+            _ => return None
+        });
+    }
+
+    fn type_arguments(&mut self, node: &GreenNode) -> Option<ast::TypeArguments> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let open_angle = children.tok::<delims::LessThan>()?;
+        let first = self.type_list(&mut children)?;
+        let close_angle = children.tok::<delims::GreaterThan>();
+        return Some(ast::TypeArguments { id, open_angle, first, close_angle });
+    }
+
+    /// Lowers the `TypeArgument` nodes among `children`.
+    fn type_list(&mut self, children: &mut Children)
+    -> Option<Option<AstRef<ast::TypeArgumentNode>>>
+    {
+        let mut args: LLBuilder<ast::TypeArgument> = LLBuilder::new();
+        while let Some(arg) = children.node(SyntaxKind::TypeArgument) {
+            let arg_id = self.node_id();
+            let mut arg_children = Children::new(self.tokbuf, arg);
+            let ty = self.ty(arg_children.type_node()?)?;
+            let comma = arg_children.tok::<delims::Comma>();
+            args.push(&mut self.mem, ast::TypeArgument { id: arg_id, ty, comma });
+        }
+        return Some(args.head());
+    }
+
+    fn imperative_block(&mut self, node: &GreenNode) -> Option<ast::ImperativeBlock> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        let open_curly = children.tok::<delims::OpenCurly>();
+        let mut statements: LLBuilder<ast::AnyStatement> = LLBuilder::new();
+        while let Some(statement) = children.node_where(|kind| matches!(kind,
+            SyntaxKind::LetStatement | SyntaxKind::ExprStatement | SyntaxKind::ErrorStatement))
+        {
+            let statement = self.statement(statement)?;
+            statements.push(&mut self.mem, statement);
+        }
+        let close_curly = children.tok::<delims::CloseCurly>();
+        return Some(ast::ImperativeBlock { id, open_curly, first: statements.head(),
+            close_curly });
+    }
+
+    fn statement(&mut self, node: &GreenNode) -> Option<ast::AnyStatement> {
+        let id = self.node_id();
+        let mut children = Children::new(self.tokbuf, node);
+        return Some(match node.kind {
+            SyntaxKind::LetStatement => {
+                let let_keyword = children.tok::<delims::Let>()?;
+                let ident = children.tok::<class::Ident>()?;
+                let mut annotation: Option<ast::TypeAnnotation> = None;
+                if let Some(colon) = children.tok::<delims::Colon>() {
+                    let ty = self.ty(children.type_node()?)?;
+                    annotation = Some(ast::TypeAnnotation { colon, ty });
+                }
+                let eq = children.tok::<delims::Eq>()?;
+                let value = self.expr(children.expr_node()?)?;
+                let semicolon = children.tok::<delims::Semicolon>();
+                ast::AnyStatement::Let(ast::LetStatement { id, let_keyword, ident, annotation, eq,
+                    value, semicolon })
+            },
+            SyntaxKind::ExprStatement => {
+                let expr = self.expr(children.expr_node()?)?;
+                let semicolon = children.tok::<delims::Semicolon>();
+                ast::AnyStatement::Expr(ast::ExprStatement { id, expr, semicolon })
+            },
+            SyntaxKind::ErrorStatement => ast::AnyStatement::Error(self.error_node(id, node)),
+            _ => return None
+        });
+    }
+
+    fn expr(&mut self, node: &GreenNode) -> Option<ast::ExprNode> {
+        let mut children = Children::new(self.tokbuf, node);
+        if node.kind == SyntaxKind::InfixExpr {
+            // The parser numbers an infix expression after its left operand.
+            let left = self.expr(children.expr_node()?)?;
+            let id = self.node_id();
+            let operator = children.tok::<class::BinaryOperator>()?;
+            let right = self.expr(children.expr_node()?)?;
+            let left_operand = self.mem.bump(left);
+            let right_operand = self.mem.bump(right);
+            return Some(ast::ExprNode::Infix(ast::InfixExpr { id, left_operand, operator,
+                right_operand }));
+        }
+        let id = self.node_id();
+        return Some(match node.kind {
+            SyntaxKind::IdentExpr => {
+                ast::ExprNode::Ident(ast::IdentExpr { id, ident: children.tok::<class::Ident>()? })
+            },
+            SyntaxKind::LiteralExpr => {
+                let tok = children.tok::<class::Literal>()?;
+                ast::ExprNode::Literal(ast::LiteralExpr { id, tok })
+            },
+            SyntaxKind::ErrorExpr => ast::ExprNode::Error(self.error_node(id, node)),
+            _ => return None
+        });
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_cst {
+    use std::path::Path;
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::Ast;
+    use crate::parse::parse::{parse, parse_cst};
+    use crate::parse::visit::{visit_ast, Visitor};
+    use crate::tok::class::{TokClass, TokRef};
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::Key;
+    use crate::util::str_interner::StrInterner;
+    use super::{SyntaxElement, SyntaxKind, SyntaxNode};
+
+    #[derive(Default)]
+    struct TokKeys { keys: Vec<Key> }
+
+    impl Visitor<'_> for TokKeys {
+        fn visit_tok_ref<C: TokClass>(&mut self, _ast: &Ast, tok_ref: TokRef<C>) {
+            self.keys.push(tok_ref.key());
+        }
+    }
+
+    #[test]
+    fn test_structure() {
+        const SOURCE_TEXT: &'static str = "proc main( a: int , ): int {}\n";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let cst = parse_cst(&tokbuf, 0, &mut diagnostics);
+
+        let root = cst.root();
+        let proc_def = root.child_nodes().next().unwrap();
+        assert_eq!(proc_def.kind(), SyntaxKind::ProcDefinition);
+        assert_eq!(&SOURCE_TEXT[proc_def.text_range()], SOURCE_TEXT.trim_end());
+        let params = proc_def.child_nodes().next().unwrap();
+        assert_eq!(&SOURCE_TEXT[params.text_range()], "( a: int , )");
+        let param = params.child_nodes().next().unwrap();
+        assert_eq!(&SOURCE_TEXT[param.text_range()], "a: int ,");
+        assert_eq!(param.parent().unwrap().kind(), SyntaxKind::Parameters);
+        let Some(SyntaxElement::Token(linebreak)) = root.children().last() else { panic!(); };
+        assert_eq!(linebreak.text_range(), (SOURCE_TEXT.len() - 1)..SOURCE_TEXT.len());
+    }
+
+    #[test]
+    fn test_infix_chain() {
+        const SOURCE_TEXT: &'static str = "proc f(): int {\n    a < b < c < d;\n}\n";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let cst = parse_cst(&tokbuf, 0, &mut diagnostics);
+
+        // Each operation encloses the one before it.
+        fn infix_texts(node: &SyntaxNode, out: &mut Vec<&str>) {
+            if node.kind() == SyntaxKind::InfixExpr { out.push(&SOURCE_TEXT[node.text_range()]); }
+            for child in node.child_nodes() { infix_texts(&child, out); }
+        }
+        let mut texts: Vec<&str> = Vec::new();
+        infix_texts(&cst.root(), &mut texts);
+        assert_eq!(texts, ["a < b < c < d", "a < b < c", "a < b"]);
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        const SOURCE_TEXT: &'static str = "; proc f(x int): int {} proc g(): int {}";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let cst = parse_cst(&tokbuf, 0, &mut diagnostics);
+        let kinds: Vec<SyntaxKind> = cst.root().child_nodes().map(|node| node.kind()).collect();
+        assert_eq!(kinds, [SyntaxKind::Error, SyntaxKind::ProcDefinition,
+            SyntaxKind::ProcDefinition]);
+        let mut text: Vec<u8> = Vec::new();
+        cst.root().write_text(&tokbuf, &mut text);
+        assert_eq!(text, SOURCE_TEXT.as_bytes());
+    }
+
+    /// Checks that the CST of every golden parser input is lossless and converts into the same
+    /// AST that the parser produces, down to the positions of error nodes.
+    #[test]
+    fn test_lossless_and_lowers_to_ast() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/parse");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "cyan") { continue; }
+            let source_text = std::fs::read(&path).unwrap();
+            let str_interner = StrInterner::default();
+            let tokbuf = lex(&source_text, &str_interner);
+            let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+            let cst = parse_cst(&tokbuf, 0, &mut diagnostics);
+
+            let mut text: Vec<u8> = Vec::new();
+            cst.root().write_text(&tokbuf, &mut text);
+            assert_eq!(text, source_text, "{}", path.display());
+            let ast = parse(&tokbuf, 0, &mut diagnostics);
+            let lowered = cst.to_ast(&tokbuf);
+            assert_eq!(lowered.dump(&tokbuf), ast.dump(&tokbuf), "{}", path.display());
+            let (mut lowered_keys, mut keys) = (TokKeys::default(), TokKeys::default());
+            visit_ast(&mut lowered_keys, &lowered);
+            visit_ast(&mut keys, &ast);
+            assert_eq!(lowered_keys.keys, keys.keys, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! A stable, human-readable textual representation of an [`Ast`].
+//!
+//! Each top-level item is printed as an S-expression whose head names the kind of node, followed
+//! by the source text of the tokens which distinguish the node, followed by its child nodes.
+//! A node whose children are all leaves is printed on one line. Otherwise, each child begins
+//! a new line, indented by two spaces beneath its parent.
+//!
+//! ```txt
+//! (proc main
+//!   (params
+//!     (param argc (type int)))
+//!   (type int)
+//!   (block))
+//! ```
+
+use crate::parse::ast::*;
+use crate::parse::visit::{self, Visitor};
+use crate::tok::class::{TokClass, TokRef};
+use crate::tok::tokbuf::TokBuf;
+
+impl Ast {
+    /// Renders the AST as a list of S-expressions, one per top-level item. `tokbuf` must be the
+    /// buffer which this AST was parsed from.
+    pub fn dump(&self, tokbuf: &TokBuf) -> String {
+        let mut dumper = Dumper { tokbuf, stack: vec![SExpr::default()] };
+        visit::visit_ast(&mut dumper, self);
+        let mut out = String::new();
+        for item in dumper.stack.pop().unwrap().children {
+            item.write(&mut out, 0);
+            out.push('\n');
+        }
+        return out;
+    }
+}
+
+#[derive(Default)]
+struct SExpr {
+    head: &'static str,
+    atoms: Vec<String>,
+    children: Vec<SExpr>
+}
+
+impl SExpr {
+    fn write(&self, out: &mut String, indent: usize) {
+        out.push('(');
+        out.push_str(self.head);
+        for atom in &self.atoms {
+            out.push(' ');
+            out.push_str(atom);
+        }
+        let inline = self.children.iter().all(|child| child.children.is_empty());
+        for child in &self.children {
+            if inline {
+                out.push(' ');
+            } else {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent + 2));
+            }
+            child.write(out, indent + 2);
+        }
+        out.push(')');
+    }
+}
+
+struct Dumper<'a, 'b> {
+    tokbuf: &'a TokBuf<'b>,
+
+    /// The S-expressions of the nodes currently being visited, outermost first.
+    stack: Vec<SExpr>
+}
+
+impl Dumper<'_, '_> {
+    fn open(&mut self, head: &'static str) {
+        self.stack.push(SExpr { head, ..SExpr::default() });
+    }
+
+    fn close(&mut self) {
+        let sexpr = self.stack.pop().unwrap();
+        self.stack.last_mut().unwrap().children.push(sexpr);
+    }
+
+    /// Appends the source text of `tok_ref` to the innermost S-expression.
+    fn atom<C: TokClass>(&mut self, tok_ref: TokRef<C>) {
+        let tok = self.tokbuf.get(tok_ref.key()).expect("TokRef does not point into this TokBuf");
+        let mut source_text: Vec<u8> = Vec::new();
+        tok.write_source_text(&mut source_text);
+        let text = String::from_utf8_lossy(&source_text).into_owned();
+        self.stack.last_mut().unwrap().atoms.push(text);
+    }
+
+}
+
+impl<'ast> Visitor<'ast> for Dumper<'_, '_> {
+    fn visit_proc_definition(&mut self, ast: &'ast Ast, proc_def: &'ast ProcDefinition) {
+        self.open("proc");
+        self.atom(proc_def.ident);
+        visit::walk_proc_definition(self, ast, proc_def);
+        self.close();
+    }
+
+    fn visit_struct_definition(&mut self, ast: &'ast Ast, struct_def: &'ast StructDefinition) {
+        self.open("struct");
+        self.atom(struct_def.ident);
+        visit::walk_struct_definition(self, ast, struct_def);
+        self.close();
+    }
+
+    fn visit_import_declaration(&mut self, _ast: &'ast Ast, import: &'ast ImportDeclaration) {
+        self.open("import");
+        self.atom(import.path);
+        self.close();
+    }
+
+    fn visit_field(&mut self, ast: &'ast Ast, field: &'ast Field) {
+        self.open("field");
+        self.atom(field.ident);
+        visit::walk_field(self, ast, field);
+        self.close();
+    }
+
+    fn visit_generic_parameters(&mut self, ast: &'ast Ast, generics: &'ast GenericParameters) {
+        self.open("generics");
+        visit::walk_generic_parameters(self, ast, generics);
+        self.close();
+    }
+
+    fn visit_generic_parameter(&mut self, ast: &'ast Ast, generic: &'ast GenericParameter) {
+        self.open("generic");
+        self.atom(generic.ident);
+        visit::walk_generic_parameter(self, ast, generic);
+        self.close();
+    }
+
+    fn visit_generic_bound(&mut self, ast: &'ast Ast, bound: &'ast GenericBound) {
+        self.open("bound");
+        visit::walk_generic_bound(self, ast, bound);
+        self.close();
+    }
+
+    fn visit_parameters(&mut self, ast: &'ast Ast, parameters: &'ast Parameters) {
+        self.open("params");
+        visit::walk_parameters(self, ast, parameters);
+        self.close();
+    }
+
+    fn visit_parameter(&mut self, ast: &'ast Ast, parameter: &'ast Parameter) {
+        self.open("param");
+        self.atom(parameter.ident);
+        visit::walk_parameter(self, ast, parameter);
+        self.close();
+    }
+
+    fn visit_named_type(&mut self, ast: &'ast Ast, named_type: &'ast NamedType) {
+        self.open("type");
+        self.atom(named_type.ident);
+        visit::walk_named_type(self, ast, named_type);
+        self.close();
+    }
+
+    fn visit_reference_type(&mut self, ast: &'ast Ast, reference: &'ast ReferenceType) {
+        self.open("ref");
+        if let Some(mut_keyword) = reference.mut_keyword { self.atom(mut_keyword); }
+        visit::walk_reference_type(self, ast, reference);
+        self.close();
+    }
+
+    fn visit_array_type(&mut self, ast: &'ast Ast, array: &'ast ArrayType) {
+        self.open("array");
+        self.atom(array.length);
+        visit::walk_array_type(self, ast, array);
+        self.close();
+    }
+
+    fn visit_slice_type(&mut self, ast: &'ast Ast, slice: &'ast SliceType) {
+        self.open("slice");
+        visit::walk_slice_type(self, ast, slice);
+        self.close();
+    }
+
+    fn visit_tuple_type(&mut self, ast: &'ast Ast, tuple: &'ast TupleType) {
+        self.open("tuple");
+        visit::walk_tuple_type(self, ast, tuple);
+        self.close();
+    }
+
+    fn visit_proc_type(&mut self, ast: &'ast Ast, proc_type: &'ast ProcType) {
+        self.open("proctype");
+        visit::walk_proc_type(self, ast, proc_type);
+        self.close();
+    }
+
+    fn visit_type(&mut self, ast: &'ast Ast, ty: &'ast Type) {
+        if let Type::Error(_) = ty {
+            self.open("error");
+            self.close();
+            return;
+        }
+        visit::walk_type(self, ast, ty);
+    }
+
+    fn visit_imperative_block(&mut self, ast: &'ast Ast, block: &'ast ImperativeBlock) {
+        self.open("block");
+        visit::walk_imperative_block(self, ast, block);
+        self.close();
+    }
+
+    fn visit_statement(&mut self, ast: &'ast Ast, statement: &'ast AnyStatement) {
+        match statement {
+            AnyStatement::Let(let_statement) => {
+                self.open("let");
+                self.atom(let_statement.ident);
+            },
+            AnyStatement::Expr(_) => self.open("expr"),
+            AnyStatement::Error(_) => self.open("error"),
+        }
+        visit::walk_statement(self, ast, statement);
+        self.close();
+    }
+
+    fn visit_expr(&mut self, ast: &'ast Ast, expr: &'ast ExprNode) {
+        match expr {
+            ExprNode::Ident(ident_expr) => {
+                self.open("ident");
+                self.atom(ident_expr.ident);
+                self.close();
+            },
+            ExprNode::Infix(infix_expr) => {
+                self.open("infix");
+                self.atom(infix_expr.operator);
+                visit::walk_expr(self, ast, expr);
+                self.close();
+            },
+            ExprNode::Literal(literal_expr) => {
+                self.open("literal");
+                self.atom(literal_expr.tok);
+                self.close();
+            },
+            ExprNode::Error(_) => {
+                self.open("error");
+                self.close();
+            },
+        }
+    }
+
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_dump {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::parse::parse;
+    use crate::tok::lex::lex;
+    use crate::util::golden;
+    use crate::util::str_interner::StrInterner;
+
+    #[test]
+    fn test_dump() {
+        const SOURCE_TEXT: &'static str = "proc main(): int {}";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert_eq!(ast.dump(&tokbuf), "(proc main (params) (type int) (block))\n");
+    }
+
+    #[test]
+    fn test_golden() {
+        golden::check_dir("testdata/parse", "cyan", "ast", |source_text| {
+            let str_interner = StrInterner::default();
+            let tokbuf = lex(source_text, &str_interner);
+            let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+            let ast = parse(&tokbuf, 0, &mut diagnostics);
+            let mut out = ast.dump(&tokbuf);
+            if !diagnostics.is_empty() {
+                out.push_str(&format!("; {} diagnostic(s)\n", diagnostics.len()));
+            }
+            return out;
+        });
+    }
+}
@@ -0,0 +1,115 @@
+//! Parsing of fragments of a source unit, such as a single expression or type.
+//!
+//! Each entry point parses one production from a range of keys in a [`TokBuf`], which may be
+//! the whole buffer, see [`TokBuf::key_range`]. Parsing stops once the production is complete,
+//! and the tokens which follow it are left unconsumed. The fragment's diagnostics are returned
+//! with it rather than pushed onto a shared list, so that a failed attempt can be discarded.
+
+use std::ops::Range;
+use crate::diagnostic::AnyDiagnostic;
+use crate::parse::ast::{AnyStatement, AnyTopLevelItem, Ast, ExprNode, Type};
+use crate::parse::parse;
+use crate::source_unit::SourceUnitId;
+use crate::tok::tokbuf::{Key, TokBuf};
+
+/// A node parsed from a fragment of a source unit.
+pub struct Fragment<T> {
+    /// Holds the nodes of the fragment. The root of this AST has no items; `node` is the root of
+    /// the fragment.
+    pub ast: Ast,
+    pub node: T,
+    pub diagnostics: Vec<AnyDiagnostic>,
+
+    /// The number of tokens consumed, including the trivia which follows the fragment.
+    pub consumed: usize,
+
+    /// The key of the first token which was not consumed.
+    pub end: Key
+}
+
+pub fn parse_expr(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId)
+-> Fragment<ExprNode>
+{
+    return parse::parse_fragment(tokbuf, range, source_unit, parse::parse_expr_fragment);
+}
+
+/// Parses a type. A type which cannot be parsed is replaced by [`Type::Error`].
+pub fn parse_type(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId)
+-> Fragment<Type>
+{
+    return parse::parse_fragment(tokbuf, range, source_unit, parse::parse_type_fragment);
+}
+
+/// Parses a statement, including the `;` which terminates it.
+pub fn parse_statement(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId)
+-> Fragment<AnyStatement>
+{
+    return parse::parse_fragment(tokbuf, range, source_unit, parse::parse_statement_fragment);
+}
+
+/// Parses a top-level item. Returns `None` for the node if the item cannot be parsed.
+pub fn parse_item(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId)
+-> Fragment<Option<AnyTopLevelItem>>
+{
+    return parse::parse_fragment(tokbuf, range, source_unit, parse::parse_item_fragment);
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_fragment {
+    use crate::parse::ast::{AnyStatement, AnyTopLevelItem, ExprNode, Type};
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::TokCursor;
+    use crate::util::str_interner::StrInterner;
+    use super::{parse_expr, parse_item, parse_statement, parse_type};
+
+    #[test]
+    fn test_parse_expr() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(b"a == 1 = b rest", &str_interner);
+        let fragment = parse_expr(&tokbuf, tokbuf.key_range(), 0);
+        assert!(fragment.diagnostics.is_empty());
+        let ExprNode::Infix(_) = fragment.node else { panic!(); };
+        // Every token but `rest` is consumed, including the space which precedes it.
+        assert_eq!(fragment.consumed, tokbuf.len() - 1);
+        assert!(fragment.end < tokbuf.key_range().end);
+    }
+
+    #[test]
+    fn test_parse_in_range() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(b"let x: List<int> = y;", &str_interner);
+        // The tokens of `List<int>` are the sixth through the ninth.
+        let mut cursor = TokCursor::new(&tokbuf);
+        for _ in 0..5 { cursor.advance(); }
+        let start = cursor.at();
+        for _ in 0..4 { cursor.advance(); }
+        let fragment = parse_type(&tokbuf, start..cursor.at(), 0);
+        assert!(fragment.diagnostics.is_empty());
+        let Type::NamedType(_) = fragment.node else { panic!(); };
+        assert_eq!(fragment.consumed, 4);
+        assert_eq!(fragment.end, cursor.at());
+
+        let fragment = parse_statement(&tokbuf, tokbuf.key_range(), 0);
+        assert!(fragment.diagnostics.is_empty());
+        let AnyStatement::Let(_) = fragment.node else { panic!(); };
+        assert_eq!(fragment.consumed, tokbuf.len());
+    }
+
+    #[test]
+    fn test_parse_item() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(b"struct S { a: int }", &str_interner);
+        let fragment = parse_item(&tokbuf, tokbuf.key_range(), 0);
+        assert!(fragment.diagnostics.is_empty());
+        let Some(AnyTopLevelItem::Struct(struct_def)) = fragment.node else { panic!(); };
+        assert_eq!(struct_def.fields(&fragment.ast).count(), 1);
+
+        let tokbuf = lex(b"let", &str_interner);
+        let fragment = parse_item(&tokbuf, tokbuf.key_range(), 0);
+        assert!(fragment.node.is_none());
+        assert_eq!(fragment.diagnostics.len(), 1);
+        assert_eq!(fragment.consumed, 0);
+    }
+}
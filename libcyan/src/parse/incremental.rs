@@ -0,0 +1,341 @@
+//! Reparsing of the top-level items affected by an edit of the source text.
+//!
+//! Every top-level item begins with an `ItemDeclarator`, and the parser resumes at the next
+//! declarator after a malformed item, so items are natural units of reparsing. After an edit,
+//! parsing restarts at the item preceding the edited tokens. It continues until the next item
+//! would begin at the first token of an old item following the edit. That item, and all the
+//! items after it, are kept, along with all the items before the restart. Note that an item is
+//! kept only if none of its tokens were relexed, and [`TokBuf::apply_edit`] relexes whole lines.
+//!
+//! The edit may change how delimiters pair across the restart or the resumption, as when it
+//! closes a group opened before the restart. So parsing never restarts at an item which began
+//! within a group, see [`TokTrees::is_enclosed`], but at an earlier one. It resumes only at an
+//! item which began outside of every group, and at which no group opened after the restart is
+//! left open. Otherwise it continues through the end of the buffer.
+//!
+//! Kept items keep their [`NodeId`]s. Reparsed items are assigned new ids, above those of the
+//! old AST. The keys of the tokens of the items after the edit are rebased, unless the edit
+//! kept the number of entries of the buffer. The nodes of replaced items are not reclaimed one by
+//! one. Instead, the AST is parsed from scratch once reparsing has assigned more ids than that
+//! parse did, see `REBUILD_MIN_NODES`. Then no item keeps its id.
+//!
+//! Diagnostics are attributed to items by their [`subject`]. The parser reports a missing token
+//! at the next token, so a diagnostic at the first token of an item belongs to the item before
+//! it. The diagnostics of replaced items are removed along with them.
+//!
+//! [`subject`]: AnyDiagnostic::subject
+//! [`NodeId`]: crate::parse::ast::NodeId
+
+use crate::diagnostic::AnyDiagnostic;
+use crate::parse::ast::{AnyTopLevelItem, Ast, AstRef, TopLevelItemNode};
+use crate::parse::parse;
+use crate::parse::visit::VisitorMut;
+use crate::source_unit::SourceUnitId;
+use crate::tok::class::{TokClass, TokRef};
+use crate::tok::tokbuf::{Key, TokBuf, TokBufEdit};
+use crate::tok::tree::TokTrees;
+
+/// The fewest `NodeId`s which reparsing assigns before the AST is parsed from scratch, so that
+/// a small AST is not rebuilt on every edit.
+const REBUILD_MIN_NODES: u32 = 1 << 12;
+
+/// Updates `ast`, which was parsed from `tokbuf` before `edit` was applied to it, to match the
+/// edited `tokbuf`. Returns whether `ast` was parsed from scratch, in which case no item kept its
+/// [`NodeId`](crate::parse::ast::NodeId).
+///
+/// `diagnostics` are updated along with it. Those of `source_unit` which belong to replaced items
+/// are removed, and the rest are rebased onto the edited `tokbuf`. The diagnostics of the
+/// reparsed items are appended. Diagnostics of other source units are left alone.
+pub fn reparse(ast: &mut Ast, tokbuf: &TokBuf, edit: &TokBufEdit, source_unit: SourceUnitId,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> bool
+{
+    let reparsed_nodes = ast.node_count - ast.parsed_node_count;
+    if reparsed_nodes > ast.parsed_node_count.max(REBUILD_MIN_NODES) {
+        diagnostics.retain(|diagnostic| diagnostic.source_unit() != source_unit);
+        *ast = parse::parse(tokbuf, source_unit, diagnostics);
+        return true;
+    }
+
+    let mut nodes: Vec<AstRef<TopLevelItemNode>> = Vec::new();
+    let mut next = ast.root.ll_head;
+    while let Some(node) = next {
+        nodes.push(node);
+        next = ast.get(node).next;
+    }
+    let firsts: Vec<Key> = nodes.iter().map(|node| ast.get(*node).value.declarator_key())
+        .collect();
+    let is_enclosed = |idx: usize| ast.enclosed_items.binary_search(&firsts[idx]).is_ok();
+
+    // The item preceding the edit is reparsed too, since the edit may complete it. `restart` is
+    // the index of the first reparsed item, or `None` if parsing restarts at the beginning of the
+    // buffer.
+    let preceding = firsts.partition_point(|first| *first < edit.old.start);
+    let mut restart = preceding.checked_sub(1);
+    while let Some(idx) = restart.filter(|idx| is_enclosed(*idx)) {
+        restart = idx.checked_sub(1);
+    }
+    let start = restart.map_or(tokbuf.key_range().start, |idx| firsts[idx]);
+
+    // Resuming at the first candidate may leave a group open, so the reparsed region is widened
+    // over ever more candidates until none is.
+    let resumable = firsts.partition_point(|first| *first < edit.old.end);
+    let candidates: Vec<usize> = (resumable..firsts.len()).filter(|idx| !is_enclosed(*idx))
+        .collect();
+    let end = tokbuf.key_range().end;
+    let mut skip: usize = 0;
+    let (mut kept, trees, mut reparsed_diagnostics) = loop {
+        let resume = candidates.get(skip).copied();
+        let resume_at = resume.map_or(end, |idx| edit.rebase(firsts[idx]).unwrap());
+        let mut trees_diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let trees = TokTrees::build_range(tokbuf, start..resume_at, source_unit,
+            &mut trees_diagnostics);
+        if resume.is_none() || !trees.is_left_open() { break (resume, trees, trees_diagnostics); }
+        skip = 2 * skip + 1;
+    };
+
+    let resume_at = kept.map(|idx| edit.rebase(firsts[idx]).unwrap());
+    let (mut items, mut enclosed, resumed) = parse::parse_items_until(ast, tokbuf, start, trees,
+        resume_at, source_unit, &mut reparsed_diagnostics);
+    if !resumed && kept.is_some() {
+        // An item ran past the one at which parsing would have resumed, so the rest of the
+        // buffer is reparsed instead.
+        reparsed_diagnostics.clear();
+        let trees = TokTrees::build_range(tokbuf, start..end, source_unit,
+            &mut reparsed_diagnostics);
+        (items, enclosed, _) = parse::parse_items_until(ast, tokbuf, start, trees, None,
+            source_unit, &mut reparsed_diagnostics);
+        kept = None;
+    }
+
+    // The replaced items span the keys after `start` through the first kept item, or through the
+    // end of the buffer. Only when parsing restarted at the beginning of the buffer does the
+    // token at `start` belong to them.
+    let replaced = |key: Key| {
+        return (key > start || (restart.is_none() && key == start))
+            && kept.is_none_or(|idx| key <= firsts[idx]);
+    };
+    let old_diagnostics = std::mem::take(diagnostics);
+    diagnostics.extend(old_diagnostics.into_iter().filter_map(|diagnostic| {
+        if diagnostic.source_unit() != source_unit { return Some(diagnostic); }
+        if replaced(diagnostic.subject()) { return None; }
+        return diagnostic.rebase(edit);
+    }));
+    diagnostics.extend(reparsed_diagnostics);
+
+    let old_enclosed = std::mem::take(&mut ast.enclosed_items);
+    let kept_enclosed = old_enclosed.iter().copied()
+        .filter(|key| kept.is_some_and(|idx| *key >= firsts[idx]))
+        .map(|key| edit.rebase(key).unwrap());
+    ast.enclosed_items = old_enclosed.iter().copied().filter(|key| *key < start)
+        .chain(enclosed)
+        .chain(kept_enclosed)
+        .collect();
+
+    // Items after the edit keep their nodes, but the keys of their tokens have shifted, unless
+    // the edit kept the number of entries.
+    if edit.shifts_keys() {
+        let mut rebase = Rebase { edit };
+        for node in &nodes[kept.unwrap_or(nodes.len())..] {
+            let mut item = ast.get(*node).value;
+            rebase.visit_top_level_item_mut(ast, &mut item);
+            ast.get_mut(*node).value = item;
+        }
+    }
+
+    // Splice the reparsed items into the list in place of the replaced ones.
+    let resumed_node = kept.map(|idx| nodes[idx]);
+    let reparsed_head = items.head().or(resumed_node);
+    if let Some(tail) = items.tail() { ast.get_mut(tail).next = resumed_node; }
+    match restart.and_then(|idx| idx.checked_sub(1)) {
+        Some(last_kept) => ast.get_mut(nodes[last_kept]).next = reparsed_head,
+        None => ast.root.ll_head = reparsed_head
+    }
+    return false;
+}
+
+/// Moves every token reference past the edit to the token's key in the edited buffer.
+struct Rebase<'a> { edit: &'a TokBufEdit }
+
+impl VisitorMut for Rebase<'_> {
+    fn visit_tok_ref_mut<C: TokClass>(&mut self, _ast: &mut Ast, tok_ref: &mut TokRef<C>) {
+        *tok_ref = tok_ref.rebase(self.edit).expect("kept item references a replaced token");
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_incremental {
+    use std::ops::Range;
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::{Ast, NodeId};
+    use crate::parse::parse::parse;
+    use crate::source_unit::SourceMap;
+    use crate::tok::lex::lex;
+    use crate::util::str_interner::StrInterner;
+    use super::{reparse, REBUILD_MIN_NODES};
+
+    const SOURCE_TEXT: &'static str = "\
+        proc a(): int {\n    let x = 1;\n}\n\
+        \n\
+        proc b(y: int): int {\n    y;\n}\n\
+        \n\
+        struct C { f: int }\n";
+
+    fn item_ids(ast: &Ast) -> Vec<NodeId> {
+        return ast.root.items(ast).map(|item| item.id()).collect();
+    }
+
+    /// Applies the edit to `SOURCE_TEXT`, and checks that reparsing gives the same AST and
+    /// diagnostics as parsing the edited text from scratch. Returns the ids of the items before
+    /// and after the edit.
+    fn check_edit(range: Range<usize>, new_text: &str) -> (Vec<NodeId>, Vec<NodeId>, u32) {
+        return check_edit_in(SOURCE_TEXT, range, new_text);
+    }
+
+    fn check_edit_in(source_text: &str, range: Range<usize>, new_text: &str)
+        -> (Vec<NodeId>, Vec<NodeId>, u32)
+    {
+        let str_interner = StrInterner::default();
+        let mut tokbuf = lex(source_text.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let mut ast = parse(&tokbuf, 0, &mut diagnostics);
+        let old_ids = item_ids(&ast);
+        let old_count = ast.node_count;
+
+        let edit = tokbuf.apply_edit(range.clone(), new_text.as_bytes());
+        reparse(&mut ast, &tokbuf, &edit, 0, &mut diagnostics);
+
+        let mut edited_text = source_text.to_string();
+        edited_text.replace_range(range, new_text);
+        let fresh_tokbuf = lex(edited_text.as_bytes(), &str_interner);
+        let mut fresh_diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let fresh = parse(&fresh_tokbuf, 0, &mut fresh_diagnostics);
+        assert_eq!(ast.dump(&tokbuf), fresh.dump(&fresh_tokbuf));
+        let subjects = |diagnostics: &[AnyDiagnostic]| {
+            let mut subjects: Vec<_> = diagnostics.iter().map(|diag| diag.subject()).collect();
+            subjects.sort();
+            return subjects;
+        };
+        assert_eq!(subjects(&diagnostics), subjects(&fresh_diagnostics));
+        return (old_ids, item_ids(&ast), old_count);
+    }
+
+    #[test]
+    fn test_reparse_body() {
+        let offset = SOURCE_TEXT.find("y;").unwrap();
+        let (old, new, old_count) = check_edit(offset..(offset + 2), "let z = y == 2;\n    z;");
+        // Only the edited item is reparsed.
+        assert_eq!((new[0], new[2]), (old[0], old[2]));
+        assert!(new[1].0 >= old_count);
+    }
+
+    #[test]
+    fn test_insert_item() {
+        // The edit is confined to the blank line preceding `b`.
+        let offset = SOURCE_TEXT.find("\nproc b").unwrap();
+        let (old, new, old_count) = check_edit(offset..offset, "struct D {}\n");
+        assert_eq!(new.len(), 4);
+        assert!(new[0].0 >= old_count && new[1].0 >= old_count);
+        assert_eq!((new[2], new[3]), (old[1], old[2]));
+    }
+
+    #[test]
+    fn test_remove_items() {
+        let start = SOURCE_TEXT.find("proc a").unwrap();
+        let end = SOURCE_TEXT.find("\nstruct").unwrap();
+        let (old, new, _) = check_edit(start..end, "");
+        assert_eq!(new, [old[2]]);
+    }
+
+    #[test]
+    fn test_unbalance_delimiters() {
+        // Removing the `}` of `a` leaves its block open through the end of the buffer, so `C`,
+        // which now begins within it, is reparsed too.
+        let offset = SOURCE_TEXT.find("}").unwrap();
+        let (_, new, old_count) = check_edit(offset..(offset + 1), "");
+        assert_eq!(new.len(), 3);
+        assert!(new.iter().all(|id| id.0 >= old_count));
+    }
+
+    #[test]
+    fn test_close_group_before_restart() {
+        // The `)` closes the group which `S` and `T` began within, so parsing restarts at `a`,
+        // and `S` and `T` become part of the call.
+        let source_text = "proc a(): int {}\nf(\nstruct S { f: int }\nstruct T {}\n";
+        let (_, new, old_count) = check_edit_in(source_text,
+            source_text.len()..source_text.len(), ")\n");
+        assert_eq!(new.len(), 1);
+        assert!(new[0].0 >= old_count);
+    }
+
+    #[test]
+    fn test_edit_delimiters() {
+        // Inserting or removing any one delimiter gives the same AST as parsing from scratch.
+        const SOURCE_TEXT: &'static str = "\
+            proc a(x: List<int>): int { f(x); }\n\
+            struct S { f: int }\n\
+            proc b(): int { g([1, 2]); }\n\
+            struct T {}\n";
+        for offset in 0..=SOURCE_TEXT.len() {
+            for delimiter in ["(", ")", "[", "]", "{", "}", "<", ">"] {
+                check_edit_in(SOURCE_TEXT, offset..offset, delimiter);
+            }
+            if offset < SOURCE_TEXT.len() { check_edit_in(SOURCE_TEXT, offset..(offset + 1), ""); }
+        }
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let str_interner = StrInterner::default();
+        let mut tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let mut ast = parse(&tokbuf, 0, &mut diagnostics);
+        let offset = SOURCE_TEXT.find("1;").unwrap();
+        // Each edit reparses `a`, until the replaced nodes outnumber those of a fresh parse.
+        let mut edits: u32 = 0;
+        loop {
+            let edit = tokbuf.apply_edit(offset..(offset + 1), b"2");
+            edits += 1;
+            if reparse(&mut ast, &tokbuf, &edit, 0, &mut diagnostics) { break; }
+        }
+        assert!(edits > 1 && edits < REBUILD_MIN_NODES);
+        assert_eq!(ast.node_count, ast.parsed_node_count);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_reparse_diagnostics() {
+        const SOURCE_TEXT: &'static str = "\
+            struct S { f int }\n\
+            proc a(): int {}\n\
+            proc b(y int): int {\n    y;\n}\n\
+            struct C { f int }\n";
+        let str_interner = StrInterner::default();
+        let mut tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let mut ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert_eq!(diagnostics.len(), 3);
+
+        // Fixes the parameter of `b`, breaks its body, and moves `C` down by a line.
+        let start = SOURCE_TEXT.find("y int").unwrap();
+        let end = SOURCE_TEXT.find("y;").unwrap();
+        let edit = tokbuf.apply_edit(start..end, b"y: int): int {\n    let = 2;\n    ");
+        reparse(&mut ast, &tokbuf, &edit, 0, &mut diagnostics);
+
+        let mut edited_text = SOURCE_TEXT.to_string();
+        edited_text.replace_range(start..end, "y: int): int {\n    let = 2;\n    ");
+        let mut source_map = SourceMap::new(&str_interner);
+        let mut fresh_diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        source_map.add("a.cyan", edited_text.into_bytes(), &mut fresh_diagnostics);
+        let render = |diagnostics: &[AnyDiagnostic]| {
+            let mut rendered: Vec<String> = diagnostics.iter()
+                .map(|diagnostic| diagnostic.view().render(&source_map))
+                .collect();
+            rendered.sort();
+            return rendered;
+        };
+        assert_eq!(render(&diagnostics), render(&fresh_diagnostics));
+        assert!(render(&diagnostics).iter().any(|rendered| rendered.contains("a.cyan:7:")));
+    }
+}
@@ -1,2 +1,10 @@
 pub mod ast;
 pub mod parse;
+pub mod visit;
+pub mod dump;
+pub mod span;
+pub mod trivia;
+pub mod cst;
+pub mod fragment;
+pub mod incremental;
+pub mod trace;
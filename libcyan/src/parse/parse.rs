@@ -1,36 +1,41 @@
 use std::marker::PhantomData;
+use std::ops::Range;
 use crate::diagnostic::{self, AnyDiagnostic};
 use crate::source_unit::SourceUnitId;
 use crate::tok;
-use crate::tok::tokbuf::{TokBuf, TokCursor};
-use crate::tok::class::{delims, TokClass, TokRef};
-use crate::parse::ast::{self, Ast, AstRef, calc_ast_size_upperbound, AST_ALIGN};
-use crate::util::bump_allocator::{BumpAllocator, extend_ll};
+use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+use crate::tok::tree::TokTrees;
+use crate::tok::class::{delims, AnyTok, TokClass, TokRef};
+use crate::parse::ast::{self, Ast, AstRef, NodeId};
+use crate::parse::cst::{self, Cst, Event, SyntaxKind};
+use crate::parse::fragment::Fragment;
+use crate::parse::trace::{Outcome, Trace};
+use crate::util::bump_allocator::{BumpAllocator, LLBuilder};
 
 // -- TokStream ----------------------------------------------------------------------------------
 
-struct TokStream<'a> { cursor: TokCursor<'a> }
+struct TokStream<'a> { cursor: TokCursor<'a>, trees: TokTrees }
 
 impl<'a> TokStream<'a> {
-    fn new(tokbuf: &'a TokBuf<'a>) -> Self {
-        Self { cursor: TokCursor::new(tokbuf) }
+    fn new(cursor: TokCursor<'a>, trees: TokTrees) -> Self {
+        Self { cursor, trees }
     }
 
-    /// Consumes and discards all formatting tokens (whitespace, linebreaks, etc). Then, checks if
+    /// Consumes and discards all trivia (whitespace, linebreaks, comments). Then, checks if
     /// the next token in the stream is in the token-class `C`. If so, consumes it and returns
     /// a reference to it. Otherwise, returns `None` and doesnt consume it.
     fn consume_ref<C: TokClass>(&mut self) -> Option<TokRef<C>> {
-        self.discard::<tok::class::Formatting>();
+        self.discard::<tok::class::Trivia>();
         let tokref = self.cursor.match_ref()?;
         self.cursor.advance();
         return Some(tokref);
     }
 
-    /// Consumes and discards all formatting tokens (whitespace, linebreaks, etc). Then, checks if
+    /// Consumes and discards all trivia (whitespace, linebreaks, comments). Then, checks if
     /// the next token in the stream is in the token-class `C`. If so, returns it, but does not
     /// consume it. Otherwise, returns `None` and doesnt consume it.
     fn peek<C: TokClass>(&mut self) -> Option<C::View<'a>> {
-        self.discard::<tok::class::Formatting>();
+        self.discard::<tok::class::Trivia>();
         return self.cursor.r#match::<C>();
     }
 
@@ -53,11 +58,16 @@ impl<'a> TokStream<'a> {
     }
 
     /// Consumes and discards all tokens up to but not including the next occurrence of `C`.
+    /// Groups of tokens enclosed by paired delimiters are discarded whole, so an occurrence of `C`
+    /// within such a group does not stop the discarding.
     fn sync<C: TokClass>(&mut self) {
         while let Some(next) = self.cursor.read_tok() {
             if C::r#match(&next).is_some() {
                 return;
             }
+            if let Some(close) = self.trees.close_of(self.cursor.at()) {
+                self.cursor.seek(close);
+            }
             self.cursor.advance();
         }
     }
@@ -72,7 +82,7 @@ impl<'a> TokStream<'a> {
 
 // -- Support ------------------------------------------------------------------------------------
 
-type AstAllocator = BumpAllocator<AST_ALIGN>;
+type AstAllocator = BumpAllocator;
 
 /// A `ParsePanic` is thrown when an unexpected token sequence is encountered.
 /// Important: The code that constructs a `ParsePanic` is **also responsible for** pushing
@@ -84,62 +94,365 @@ struct ParsePanic;
 
 type ParseResult<T> = Result<T, ParsePanic>;
 
-struct ParseContext<'a, 'b> {
+pub(super) struct ParseContext<'a, 'b> {
     stream: &'a mut TokStream<'b>,
     ast_mem: &'a mut AstAllocator,
     source_unit: SourceUnitId,
     diagnostics: &'a mut Vec<AnyDiagnostic>,
+
+    /// The `NodeId` which will be assigned to the next node.
+    next_node_id: u32,
+
+    /// The events from which a CST is built, or `None` if no CST is being built.
+    events: Option<Vec<Event>>,
+
+    /// The productions entered and exited so far, or `None` if parsing is not being traced.
+    trace: Option<Trace>,
+
+    /// The number of nodes which have been started but not yet finished.
+    open_nodes: u32,
+
+    /// The position of the last reported syntax error. At most one syntax error is reported at
+    /// each position, so that the recovery from an error does not cause further diagnostics.
+    last_error_at: Option<Key>,
+
+    options: ParseOptions,
+
+    /// The number of nested productions being parsed, see [`ParseContext::nested`].
+    depth: u32
 }
 
 impl<'a, 'b> ParseContext<'a, 'b> {
     fn new(stream: &'a mut TokStream<'b>, ast_mem: &'a mut AstAllocator, source_unit: SourceUnitId,
         diagnostics: &'a mut Vec<AnyDiagnostic>) -> Self 
     {
-        Self { stream, ast_mem, source_unit, diagnostics }
+        Self { stream, ast_mem, source_unit, diagnostics, next_node_id: 0, events: None,
+            trace: None, open_nodes: 0, last_error_at: None, options: ParseOptions::default(),
+            depth: 0 }
+    }
+
+    /// Parses a production which may be nested within itself, such as a type or an expression,
+    /// one level deeper. If that exceeds [`ParseOptions::max_depth`], reports the nesting and
+    /// panics instead, so that the depth of recursion, and of the AST, is bounded.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.deepen()?;
+        let result = parse(self);
+        self.depth -= 1;
+        return result;
+    }
+
+    /// Enters one more level of nesting, reporting the nesting and panicking if that exceeds
+    /// [`ParseOptions::max_depth`]. The caller is responsible for restoring the depth.
+    fn deepen(&mut self) -> ParseResult<()> {
+        if self.depth >= self.options.max_depth {
+            self.stream.discard::<tok::class::Trivia>();
+            let at = self.stream.cursor.at();
+            self.last_error_at = Some(at);
+            if let Some(trace) = &mut self.trace {
+                trace.error(at, String::from("nesting too deep"));
+            }
+            let diagnostic = diagnostic::NestingTooDeep::new(self.source_unit, at,
+                self.options.max_depth);
+            self.diagnostics.push(AnyDiagnostic::NestingTooDeep(diagnostic));
+            return Err(ParsePanic);
+        }
+        self.depth += 1;
+        return Ok(());
+    }
+
+    /// Begins a node at the current position of the stream and returns its id.
+    /// Every node which is started must be finished with [`ParseContext::finish_node`], unless
+    /// parsing the node panics.
+    fn start_node(&mut self, kind: SyntaxKind) -> NodeId {
+        let id = NodeId(self.next_node_id);
+        self.next_node_id += 1;
+        self.open_nodes += 1;
+        let at = self.stream.cursor.at();
+        if let Some(events) = &mut self.events {
+            events.push(Event::Start { kind, at, forward_parent: None });
+        }
+        if let Some(trace) = &mut self.trace { trace.enter(kind, at); }
+        return id;
+    }
+
+    /// Returns a checkpoint at the current position of the stream, before which a node may be
+    /// started later with [`ParseContext::start_node_before`].
+    fn checkpoint(&mut self) -> Checkpoint {
+        self.stream.discard::<tok::class::Trivia>();
+        let event_idx = self.events.as_ref().map_or(0, |events| events.len());
+        let trace_idx = self.trace.as_ref().map_or(0, |trace| trace.len());
+        return Checkpoint { event_idx, trace_idx, at: self.stream.cursor.at() };
+    }
+
+    /// Begins a node at `checkpoint`, enclosing every node begun since, and returns its id. This
+    /// is for productions whose first child is parsed before the production is known, such as an
+    /// infix expression. `checkpoint` is moved to the new node, so that a node begun before it
+    /// later encloses the new node too.
+    ///
+    /// The event which begins the node is appended, and the first event since `checkpoint` is
+    /// linked to it as its forward parent, see [`Event::Start`].
+    fn start_node_before(&mut self, checkpoint: &mut Checkpoint, kind: SyntaxKind) -> NodeId {
+        let id = NodeId(self.next_node_id);
+        self.next_node_id += 1;
+        self.open_nodes += 1;
+        if let Some(events) = &mut self.events {
+            let event_idx = events.len();
+            if let Some(first) = events.get_mut(checkpoint.event_idx) {
+                let Event::Start { forward_parent, .. } = first else {
+                    panic!("node begun before a checkpoint which is not followed by a node");
+                };
+                *forward_parent = Some(event_idx);
+            }
+            events.push(Event::Start { kind, at: checkpoint.at, forward_parent: None });
+            checkpoint.event_idx = event_idx;
+        }
+        if let Some(trace) = &mut self.trace {
+            checkpoint.trace_idx = trace.enter_before(kind, checkpoint.at, checkpoint.trace_idx);
+        }
+        return id;
+    }
+
+    /// Begins and immediately finishes a node which stands in for a missing expression, type,
+    /// or statement, and returns its id. The caller is responsible for reporting a diagnostic.
+    fn placeholder(&mut self, kind: SyntaxKind) -> ast::ErrorNode {
+        self.stream.discard::<tok::class::Trivia>();
+        let at = self.stream.cursor.position();
+        let id = self.start_node(kind);
+        self.finish_node();
+        return ast::ErrorNode { id, at, last: None };
+    }
+
+    /// Returns the last token which is not trivia from `start` up to the current position of the
+    /// stream, if any.
+    fn last_tok_since(&self, start: Key) -> Option<TokRef<AnyTok>> {
+        let end = self.stream.cursor.at();
+        let mut cursor = self.stream.cursor;
+        cursor.seek(start);
+        let mut last: Option<TokRef<AnyTok>> = None;
+        while cursor.at() < end {
+            if cursor.r#match::<tok::class::Trivia>().is_none() { last = cursor.match_ref(); }
+            cursor.advance();
+        }
+        return last;
+    }
+
+    fn finish_node(&mut self) { self.exit_node(Outcome::Finished); }
+
+    fn exit_node(&mut self, outcome: Outcome) {
+        self.open_nodes -= 1;
+        let at = self.stream.cursor.at();
+        if let Some(events) = &mut self.events { events.push(Event::Finish(at)); }
+        if let Some(trace) = &mut self.trace { trace.exit(at, outcome); }
+    }
+
+    /// Changes the kind of the innermost open node. This is for productions which can only be
+    /// told apart after some of their children have been parsed.
+    fn retag_node(&mut self, kind: SyntaxKind) {
+        if let Some(trace) = &mut self.trace { trace.retag(kind); }
+        let Some(events) = &mut self.events else { return; };
+        let mut depth: u32 = 0;
+        for event in events.iter_mut().rev() {
+            match event {
+                Event::Finish(_) => depth += 1,
+                Event::Start { .. } if depth > 0 => depth -= 1,
+                Event::Start { kind: open_kind, .. } => {
+                    *open_kind = kind;
+                    return;
+                },
+                Event::Tombstone => {},
+            }
+        }
+    }
+
+    /// Finishes the nodes which were left open by a `ParsePanic`, until only `open_nodes`
+    /// remain open.
+    fn abandon_nodes(&mut self, open_nodes: u32) {
+        while self.open_nodes > open_nodes { self.exit_node(Outcome::Abandoned); }
+    }
+
+    /// Discards all tokens up to but not including the next occurrence of `C`, placing them
+    /// in an error node.
+    fn sync<C: TokClass>(&mut self) {
+        let start = self.stream.cursor.at();
+        self.stream.sync::<C>();
+        let end = self.stream.cursor.at();
+        if start == end { return; }
+        self.skipped(start..end);
+    }
+
+    /// Records that the tokens in `range` were skipped while recovering from a syntax error.
+    fn skipped(&mut self, range: Range<Key>) {
+        if let Some(events) = &mut self.events {
+            events.push(Event::Start { kind: SyntaxKind::Error, at: range.start,
+                forward_parent: None });
+            events.push(Event::Finish(range.end));
+        }
+        if let Some(trace) = &mut self.trace { trace.skip(range); }
     }
 
     fn expect_ref<C: TokClass>(&mut self) -> ParseResult<TokRef<C>> {    
         if let Some(tokref) = self.stream.consume_ref::<C>() { return Ok(tokref); };
-        let diagnostic = diagnostic::MissingTok::new(self.source_unit, self.stream.cursor.at());
-        self.diagnostics.push(AnyDiagnostic::MissingTok(diagnostic));
+        self.report_missing(C::MEMBERS);
         return Err(ParsePanic);
     }
+
+    /// Consumes the next token if it is in class `C`. Otherwise, reports it missing and continues
+    /// as though it had been inserted, returning `None`.
+    fn expect_or_insert<C: TokClass>(&mut self) -> Option<TokRef<C>> {
+        let tokref = self.stream.consume_ref::<C>();
+        if tokref.is_none() { self.report_missing(C::MEMBERS); }
+        return tokref;
+    }
+
+    /// Consumes the delimiter `C` which closes the group opened at `open`.
+    ///
+    /// If the group has a closing delimiter further ahead, the tokens before it could not be
+    /// parsed. They are reported once and skipped. If the group is never closed, which was
+    /// reported when pairing delimiters, the delimiter is treated as inserted without reporting
+    /// it again.
+    fn expect_close<C: TokClass>(&mut self, open: Key) -> Option<TokRef<C>> {
+        if let Some(tokref) = self.stream.consume_ref::<C>() { return Some(tokref); }
+        if self.stream.trees.is_unclosed(open) { return None; }
+        let Some(close) = self.stream.trees.close_of(open) else {
+            return self.expect_or_insert::<C>();
+        };
+        self.report_missing(C::MEMBERS);
+        let start = self.stream.cursor.at();
+        self.stream.cursor.seek(close);
+        self.skipped(start..close);
+        return self.stream.consume_ref::<C>();
+    }
+
+    /// Consumes the delimiter `C` which closes the group opened at `open`, see
+    /// [`ParseContext::expect_close`]. If the opening delimiter is missing, which was reported
+    /// already, the closing delimiter is expected on its own.
+    fn expect_close_of<C: TokClass>(&mut self, open: Option<Key>) -> Option<TokRef<C>> {
+        let Some(open) = open else { return self.expect_or_insert::<C>(); };
+        return self.expect_close::<C>(open);
+    }
+
+    /// Reports that one of `expected` was missing before the next token, unless a syntax error
+    /// was already reported there.
+    fn report_missing(&mut self, expected: &'static [tok::tok::TokKind]) {
+        self.stream.discard::<tok::class::Trivia>();
+        let at = self.stream.cursor.at();
+        if self.last_error_at == Some(at) { return; }
+        self.last_error_at = Some(at);
+        if let Some(trace) = &mut self.trace {
+            trace.error(at, format!("expected {}", tok::class::describe_kinds(expected)));
+        }
+        let diagnostic = diagnostic::MissingTok::new(self.source_unit, at, expected);
+        self.diagnostics.push(AnyDiagnostic::MissingTok(diagnostic));
+    }
 }
 
+/// A position in the stream of events and in the trace, see [`ParseContext::checkpoint`].
+#[derive(Clone, Copy)]
+struct Checkpoint { event_idx: usize, trace_idx: usize, at: Key }
+
 // -- Parser -------------------------------------------------------------------------------------
 
+#[derive(Clone, Copy, Debug)]
+pub struct ParseOptions {
+    /// The maximum depth to which types and expressions may be nested within one another. Deeper
+    /// nesting is reported as [`NestingTooDeep`] rather than overflowing the stack.
+    ///
+    /// [`NestingTooDeep`]: crate::diagnostic::NestingTooDeep
+    pub max_depth: u32
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self { return Self { max_depth: 256 }; }
+}
+
 pub fn parse(tokbuf: &TokBuf, source_unit: SourceUnitId, diagnostics: &mut Vec<AnyDiagnostic>)  
 -> Ast 
 {
-    let mut stream = TokStream::new(tokbuf);
-    let mut mem = AstAllocator::new(calc_ast_size_upperbound(tokbuf.len()));
-    let root = parse_root(&mut ParseContext::new(&mut stream, &mut mem, source_unit, diagnostics));
+    return parse_with_options(tokbuf, source_unit, ParseOptions::default(), diagnostics);
+}
+
+pub fn parse_with_options(tokbuf: &TokBuf, source_unit: SourceUnitId, options: ParseOptions,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> Ast
+{
+    return parse_with_events(tokbuf, source_unit, options, diagnostics, None, None).0;
+}
+
+/// Parses `tokbuf` while recording the productions which the parser enters and exits, see
+/// [`trace`](crate::parse::trace).
+pub fn parse_traced(tokbuf: &TokBuf, source_unit: SourceUnitId,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> (Ast, Trace)
+{
+    let (ast, _, trace) = parse_with_events(tokbuf, source_unit, ParseOptions::default(),
+        diagnostics, None, Some(Trace::default()));
+    return (ast, trace.unwrap());
+}
+
+/// Parses `tokbuf` into a lossless concrete syntax tree, see [`cst`].
+pub fn parse_cst(tokbuf: &TokBuf, source_unit: SourceUnitId,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> Cst
+{
+    let (_, events, _) = parse_with_events(tokbuf, source_unit, ParseOptions::default(),
+        diagnostics, Some(Vec::new()), None);
+    return cst::build(tokbuf, events.unwrap());
+}
+
+fn parse_with_events(tokbuf: &TokBuf, source_unit: SourceUnitId, options: ParseOptions,
+    diagnostics: &mut Vec<AnyDiagnostic>, events: Option<Vec<Event>>, trace: Option<Trace>)
+    -> (Ast, Option<Vec<Event>>, Option<Trace>)
+{
+    let trees = TokTrees::build(tokbuf, source_unit, diagnostics);
+    let mut stream = TokStream::new(TokCursor::new(tokbuf), trees);
+    let mut mem = AstAllocator::new();
+    let mut ctx = ParseContext::new(&mut stream, &mut mem, source_unit, diagnostics);
+    ctx.events = events;
+    ctx.trace = trace;
+    ctx.options = options;
+    let mut enclosed_items: Vec<Key> = Vec::new();
+    let root = parse_root(&mut ctx, &mut enclosed_items);
+    let node_count = ctx.next_node_id;
+    let events = ctx.events.take();
+    let trace = ctx.trace.take();
     mem.shrink_to_fit();
-    return Ast { mem, root };
+    let ast = Ast { mem, root, node_count, parsed_node_count: node_count, enclosed_items };
+    return (ast, events, trace);
 }
 
-fn parse_root(ctx: &mut ParseContext) -> ast::Root {
-    let mut ll_head: Option<AstRef<ast::TopLevelItemNode>> = None;
-    let mut next: &mut Option<AstRef<ast::TopLevelItemNode>> = &mut ll_head;
-    
-    while ctx.stream.cursor.has_next() {
+fn parse_root(ctx: &mut ParseContext, enclosed_items: &mut Vec<Key>) -> ast::Root {
+    let mut items: LLBuilder<ast::AnyTopLevelItem> = LLBuilder::new();
+    parse_items(ctx, &mut items, enclosed_items, |_| false);
+    return ast::Root { ll_head: items.head() };
+}
+
+/// Parses top-level items until the end of the stream, or until `stop` returns `true` for the
+/// key of the token at which the next item would begin. The keys of the declarators of the items
+/// which begin within a group are appended to `enclosed_items`.
+fn parse_items(ctx: &mut ParseContext, items: &mut LLBuilder<ast::AnyTopLevelItem>,
+    enclosed_items: &mut Vec<Key>, mut stop: impl FnMut(Key) -> bool)
+{
+    loop {
+        // Trailing trivia does not begin another item.
+        ctx.stream.discard::<tok::class::Trivia>();
+        if !ctx.stream.cursor.has_next() { break; }
+        if stop(ctx.stream.cursor.at()) { break; }
+
         /// A source unit is a list of top level items.
         /// Every top level item begins with an `ItemDeclarator`.
         let Some(declarator) = ctx.stream.peek::<tok::class::ItemDeclarator>() else {
-            let diagnostic = diagnostic::MissingTok::new(ctx.source_unit, ctx.stream.cursor.at());
-            ctx.diagnostics.push(AnyDiagnostic::MissingTok(diagnostic));
-            ctx.stream.sync::<tok::class::ItemDeclarator>();
+            ctx.report_missing(tok::class::ItemDeclarator::MEMBERS);
+            ctx.sync::<tok::class::ItemDeclarator>();
             continue;
         };
+        let at = ctx.stream.cursor.at();
+        if ctx.stream.trees.is_enclosed(at) { enclosed_items.push(at); }
+        let open_nodes = ctx.open_nodes;
         let Ok(tl_item) = parse_tl_item(ctx, declarator) else {
             // The panic occurred within parse_tl_item. It was reported there.
-            ctx.stream.sync::<tok::class::ItemDeclarator>();
+            ctx.abandon_nodes(open_nodes);
+            ctx.sync::<tok::class::ItemDeclarator>();
             continue;
         };
-        extend_ll(ctx.ast_mem, &mut next, tl_item);
+        items.push(ctx.ast_mem, tl_item);
     }
-
-    return ast::Root { ll_head };
 }
 
 /// Parses the next top-level item (proc, struct, namespace, etc.).
@@ -150,86 +463,477 @@ fn parse_tl_item(ctx: &mut ParseContext, declarator: tok::class::ItemDeclarator)
     use ast::AnyTopLevelItem;
     return Ok(match declarator {
         Proc => AnyTopLevelItem::Proc(parse_proc_def(ctx)?),
-        Struct => todo!(),
-        Enum => todo!(),
-        LineComment => AnyTopLevelItem::LineComment(parse_line_comment(ctx)?),
+        Struct => AnyTopLevelItem::Struct(parse_struct_def(ctx)?),
+        Import => AnyTopLevelItem::Import(parse_import(ctx)?),
+        Enum => return Err(skip_unsupported_item(ctx)),
     });
 }
 
+/// Reports an item which the parser recognizes but cannot parse yet, and skips it through to
+/// the next item declarator.
+fn skip_unsupported_item(ctx: &mut ParseContext) -> ParsePanic {
+    let at = ctx.stream.cursor.at();
+    ctx.last_error_at = Some(at);
+    if let Some(trace) = &mut ctx.trace { trace.error(at, String::from("unsupported item")); }
+    let diagnostic = diagnostic::UnsupportedItem::new(ctx.source_unit, at);
+    ctx.diagnostics.push(AnyDiagnostic::UnsupportedItem(diagnostic));
+    // The declarator itself would stop the sync, so it is skipped first.
+    ctx.stream.cursor.advance();
+    ctx.stream.sync::<tok::class::ItemDeclarator>();
+    let end = ctx.stream.cursor.at();
+    ctx.skipped(at..end);
+    return ParsePanic;
+}
+
 fn parse_proc_def(ctx: &mut ParseContext) -> ParseResult<ast::ProcDefinition> {
+    let id = ctx.start_node(SyntaxKind::ProcDefinition);
     let proc_keyword = ctx.stream.assert_ref::<delims::Proc>();
     let ident = ctx.expect_ref::<tok::class::Ident>()?;
+    let generics = parse_optional_generic_parameters(ctx)?;
     let parameters = parse_parameters(ctx)?;
-    let return_type_separator = ctx.expect_ref::<delims::Colon>()?;
+    let return_type_separator = ctx.expect_or_insert::<delims::Colon>();
     let return_type = parse_type(ctx)?;
     let body = parse_imperative_block(ctx)?;
-    return Ok(ast::ProcDefinition { proc_keyword, ident, parameters, return_type_separator,
-        return_type, body });
+    ctx.finish_node();
+    return Ok(ast::ProcDefinition { id, proc_keyword, ident, generics, parameters,
+        return_type_separator, return_type, body });
 }
 
-fn parse_parameters(ctx: &mut ParseContext) -> ParseResult<ast::Parameters> {
-    let open_paren = ctx.expect_ref::<delims::OpenParen>()?;
-    let mut first: Option<AstRef<ast::ParameterNode>> = None;
-    let mut ll_next = &mut first;
+fn parse_struct_def(ctx: &mut ParseContext) -> ParseResult<ast::StructDefinition> {
+    let id = ctx.start_node(SyntaxKind::StructDefinition);
+    let struct_keyword = ctx.stream.assert_ref::<delims::Struct>();
+    let ident = ctx.expect_ref::<tok::class::Ident>()?;
+    let generics = parse_optional_generic_parameters(ctx)?;
+    let open_curly = ctx.expect_or_insert::<delims::OpenCurly>();
+    let mut fields: LLBuilder<ast::Field> = LLBuilder::new();
     loop {
         if !ctx.stream.cursor.has_next() { break; }
-        if ctx.stream.peek::<delims::CloseParen>().is_some() { break; }
+        if ctx.stream.peek::<delims::CloseCurly>().is_some() { break; }
+        let field_id = ctx.start_node(SyntaxKind::Field);
         let ident = ctx.expect_ref::<tok::class::Ident>()?;
-        let colon = ctx.expect_ref::<delims::Colon>()?;
+        let colon = ctx.expect_or_insert::<delims::Colon>();
         let ty = parse_type(ctx)?;
         let comma = ctx.stream.consume_ref::<delims::Comma>();
-        extend_ll(ctx.ast_mem, &mut ll_next, ast::Parameter { ident, colon, ty, comma });
+        ctx.finish_node();
+        fields.push(ctx.ast_mem, ast::Field { id: field_id, ident, colon, ty, comma });
         if comma.is_none() { break; }
     }
-    let close_paren = ctx.expect_ref::<delims::CloseParen>()?;
-    return Ok(ast::Parameters { open_paren, close_paren, first });
+    let close_curly = ctx.expect_close_of::<delims::CloseCurly>(open_curly.map(TokRef::key));
+    ctx.finish_node();
+    return Ok(ast::StructDefinition { id, struct_keyword, ident, generics, open_curly,
+        first: fields.head(), close_curly });
 }
 
-fn parse_type(ctx: &mut ParseContext) -> ParseResult<ast::Type> {
+fn parse_import(ctx: &mut ParseContext) -> ParseResult<ast::ImportDeclaration> {
+    let id = ctx.start_node(SyntaxKind::ImportDeclaration);
+    let import_keyword = ctx.stream.assert_ref::<delims::Import>();
+    let path = ctx.expect_ref::<tok::class::StringLiteral>()?;
+    let semicolon = ctx.expect_or_insert::<delims::Semicolon>();
+    ctx.finish_node();
+    return Ok(ast::ImportDeclaration { id, import_keyword, path, semicolon });
+}
+
+/// Parses the generic parameter list following the name of an item, if there is one.
+fn parse_optional_generic_parameters(ctx: &mut ParseContext)
+-> ParseResult<Option<ast::GenericParameters>>
+{
+    if ctx.stream.peek::<delims::LessThan>().is_none() { return Ok(None); }
+    let id = ctx.start_node(SyntaxKind::GenericParameters);
+    let open_angle = ctx.stream.assert_ref::<delims::LessThan>();
+    let mut params: LLBuilder<ast::GenericParameter> = LLBuilder::new();
+    loop {
+        if !ctx.stream.cursor.has_next() { break; }
+        if ctx.stream.peek::<delims::GreaterThan>().is_some() { break; }
+        let param_id = ctx.start_node(SyntaxKind::GenericParameter);
+        let ident = ctx.expect_ref::<tok::class::Ident>()?;
+        let mut bound: Option<ast::GenericBound> = None;
+        if ctx.stream.peek::<delims::Colon>().is_some() {
+            let bound_id = ctx.start_node(SyntaxKind::GenericBound);
+            let colon = ctx.stream.assert_ref::<delims::Colon>();
+            let ty = parse_type(ctx)?;
+            ctx.finish_node();
+            bound = Some(ast::GenericBound { id: bound_id, colon, ty });
+        }
+        let comma = ctx.stream.consume_ref::<delims::Comma>();
+        ctx.finish_node();
+        params.push(ctx.ast_mem, ast::GenericParameter { id: param_id, ident, bound, comma });
+        if comma.is_none() { break; }
+    }
+    let close_angle = ctx.expect_close::<delims::GreaterThan>(open_angle.key());
+    ctx.finish_node();
+    return Ok(Some(ast::GenericParameters { id, open_angle, first: params.head(), close_angle }));
+}
+
+fn parse_parameters(ctx: &mut ParseContext) -> ParseResult<ast::Parameters> {
+    let id = ctx.start_node(SyntaxKind::Parameters);
+    let open_paren = ctx.expect_ref::<delims::OpenParen>()?;
+    let mut params: LLBuilder<ast::Parameter> = LLBuilder::new();
+    loop {
+        if !ctx.stream.cursor.has_next() { break; }
+        if ctx.stream.peek::<delims::CloseParen>().is_some() { break; }
+        let open_nodes = ctx.open_nodes;
+        let param_id = ctx.start_node(SyntaxKind::Parameter);
+        let Ok(param) = parse_parameter(ctx, param_id) else {
+            // The malformed parameter is dropped. Parsing resumes at the next parameter.
+            ctx.abandon_nodes(open_nodes + 1);
+            ctx.retag_node(SyntaxKind::Error);
+            ctx.sync::<tok::class::ParameterSync>();
+            let comma = ctx.stream.consume_ref::<delims::Comma>();
+            ctx.finish_node();
+            if comma.is_none() { break; }
+            continue;
+        };
+        ctx.finish_node();
+        params.push(ctx.ast_mem, param);
+        if param.comma.is_none() { break; }
+    }
+    let close_paren = ctx.expect_close::<delims::CloseParen>(open_paren.key());
+    ctx.finish_node();
+    return Ok(ast::Parameters { id, open_paren, close_paren, first: params.head() });
+}
+
+fn parse_parameter(ctx: &mut ParseContext, id: NodeId) -> ParseResult<ast::Parameter> {
     let ident = ctx.expect_ref::<tok::class::Ident>()?;
+    let colon = ctx.expect_or_insert::<delims::Colon>();
+    let ty = parse_type(ctx)?;
+    let comma = ctx.stream.consume_ref::<delims::Comma>();
+    return Ok(ast::Parameter { id, ident, colon, ty, comma });
+}
+
+fn parse_type(ctx: &mut ParseContext) -> ParseResult<ast::Type> {
+    return ctx.nested(dispatch_type);
+}
+
+/// Parses the next type, dispatching on the token which introduces it.
+fn dispatch_type(ctx: &mut ParseContext) -> ParseResult<ast::Type> {
+    use tok::class::TypeIntroducer;
+    let Some(introducer) = ctx.stream.peek::<TypeIntroducer>() else {
+        ctx.report_missing(TypeIntroducer::MEMBERS);
+        return Ok(ast::Type::Error(ctx.placeholder(SyntaxKind::ErrorType)));
+    };
+    return Ok(match introducer {
+        TypeIntroducer::Ident => ast::Type::NamedType(parse_named_type(ctx)?),
+        TypeIntroducer::Ampersand => ast::Type::Reference(parse_reference_type(ctx)?),
+        TypeIntroducer::OpenSquare => parse_array_or_slice_type(ctx)?,
+        TypeIntroducer::OpenParen => ast::Type::Tuple(parse_tuple_type(ctx)?),
+        TypeIntroducer::Proc => ast::Type::Proc(parse_proc_type(ctx)?),
+    });
+}
+
+fn parse_named_type(ctx: &mut ParseContext) -> ParseResult<ast::NamedType> {
+    let id = ctx.start_node(SyntaxKind::NamedType);
+    let ident = ctx.stream.assert_ref::<tok::class::Ident>();
     let mut arguments: Option<ast::TypeArguments> = None;
     if ctx.stream.peek::<delims::LessThan>().is_some() {
         arguments = Some(parse_type_arguments(ctx)?);
     }
-    return Ok(ast::Type::NamedType(ast::NamedType { ident, arguments }));
+    ctx.finish_node();
+    return Ok(ast::NamedType { id, ident, arguments });
 }
 
 fn parse_type_arguments(ctx: &mut ParseContext) -> ParseResult<ast::TypeArguments> {
+    let id = ctx.start_node(SyntaxKind::TypeArguments);
     let open_angle = ctx.stream.assert_ref::<delims::LessThan>();
-    let mut first: Option<AstRef<ast::TypeArgumentNode>> = None;
-    let mut ll_next = &mut first;
+    let first = parse_type_list::<delims::GreaterThan>(ctx)?;
+    let close_angle = ctx.expect_close::<delims::GreaterThan>(open_angle.key());
+    ctx.finish_node();
+    return Ok(ast::TypeArguments { id, open_angle, first, close_angle });
+}
+
+/// Parses a comma-separated list of types up to, but not including, the closing delimiter `C`.
+fn parse_type_list<C: TokClass>(ctx: &mut ParseContext)
+-> ParseResult<Option<AstRef<ast::TypeArgumentNode>>>
+{
+    let mut args: LLBuilder<ast::TypeArgument> = LLBuilder::new();
     loop {
         if !ctx.stream.cursor.has_next() { break; }
-        if ctx.stream.peek::<delims::GreaterThan>().is_some() { break; }
+        if ctx.stream.peek::<C>().is_some() { break; }
+        let arg_id = ctx.start_node(SyntaxKind::TypeArgument);
         let ty = parse_type(ctx)?;
         let comma = ctx.stream.consume_ref::<delims::Comma>();
-        extend_ll(ctx.ast_mem, &mut ll_next, ast::TypeArgument { ty, comma });
+        ctx.finish_node();
+        args.push(ctx.ast_mem, ast::TypeArgument { id: arg_id, ty, comma });
         if comma.is_none() { break; }
     }
-    let close_angle = ctx.expect_ref::<delims::GreaterThan>()?;
-    return Ok(ast::TypeArguments { open_angle, first, close_angle });
+    return Ok(args.head());
+}
+
+fn parse_reference_type(ctx: &mut ParseContext) -> ParseResult<ast::ReferenceType> {
+    let id = ctx.start_node(SyntaxKind::ReferenceType);
+    let ampersand = ctx.stream.assert_ref::<delims::Ampersand>();
+    let mut_keyword = ctx.stream.consume_ref::<delims::Mut>();
+    let referent = parse_type(ctx)?;
+    ctx.finish_node();
+    let referent = ctx.ast_mem.bump(referent);
+    return Ok(ast::ReferenceType { id, ampersand, mut_keyword, referent });
+}
+
+/// Parses `[T]` or `[T; N]`. The two are distinguished by the `;` following the element type.
+fn parse_array_or_slice_type(ctx: &mut ParseContext) -> ParseResult<ast::Type> {
+    let id = ctx.start_node(SyntaxKind::SliceType);
+    let open_square = ctx.stream.assert_ref::<delims::OpenSquare>();
+    let element = parse_type(ctx)?;
+    let element = ctx.ast_mem.bump(element);
+    let Some(semicolon) = ctx.stream.consume_ref::<delims::Semicolon>() else {
+        let close_square = ctx.expect_close::<delims::CloseSquare>(open_square.key());
+        ctx.finish_node();
+        return Ok(ast::Type::Slice(ast::SliceType { id, open_square, element, close_square }));
+    };
+    ctx.retag_node(SyntaxKind::ArrayType);
+    let length = ctx.expect_ref::<tok::class::IntLiteral>()?;
+    let close_square = ctx.expect_close::<delims::CloseSquare>(open_square.key());
+    ctx.finish_node();
+    return Ok(ast::Type::Array(ast::ArrayType { id, open_square, element, semicolon, length,
+        close_square }));
+}
+
+fn parse_tuple_type(ctx: &mut ParseContext) -> ParseResult<ast::TupleType> {
+    let id = ctx.start_node(SyntaxKind::TupleType);
+    let open_paren = ctx.stream.assert_ref::<delims::OpenParen>();
+    let first = parse_type_list::<delims::CloseParen>(ctx)?;
+    let close_paren = ctx.expect_close::<delims::CloseParen>(open_paren.key());
+    ctx.finish_node();
+    return Ok(ast::TupleType { id, open_paren, first, close_paren });
 }
 
-fn parse_line_comment(ctx: &mut ParseContext) -> ParseResult<ast::LineComment> {
-    let tok = ctx.stream.assert_ref::<tok::class::LineComment>();
-    return Ok(ast::LineComment { tok })
+fn parse_proc_type(ctx: &mut ParseContext) -> ParseResult<ast::ProcType> {
+    let id = ctx.start_node(SyntaxKind::ProcType);
+    let proc_keyword = ctx.stream.assert_ref::<delims::Proc>();
+    let open_paren = ctx.expect_ref::<delims::OpenParen>()?;
+    let first = parse_type_list::<delims::CloseParen>(ctx)?;
+    let close_paren = ctx.expect_close::<delims::CloseParen>(open_paren.key());
+    let return_type_separator = ctx.expect_ref::<delims::Colon>()?;
+    let return_type = parse_type(ctx)?;
+    ctx.finish_node();
+    let return_type = ctx.ast_mem.bump(return_type);
+    return Ok(ast::ProcType { id, proc_keyword, open_paren, first, close_paren,
+        return_type_separator, return_type });
 }
 
 fn parse_imperative_block(ctx: &mut ParseContext) -> ParseResult<ast::ImperativeBlock> {
-    let open_curly = ctx.expect_ref::<delims::OpenCurly>()?;
-    let mut first: Option<AstRef<ast::StatementNode>> = None;
-    let mut ll_next = &mut first;
+    let id = ctx.start_node(SyntaxKind::ImperativeBlock);
+    let open_curly = ctx.expect_or_insert::<delims::OpenCurly>();
+    let mut statements: LLBuilder<ast::AnyStatement> = LLBuilder::new();
     loop {
         if !ctx.stream.cursor.has_next() { break; }
         if ctx.stream.peek::<delims::CloseCurly>().is_some() { break; }
-        // TODO: parse statement
+        // A declarator cannot begin a statement. Most likely, the block is missing its `}`.
+        if ctx.stream.peek::<tok::class::ItemDeclarator>().is_some() { break; }
+        let statement = parse_statement(ctx);
+        statements.push(ctx.ast_mem, statement);
+    }
+    let close_curly = ctx.expect_close_of::<delims::CloseCurly>(open_curly.map(TokRef::key));
+    ctx.finish_node();
+    return Ok(ast::ImperativeBlock { id, open_curly, first: statements.head(), close_curly });
+}
+
+/// Parses the next statement. A statement which cannot be parsed is replaced by an error node,
+/// and parsing resumes after the next `;`, or before the next `}` or `let`.
+fn parse_statement(ctx: &mut ParseContext) -> ast::AnyStatement {
+    let open_nodes = ctx.open_nodes;
+    ctx.stream.discard::<tok::class::Trivia>();
+    let at = ctx.stream.cursor.position();
+    // The kind of the statement is unknown until its first token has been examined.
+    let id = ctx.start_node(SyntaxKind::ErrorStatement);
+    let result = match ctx.stream.peek::<delims::Let>() {
+        Some(_) => {
+            ctx.retag_node(SyntaxKind::LetStatement);
+            parse_let_statement(ctx, id).map(ast::AnyStatement::Let)
+        },
+        None => {
+            ctx.retag_node(SyntaxKind::ExprStatement);
+            parse_expr_statement(ctx, id).map(ast::AnyStatement::Expr)
+        }
+    };
+    let statement = result.unwrap_or_else(|ParsePanic| {
+        ctx.abandon_nodes(open_nodes + 1);
+        ctx.retag_node(SyntaxKind::ErrorStatement);
+        ctx.sync::<tok::class::StatementSync>();
+        ctx.stream.consume_ref::<delims::Semicolon>();
+        ast::AnyStatement::Error(ast::ErrorNode { id, at, last: ctx.last_tok_since(at.key()) })
+    });
+    ctx.finish_node();
+    return statement;
+}
+
+fn parse_let_statement(ctx: &mut ParseContext, id: NodeId) -> ParseResult<ast::LetStatement> {
+    let let_keyword = ctx.stream.assert_ref::<delims::Let>();
+    let ident = ctx.expect_ref::<tok::class::Ident>()?;
+    let mut annotation: Option<ast::TypeAnnotation> = None;
+    if let Some(colon) = ctx.stream.consume_ref::<delims::Colon>() {
+        annotation = Some(ast::TypeAnnotation { colon, ty: parse_type(ctx)? });
+    }
+    let eq = ctx.expect_ref::<delims::Eq>()?;
+    let value = parse_expr(ctx)?;
+    let semicolon = expect_terminator(ctx);
+    return Ok(ast::LetStatement { id, let_keyword, ident, annotation, eq, value, semicolon });
+}
+
+fn parse_expr_statement(ctx: &mut ParseContext, id: NodeId) -> ParseResult<ast::ExprStatement> {
+    let expr = parse_expr(ctx)?;
+    let semicolon = expect_terminator(ctx);
+    return Ok(ast::ExprStatement { id, expr, semicolon });
+}
+
+/// Consumes the `;` which terminates a statement. If it is missing, skips ahead to the next
+/// point at which a statement could end, consuming the `;` there, if any.
+fn expect_terminator(ctx: &mut ParseContext) -> Option<TokRef<delims::Semicolon>> {
+    let semicolon = ctx.stream.consume_ref::<delims::Semicolon>();
+    if semicolon.is_some() { return semicolon; }
+    ctx.report_missing(delims::Semicolon::MEMBERS);
+    ctx.sync::<tok::class::StatementSync>();
+    return ctx.stream.consume_ref::<delims::Semicolon>();
+}
+
+/// Returns the binding power of an infix operator, and whether it is right-associative.
+fn binding_power(operator: tok::class::BinaryOperator) -> (u8, bool) {
+    use tok::class::BinaryOperator::*;
+    return match operator {
+        Eq => (1, true),
+        LessThan | LessThanEq | GreaterThan | GreaterThanEq | EqEq | NotEq => (2, false),
+    };
+}
+
+fn parse_expr(ctx: &mut ParseContext) -> ParseResult<ast::ExprNode> {
+    return parse_expr_with_binding_power(ctx, 0);
+}
+
+/// Parses an expression whose infix operators all bind more tightly than `min_power`.
+fn parse_expr_with_binding_power(ctx: &mut ParseContext, min_power: u8)
+-> ParseResult<ast::ExprNode>
+{
+    return ctx.nested(|ctx| parse_infix_expr(ctx, min_power));
+}
+
+fn parse_infix_expr(ctx: &mut ParseContext, min_power: u8) -> ParseResult<ast::ExprNode> {
+    let depth = ctx.depth;
+    let result = parse_infix_chain(ctx, min_power);
+    ctx.depth = depth;
+    return result;
+}
+
+/// Parses an operand followed by any number of operators of at least `min_power` and their
+/// right operands. The operations are folded into the left operand one after another, each
+/// nesting the expression one level deeper, which counts towards [`ParseOptions::max_depth`].
+fn parse_infix_chain(ctx: &mut ParseContext, min_power: u8) -> ParseResult<ast::ExprNode> {
+    let mut checkpoint = ctx.checkpoint();
+    let mut left = parse_primary_expr(ctx);
+    while let Some(operator) = ctx.stream.peek::<tok::class::BinaryOperator>() {
+        let (power, right_assoc) = binding_power(operator);
+        if power <= min_power { break; }
+        ctx.deepen()?;
+        let id = ctx.start_node_before(&mut checkpoint, SyntaxKind::InfixExpr);
+        let operator = ctx.stream.assert_ref::<tok::class::BinaryOperator>();
+        let right_power = if right_assoc { power - 1 } else { power };
+        let right = parse_expr_with_binding_power(ctx, right_power)?;
+        ctx.finish_node();
+        let left_operand = ctx.ast_mem.bump(left);
+        let right_operand = ctx.ast_mem.bump(right);
+        left = ast::ExprNode::Infix(ast::InfixExpr { id, left_operand, operator, right_operand });
+    }
+    return Ok(left);
+}
+
+fn parse_primary_expr(ctx: &mut ParseContext) -> ast::ExprNode {
+    use tok::class::ExprIntroducer;
+    let Some(introducer) = ctx.stream.peek::<ExprIntroducer>() else {
+        ctx.report_missing(ExprIntroducer::MEMBERS);
+        return ast::ExprNode::Error(ctx.placeholder(SyntaxKind::ErrorExpr));
+    };
+    return match introducer {
+        ExprIntroducer::Ident => {
+            let id = ctx.start_node(SyntaxKind::IdentExpr);
+            let ident = ctx.stream.assert_ref::<tok::class::Ident>();
+            ctx.finish_node();
+            ast::ExprNode::Ident(ast::IdentExpr { id, ident })
+        },
+        ExprIntroducer::StrLiteral | ExprIntroducer::DecIntLiteral => {
+            let id = ctx.start_node(SyntaxKind::LiteralExpr);
+            let tok = ctx.stream.assert_ref::<tok::class::Literal>();
+            ctx.finish_node();
+            ast::ExprNode::Literal(ast::LiteralExpr { id, tok })
+        },
+    };
+}
+
+// -- Incremental Reparsing ----------------------------------------------------------------------
+
+/// Parses top-level items into `ast`, beginning at `start`, until the next item would begin at
+/// `resume_at`, or would begin past it, or the buffer ends. `trees` must pair the delimiters from
+/// `start` up to `resume_at`, or through the end of the buffer.
+///
+/// Returns the items, the keys of the declarators of those which begin within a group, and
+/// whether parsing stopped exactly at `resume_at`, see [`incremental`].
+///
+/// [`incremental`]: crate::parse::incremental
+pub(super) fn parse_items_until(ast: &mut Ast, tokbuf: &TokBuf, start: Key, trees: TokTrees,
+    resume_at: Option<Key>, source_unit: SourceUnitId, diagnostics: &mut Vec<AnyDiagnostic>)
+-> (LLBuilder<ast::AnyTopLevelItem>, Vec<Key>, bool)
+{
+    let end = tokbuf.key_range().end;
+    let mut stream = TokStream::new(TokCursor::with_range(tokbuf, start..end), trees);
+    let mut ctx = ParseContext::new(&mut stream, &mut ast.mem, source_unit, diagnostics);
+    ctx.next_node_id = ast.node_count;
+    let mut items: LLBuilder<ast::AnyTopLevelItem> = LLBuilder::new();
+    let mut enclosed_items: Vec<Key> = Vec::new();
+    parse_items(&mut ctx, &mut items, &mut enclosed_items, |at| {
+        // Past `resume_at`, the delimiters are not paired, so parsing stops there too.
+        return resume_at.is_some_and(|resume_at| at >= resume_at);
+    });
+    let resumed = resume_at.is_some_and(|resume_at| ctx.stream.cursor.at() == resume_at);
+    ast.node_count = ctx.next_node_id;
+    return (items, enclosed_items, resumed);
+}
+
+// -- Fragments ----------------------------------------------------------------------------------
+
+/// Parses the tokens in `range` of `tokbuf` with `production`, see [`fragment`].
+///
+/// [`fragment`]: crate::parse::fragment
+pub(super) fn parse_fragment<T>(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId,
+    production: fn(&mut ParseContext) -> T) -> Fragment<T>
+{
+    let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+    let trees = TokTrees::build_range(tokbuf, range.clone(), source_unit, &mut diagnostics);
+    let mut stream = TokStream::new(TokCursor::with_range(tokbuf, range.clone()), trees);
+    let mut mem = AstAllocator::new();
+    let mut ctx = ParseContext::new(&mut stream, &mut mem, source_unit, &mut diagnostics);
+    let node = production(&mut ctx);
+    let node_count = ctx.next_node_id;
+    stream.discard::<tok::class::Trivia>();
+    let end = stream.cursor.at();
+    let mut cursor = TokCursor::with_range(tokbuf, range.start..end);
+    let mut consumed: usize = 0;
+    while cursor.has_next() {
+        cursor.advance();
+        consumed += 1;
     }
-    let close_curly = ctx.expect_ref::<delims::CloseCurly>()?;
-    return Ok(ast::ImperativeBlock { open_curly, first, close_curly });
+    mem.shrink_to_fit();
+    let ast = Ast { mem, root: ast::Root { ll_head: None }, node_count,
+        parsed_node_count: node_count, enclosed_items: Vec::new() };
+    return Fragment { ast, node, diagnostics, consumed, end };
+}
+
+pub(super) fn parse_expr_fragment(ctx: &mut ParseContext) -> ast::ExprNode {
+    return parse_expr(ctx)
+        .unwrap_or_else(|ParsePanic| ast::ExprNode::Error(ctx.placeholder(SyntaxKind::ErrorExpr)));
+}
+
+pub(super) fn parse_type_fragment(ctx: &mut ParseContext) -> ast::Type {
+    return parse_type(ctx)
+        .unwrap_or_else(|ParsePanic| ast::Type::Error(ctx.placeholder(SyntaxKind::ErrorType)));
+}
+
+pub(super) fn parse_statement_fragment(ctx: &mut ParseContext) -> ast::AnyStatement {
+    return parse_statement(ctx);
 }
 
-fn parse_statement(ctx: &mut ParseContext) -> ParseResult<ast::AnyStatement> {
-    todo!()
+pub(super) fn parse_item_fragment(ctx: &mut ParseContext) -> Option<ast::AnyTopLevelItem> {
+    let Some(declarator) = ctx.stream.peek::<tok::class::ItemDeclarator>() else {
+        ctx.report_missing(tok::class::ItemDeclarator::MEMBERS);
+        return None;
+    };
+    return parse_tl_item(ctx, declarator).ok();
 }
 
 // -- Tests --------------------------------------------------------------------------------------
@@ -237,9 +941,11 @@ fn parse_statement(ctx: &mut ParseContext) -> ParseResult<ast::AnyStatement> {
 #[cfg(test)]
 mod test_parser {
     use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::{AnyTopLevelItem, NodeId, Type};
     use crate::tok::lex::lex;
+    use crate::util::side_table::{DenseKey, SideTable};
     use crate::util::str_interner::StrInterner;
-    use super::parse;
+    use super::{parse, parse_with_options, ParseOptions};
     
     #[test]
     fn smoke_test() {
@@ -255,4 +961,111 @@ mod test_parser {
         let ast = parse(&tokbuf, 0, &mut diagnostics);
         assert!(diagnostics.is_empty());
     }
+
+    #[test]
+    fn test_resolve_proc_name() {
+        const SOURCE_TEXT: &'static str = "proc main(argc: int): int {}";
+
+        let string_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        assert_eq!(proc_def.ident.view(&tokbuf).source_text.get(), "main".as_bytes());
+        let Type::NamedType(return_type) = &proc_def.return_type else { panic!(); };
+        assert_eq!(return_type.ident.view(&tokbuf).source_text.get(), "int".as_bytes());
+        let param = proc_def.parameters.iter(&ast).next().unwrap();
+        assert_eq!(param.ident.view(&tokbuf).source_text.get(), "argc".as_bytes());
+    }
+
+    #[test]
+    fn test_node_ids() {
+        const SOURCE_TEXT: &'static str = "proc main(argc: List<int>): int {}";
+
+        let string_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert_eq!(ast.node_count(), 9);
+
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        let param = proc_def.parameters.iter(&ast).next().unwrap();
+        let mut names: SideTable<NodeId, &str> = ast.side_table();
+        names.insert(proc_def.id, "main");
+        names.insert(param.ty.id(), "List<int>");
+        names.insert(proc_def.return_type.id(), "int");
+        assert_eq!(proc_def.id.index(), 0);
+        assert_eq!(param.id.index(), 2);
+        assert_eq!(names.iter().map(|(id, _)| id.index()).collect::<Vec<_>>(), [0, 3, 7]);
+        assert_eq!(names[proc_def.return_type.id()], "int");
+        assert!(!names.contains_key(proc_def.body.id));
+    }
+
+    #[test]
+    fn test_unsupported_item() {
+        const SOURCE_TEXT: &'static str = "enum E { A }\nproc main(): int {}";
+
+        let string_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        let [AnyDiagnostic::UnsupportedItem(unsupported)] = diagnostics.as_slice()
+            else { panic!(); };
+        assert_eq!(unsupported.at(), tokbuf.key_range().start);
+        let Some(AnyTopLevelItem::Proc(_)) = ast.root.items(&ast).next() else { panic!(); };
+    }
+
+    #[test]
+    fn test_nesting_too_deep() {
+        // Neither the type nor the right-associative assignments may overflow the stack.
+        let depth = 10_000;
+        let source_text = format!("proc f(x: {}int{}, y: int): int {{\n    {}x;\n    y;\n}}",
+            "List<".repeat(depth), ">".repeat(depth), "x = ".repeat(depth));
+        let string_interner = StrInterner::default();
+        let tokbuf = lex(source_text.as_bytes(), &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|diag| matches!(diag, AnyDiagnostic::NestingTooDeep(_))));
+        assert_eq!(ast.dump(&tokbuf), "\
+            (proc f\n  \
+              (params\n    \
+                (param y (type int)))\n  \
+              (type int)\n  \
+              (block\n    \
+                (error)\n    \
+                (expr (ident y))))\n");
+
+        // Neither may a long chain of left-associative comparisons.
+        let source_text = format!("proc f(): int {{\n    x{};\n    y;\n}}", " < x".repeat(depth));
+        let tokbuf = lex(source_text.as_bytes(), &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        let [AnyDiagnostic::NestingTooDeep(_)] = diagnostics.as_slice() else { panic!(); };
+        assert_eq!(ast.dump(&tokbuf), "\
+            (proc f\n  \
+              (params)\n  \
+              (type int)\n  \
+              (block\n    \
+                (error)\n    \
+                (expr (ident y))))\n");
+
+        // Each type argument is nested one level deeper than the type it belongs to.
+        let tokbuf = lex(b"proc f(x: List<List<int>>): int {}", &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        parse_with_options(&tokbuf, 0, ParseOptions { max_depth: 3 }, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+        parse_with_options(&tokbuf, 0, ParseOptions { max_depth: 2 }, &mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+
+        // As is each comparison folded into the one before it, and the right operand of each.
+        let tokbuf = lex(b"proc f(): int { a < b < c; }", &string_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        parse_with_options(&tokbuf, 0, ParseOptions { max_depth: 4 }, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+        parse_with_options(&tokbuf, 0, ParseOptions { max_depth: 3 }, &mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+    }
 }
@@ -0,0 +1,367 @@
+//! Source spans of AST nodes.
+//!
+//! AST nodes hold references to individual tokens only. The [`Span`] of a node is computed on
+//! demand by walking the node and recording the first and last tokens it references.
+
+use std::ops::Range;
+use crate::parse::ast::*;
+use crate::parse::visit::{self, Visitor};
+use crate::tok::class::{TokClass, TokRef};
+use crate::tok::tokbuf::{Key, TokBuf};
+
+// -- Span ---------------------------------------------------------------------------------------
+
+/// The tokens spanned by a node, from `first` through `last` inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span { pub first: Key, pub last: Key }
+
+impl Span {
+    /// Returns the range of bytes in the source text covered by the spanned tokens. The end of
+    /// the buffer, where an error reported after the last token is placed, covers no bytes.
+    ///
+    /// Panics if `tokbuf` is not the buffer which the spanned node was parsed from.
+    pub fn byte_range(&self, tokbuf: &TokBuf) -> Range<usize> {
+        let byte_range = |key: Key| {
+            if key == tokbuf.key_range().end { return tokbuf.source_len()..tokbuf.source_len(); }
+            return tokbuf.byte_range(key).expect("Span does not point into this TokBuf");
+        };
+        return byte_range(self.first).start..byte_range(self.last).end;
+    }
+
+    /// Returns the smallest span which covers both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        return Span { first: self.first.min(other.first), last: self.last.max(other.last) };
+    }
+}
+
+impl<C: TokClass> From<TokRef<C>> for Span {
+    fn from(tok_ref: TokRef<C>) -> Self {
+        return Span { first: tok_ref.key(), last: tok_ref.key() };
+    }
+}
+
+/// A node which references at least one token. Error nodes reference the position at which
+/// they were reported, so recovered nodes have a span as well.
+pub trait Spanned {
+    fn span(&self, ast: &Ast) -> Span;
+}
+
+/// Records the first and last token referenced by the nodes it visits.
+#[derive(Default)]
+struct SpanCollector { span: Option<Span> }
+
+impl SpanCollector {
+    fn collect(visit: impl FnOnce(&mut SpanCollector)) -> Span {
+        let mut collector = SpanCollector::default();
+        visit(&mut collector);
+        return collector.span.expect("spanned node does not reference any token");
+    }
+}
+
+impl Visitor<'_> for SpanCollector {
+    fn visit_tok_ref<C: TokClass>(&mut self, _ast: &Ast, tok_ref: TokRef<C>) {
+        let tok_span = Span::from(tok_ref);
+        self.span = Some(self.span.map_or(tok_span, |span| span.join(tok_span)));
+    }
+}
+
+macro_rules! impl_spanned {
+    ($($node:ty => $visit:ident),* $(,)?) => {
+        $(
+            impl Spanned for $node {
+                fn span(&self, ast: &Ast) -> Span {
+                    return SpanCollector::collect(|collector| collector.$visit(ast, self));
+                }
+            }
+        )*
+    };
+}
+
+impl_spanned! {
+    AnyTopLevelItem => visit_top_level_item,
+    ProcDefinition => visit_proc_definition,
+    StructDefinition => visit_struct_definition,
+    ImportDeclaration => visit_import_declaration,
+    Field => visit_field,
+    GenericParameters => visit_generic_parameters,
+    GenericParameter => visit_generic_parameter,
+    GenericBound => visit_generic_bound,
+    Parameters => visit_parameters,
+    Parameter => visit_parameter,
+    Type => visit_type,
+    NamedType => visit_named_type,
+    ReferenceType => visit_reference_type,
+    ArrayType => visit_array_type,
+    SliceType => visit_slice_type,
+    TupleType => visit_tuple_type,
+    ProcType => visit_proc_type,
+    TypeArguments => visit_type_arguments,
+    TypeArgument => visit_type_argument,
+    ImperativeBlock => visit_imperative_block,
+    AnyStatement => visit_statement,
+    LetStatement => visit_let_statement,
+    ExprStatement => visit_expr_statement,
+    ExprNode => visit_expr,
+}
+
+impl ProcDefinition {
+    /// Returns the span of the procedure's signature, from the `proc` keyword through the
+    /// return type, or through the last token of the signature if the return type is missing.
+    pub fn signature_span(&self, ast: &Ast) -> Span {
+        return SpanCollector::collect(|collector| {
+            collector.visit_tok_ref(ast, self.proc_keyword);
+            collector.visit_parameters(ast, &self.parameters);
+            if let Some(colon) = self.return_type_separator { collector.visit_tok_ref(ast, colon); }
+            collector.visit_type(ast, &self.return_type);
+        });
+    }
+}
+
+// -- AnyNode ------------------------------------------------------------------------------------
+
+/// A reference to any node of the AST.
+#[derive(Clone, Copy)]
+pub enum AnyNode<'ast> {
+    TopLevelItem(&'ast AnyTopLevelItem),
+    ProcDefinition(&'ast ProcDefinition),
+    StructDefinition(&'ast StructDefinition),
+    ImportDeclaration(&'ast ImportDeclaration),
+    Field(&'ast Field),
+    GenericParameters(&'ast GenericParameters),
+    GenericParameter(&'ast GenericParameter),
+    GenericBound(&'ast GenericBound),
+    Parameters(&'ast Parameters),
+    Parameter(&'ast Parameter),
+    Type(&'ast Type),
+    NamedType(&'ast NamedType),
+    ReferenceType(&'ast ReferenceType),
+    ArrayType(&'ast ArrayType),
+    SliceType(&'ast SliceType),
+    TupleType(&'ast TupleType),
+    ProcType(&'ast ProcType),
+    TypeArguments(&'ast TypeArguments),
+    TypeArgument(&'ast TypeArgument),
+    ImperativeBlock(&'ast ImperativeBlock),
+    Statement(&'ast AnyStatement),
+    LetStatement(&'ast LetStatement),
+    ExprStatement(&'ast ExprStatement),
+    Expr(&'ast ExprNode)
+}
+
+impl AnyNode<'_> {
+    /// Returns the id of the node.
+    pub fn id(&self) -> NodeId {
+        return match self {
+            AnyNode::TopLevelItem(item) => item.id(),
+            AnyNode::ProcDefinition(proc_def) => proc_def.id,
+            AnyNode::StructDefinition(struct_def) => struct_def.id,
+            AnyNode::ImportDeclaration(import) => import.id,
+            AnyNode::Field(field) => field.id,
+            AnyNode::GenericParameters(generics) => generics.id,
+            AnyNode::GenericParameter(generic) => generic.id,
+            AnyNode::GenericBound(bound) => bound.id,
+            AnyNode::Parameters(parameters) => parameters.id,
+            AnyNode::Parameter(parameter) => parameter.id,
+            AnyNode::Type(ty) => ty.id(),
+            AnyNode::NamedType(named_type) => named_type.id,
+            AnyNode::ReferenceType(reference) => reference.id,
+            AnyNode::ArrayType(array) => array.id,
+            AnyNode::SliceType(slice) => slice.id,
+            AnyNode::TupleType(tuple) => tuple.id,
+            AnyNode::ProcType(proc_type) => proc_type.id,
+            AnyNode::TypeArguments(arguments) => arguments.id,
+            AnyNode::TypeArgument(argument) => argument.id,
+            AnyNode::ImperativeBlock(block) => block.id,
+            AnyNode::Statement(statement) => statement.id(),
+            AnyNode::LetStatement(statement) => statement.id,
+            AnyNode::ExprStatement(statement) => statement.id,
+            AnyNode::Expr(expr) => expr.id(),
+        };
+    }
+}
+
+impl Ast {
+    /// Returns the innermost node whose span covers the byte at `offset` in the source text.
+    ///
+    /// Formatting between the tokens of a node is covered by that node. Returns `None` if
+    /// `offset` lies outside of every top-level item.
+    pub fn node_at_offset<'ast>(&'ast self, tokbuf: &TokBuf, offset: usize)
+    -> Option<AnyNode<'ast>>
+    {
+        let mut finder = NodeFinder { tokbuf, offset, stack: Vec::new(), found: None };
+        visit::visit_ast(&mut finder, self);
+        return finder.found;
+    }
+}
+
+/// Finds the innermost node covering `offset`.
+///
+/// Nodes are exited in post-order, so the first node to be exited whose span covers `offset`
+/// is the innermost such node.
+struct NodeFinder<'a, 'b, 'ast> {
+    tokbuf: &'a TokBuf<'b>,
+    offset: usize,
+
+    /// The nodes currently being visited, outermost first, along with the span of the tokens
+    /// visited within each so far.
+    stack: Vec<(AnyNode<'ast>, Option<Span>)>,
+    found: Option<AnyNode<'ast>>
+}
+
+impl<'ast> NodeFinder<'_, '_, 'ast> {
+    fn enter(&mut self, node: AnyNode<'ast>) {
+        self.stack.push((node, None));
+    }
+
+    fn exit(&mut self) {
+        let (node, span) = self.stack.pop().unwrap();
+        let Some(span) = span else { return; };
+        if let Some((_, parent_span)) = self.stack.last_mut() {
+            *parent_span = Some(parent_span.map_or(span, |parent_span| parent_span.join(span)));
+        }
+        if self.found.is_none() && span.byte_range(self.tokbuf).contains(&self.offset) {
+            self.found = Some(node);
+        }
+    }
+}
+
+macro_rules! find_in {
+    ($($visit:ident($node:ty) => $variant:ident via $walk:ident),* $(,)?) => {
+        $(
+            fn $visit(&mut self, ast: &'ast Ast, node: &'ast $node) {
+                if self.found.is_some() { return; }
+                self.enter(AnyNode::$variant(node));
+                visit::$walk(self, ast, node);
+                self.exit();
+            }
+        )*
+    };
+}
+
+impl<'ast> Visitor<'ast> for NodeFinder<'_, '_, 'ast> {
+    find_in! {
+        visit_top_level_item(AnyTopLevelItem) => TopLevelItem via walk_top_level_item,
+        visit_proc_definition(ProcDefinition) => ProcDefinition via walk_proc_definition,
+        visit_struct_definition(StructDefinition) => StructDefinition
+            via walk_struct_definition,
+        visit_import_declaration(ImportDeclaration) => ImportDeclaration
+            via walk_import_declaration,
+        visit_field(Field) => Field via walk_field,
+        visit_generic_parameters(GenericParameters) => GenericParameters
+            via walk_generic_parameters,
+        visit_generic_parameter(GenericParameter) => GenericParameter
+            via walk_generic_parameter,
+        visit_generic_bound(GenericBound) => GenericBound via walk_generic_bound,
+        visit_parameters(Parameters) => Parameters via walk_parameters,
+        visit_parameter(Parameter) => Parameter via walk_parameter,
+        visit_type(Type) => Type via walk_type,
+        visit_named_type(NamedType) => NamedType via walk_named_type,
+        visit_reference_type(ReferenceType) => ReferenceType via walk_reference_type,
+        visit_array_type(ArrayType) => ArrayType via walk_array_type,
+        visit_slice_type(SliceType) => SliceType via walk_slice_type,
+        visit_tuple_type(TupleType) => TupleType via walk_tuple_type,
+        visit_proc_type(ProcType) => ProcType via walk_proc_type,
+        visit_type_arguments(TypeArguments) => TypeArguments via walk_type_arguments,
+        visit_type_argument(TypeArgument) => TypeArgument via walk_type_argument,
+        visit_imperative_block(ImperativeBlock) => ImperativeBlock via walk_imperative_block,
+        visit_statement(AnyStatement) => Statement via walk_statement,
+        visit_let_statement(LetStatement) => LetStatement via walk_let_statement,
+        visit_expr_statement(ExprStatement) => ExprStatement via walk_expr_statement,
+        visit_expr(ExprNode) => Expr via walk_expr,
+    }
+
+    fn visit_tok_ref<C: TokClass>(&mut self, _ast: &'ast Ast, tok_ref: TokRef<C>) {
+        let Some((_, span)) = self.stack.last_mut() else { return; };
+        let tok_span = Span::from(tok_ref);
+        *span = Some(span.map_or(tok_span, |span| span.join(tok_span)));
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_span {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::{AnyStatement, AnyTopLevelItem, ExprNode};
+    use crate::parse::parse::parse;
+    use crate::tok::lex::lex;
+    use crate::util::str_interner::StrInterner;
+    use super::{AnyNode, Spanned};
+
+    const SOURCE_TEXT: &'static str = "proc main(argc: List<int>): int {\n}\n";
+
+    #[test]
+    fn test_span() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        let text = |range: std::ops::Range<usize>| &SOURCE_TEXT[range];
+
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        assert_eq!(text(proc_def.span(&ast).byte_range(&tokbuf)), SOURCE_TEXT.trim_end());
+        assert_eq!(text(proc_def.signature_span(&ast).byte_range(&tokbuf)),
+            "proc main(argc: List<int>): int");
+        assert_eq!(text(proc_def.parameters.span(&ast).byte_range(&tokbuf)), "(argc: List<int>)");
+        let param = proc_def.parameters.iter(&ast).next().unwrap();
+        assert_eq!(text(param.ty.span(&ast).byte_range(&tokbuf)), "List<int>");
+        assert_eq!(text(proc_def.body.span(&ast).byte_range(&tokbuf)), "{\n}");
+    }
+
+    #[test]
+    fn test_span_recovered() {
+        const SOURCE_TEXT: &str = "proc f(): int { ) let = 5; a < ; }";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(!diagnostics.is_empty());
+        let text = |range: std::ops::Range<usize>| &SOURCE_TEXT[range];
+
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        assert_eq!(text(proc_def.span(&ast).byte_range(&tokbuf)), SOURCE_TEXT);
+        let spans: Vec<&str> = proc_def.body.statements(&ast)
+            .map(|statement| text(statement.span(&ast).byte_range(&tokbuf)))
+            .collect();
+        assert_eq!(spans, vec![")", "let = 5;", "a < ;"]);
+
+        let Some(AnyStatement::Expr(statement)) = proc_def.body.statements(&ast).last()
+            else { panic!(); };
+        let ExprNode::Infix(infix) = statement.expr else { panic!(); };
+        let right_operand = ast.get(infix.right_operand);
+        assert!(matches!(right_operand, ExprNode::Error(_)));
+        assert_eq!(text(right_operand.span(&ast).byte_range(&tokbuf)), ";");
+
+        // An error reported after the last token covers no bytes.
+        let source_text = "proc f(): int { a <";
+        let tokbuf = lex(source_text.as_bytes(), &str_interner);
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        assert_eq!(proc_def.span(&ast).byte_range(&tokbuf), 0..source_text.len());
+    }
+
+    #[test]
+    fn test_node_at_offset() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        let offset_of = |needle: &str| SOURCE_TEXT.find(needle).unwrap();
+
+        let Some(AnyNode::NamedType(named_type)) = ast.node_at_offset(&tokbuf, offset_of("int>"))
+            else { panic!(); };
+        assert_eq!(named_type.ident.view(&tokbuf).source_text.get(), "int".as_bytes());
+        let Some(AnyNode::NamedType(named_type)) = ast.node_at_offset(&tokbuf, offset_of("ist"))
+            else { panic!(); };
+        assert_eq!(named_type.ident.view(&tokbuf).source_text.get(), "List".as_bytes());
+        let Some(AnyNode::TypeArguments(_)) = ast.node_at_offset(&tokbuf, offset_of("<"))
+            else { panic!(); };
+        let Some(AnyNode::Parameter(_)) = ast.node_at_offset(&tokbuf, offset_of(": List"))
+            else { panic!(); };
+        let Some(AnyNode::ImperativeBlock(_)) = ast.node_at_offset(&tokbuf, offset_of("\n}"))
+            else { panic!(); };
+        let Some(AnyNode::ProcDefinition(_)) = ast.node_at_offset(&tokbuf, offset_of(" main"))
+            else { panic!(); };
+        assert!(ast.node_at_offset(&tokbuf, SOURCE_TEXT.len() - 1).is_none());
+    }
+}
@@ -0,0 +1,266 @@
+//! A record of the productions the parser entered and exited, for diagnosing grammar bugs
+//! without a debugger.
+//!
+//! Tracing is opt-in, see [`parse_traced`]. A production is entered when the parser begins its
+//! node, and exited when the node is finished. A node which is finished during the recovery from
+//! a syntax error is marked as abandoned. Skipped tokens and reported syntax errors are recorded
+//! where they occur. A production whose first child is parsed before the production is known,
+//! such as an infix expression, is entered after that child. The first event since the
+//! checkpoint is linked to it as its forward parent, so that the child is still printed within
+//! it.
+//!
+//! Each entry is printed on one line, indented by two spaces beneath the production it occurred
+//! in. A range of tokens is printed as the range of its keys and the byte range of its source
+//! text, both excluding trailing trivia, followed by the quoted source text. A key is printed as
+//! in [`Key`]'s `Display`.
+//!
+//! ```txt
+//! ProcDefinition 0.0..6.0 0..19 "proc main(): int {}"
+//!   Parameters 2.0..2.2 9..11 "()"
+//!   NamedType 4.0..5.0 13..16 "int"
+//!   ImperativeBlock 5.1..6.0 17..19 "{}"
+//! ```
+//!
+//! [`parse_traced`]: crate::parse::parse::parse_traced
+
+use std::ops::Range;
+use crate::parse::cst::SyntaxKind;
+use crate::tok::class::Trivia;
+use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Finished,
+
+    /// The production panicked, and its node was finished while recovering from the error.
+    Abandoned
+}
+
+enum TraceEvent {
+    Enter(SyntaxKind, Key),
+    Exit(Key, Outcome),
+    Skip(Range<Key>),
+    Error(Key, String)
+}
+
+#[derive(Default)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+
+    /// The index of the `Enter` event of the production each event is printed within, for the
+    /// first event since a checkpoint, see [`Trace::enter_before`].
+    forward_parents: Vec<Option<usize>>,
+
+    /// The indices of the `Enter` events of the productions not yet exited, innermost last.
+    open: Vec<usize>
+}
+
+impl Trace {
+    /// Returns the number of events recorded so far.
+    pub(super) fn len(&self) -> usize { return self.events.len(); }
+
+    fn push(&mut self, event: TraceEvent) {
+        self.events.push(event);
+        self.forward_parents.push(None);
+    }
+
+    pub(super) fn enter(&mut self, kind: SyntaxKind, at: Key) {
+        self.open.push(self.events.len());
+        self.push(TraceEvent::Enter(kind, at));
+    }
+
+    /// Enters a production at `at` which encloses the events from `first` on, and returns the
+    /// index of its `Enter` event. The event at `first` is linked to it as its forward parent.
+    pub(super) fn enter_before(&mut self, kind: SyntaxKind, at: Key, first: usize) -> usize {
+        let idx = self.events.len();
+        if let Some(forward_parent) = self.forward_parents.get_mut(first) {
+            *forward_parent = Some(idx);
+        }
+        self.enter(kind, at);
+        return idx;
+    }
+
+    pub(super) fn exit(&mut self, at: Key, outcome: Outcome) {
+        self.open.pop();
+        self.push(TraceEvent::Exit(at, outcome));
+    }
+
+    /// Changes the kind of the innermost production not yet exited.
+    pub(super) fn retag(&mut self, kind: SyntaxKind) {
+        let Some(idx) = self.open.last() else { return; };
+        if let TraceEvent::Enter(open_kind, _) = &mut self.events[*idx] { *open_kind = kind; }
+    }
+
+    pub(super) fn skip(&mut self, range: Range<Key>) {
+        self.push(TraceEvent::Skip(range));
+    }
+
+    pub(super) fn error(&mut self, at: Key, message: String) {
+        self.push(TraceEvent::Error(at, message));
+    }
+
+    /// Renders the trace as an indented tree. `tokbuf` must be the buffer which was parsed.
+    pub fn render(&self, tokbuf: &TokBuf) -> String {
+        // The exit of each production, by the index of its `Enter` event.
+        let mut exits: Vec<Option<(Key, Outcome)>> = vec![None; self.events.len()];
+        let mut open: Vec<usize> = Vec::new();
+        for (idx, event) in self.events.iter().enumerate() {
+            match event {
+                TraceEvent::Enter(_, _) => open.push(idx),
+                TraceEvent::Exit(at, outcome) => {
+                    if let Some(enter) = open.pop() { exits[enter] = Some((*at, *outcome)); }
+                },
+                _ => {}
+            }
+        }
+
+        // A forward parent is printed, and entered, before the first event it encloses.
+        let mut is_forward_parent = vec![false; self.events.len()];
+        for forward_parent in self.forward_parents.iter().flatten() {
+            is_forward_parent[*forward_parent] = true;
+        }
+
+        let mut out = String::new();
+        let mut depth: usize = 0;
+        for (idx, event) in self.events.iter().enumerate() {
+            if is_forward_parent[idx] { continue; }
+            let mut parents: Vec<usize> = Vec::new();
+            let mut next = self.forward_parents[idx];
+            while let Some(parent) = next {
+                parents.push(parent);
+                next = self.forward_parents[parent];
+            }
+            for parent in parents.into_iter().rev() {
+                self.render_enter(tokbuf, parent, &exits, depth, &mut out);
+                depth += 1;
+            }
+            match event {
+                TraceEvent::Enter(_, _) => {
+                    self.render_enter(tokbuf, idx, &exits, depth, &mut out);
+                    depth += 1;
+                },
+                TraceEvent::Exit(_, _) => depth -= 1,
+                TraceEvent::Skip(range) => {
+                    let range = describe_range(tokbuf, range.clone());
+                    push_line(&mut out, depth, &format!("skipped {}", range));
+                },
+                TraceEvent::Error(at, message) => {
+                    let line = format!("error at {}: {}", offset(tokbuf, *at), message);
+                    push_line(&mut out, depth, &line);
+                },
+            }
+        }
+        return out;
+    }
+
+    /// Renders the `Enter` event at `idx`, given the exit of each production.
+    fn render_enter(&self, tokbuf: &TokBuf, idx: usize, exits: &[Option<(Key, Outcome)>],
+        depth: usize, out: &mut String)
+    {
+        let TraceEvent::Enter(kind, start) = &self.events[idx] else { unreachable!() };
+        // Every node is finished, or abandoned, before the parser returns.
+        let (end, outcome) = exits[idx].expect("production was never exited");
+        let range = describe_range(tokbuf, *start..end);
+        let line = match outcome {
+            Outcome::Finished => format!("{:?} {}", kind, range),
+            Outcome::Abandoned => format!("{:?} {} abandoned", kind, range),
+        };
+        push_line(out, depth, &line);
+    }
+}
+
+fn push_line(out: &mut String, depth: usize, line: &str) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Returns the byte offset of the token at `key`, or the length of the source text if `key` is
+/// the end of the buffer.
+fn offset(tokbuf: &TokBuf, key: Key) -> usize {
+    return tokbuf.byte_range(key).map_or(tokbuf.source_len(), |range| range.start);
+}
+
+/// Describes the tokens in `range` by the key range, byte range, and quoted source text of all
+/// but their trailing trivia.
+fn describe_range(tokbuf: &TokBuf, range: Range<Key>) -> String {
+    let start_key = range.start;
+    let mut end_key = start_key;
+    let start = offset(tokbuf, range.start);
+    let mut end = start;
+    let mut source_text: Vec<u8> = Vec::new();
+    let mut cursor = TokCursor::with_range(tokbuf, range);
+    while let Some(tok) = cursor.read_tok() {
+        tok.write_source_text(&mut source_text);
+        let is_trivia = cursor.r#match::<Trivia>().is_some();
+        if !is_trivia { end = tokbuf.byte_range(cursor.at()).unwrap().end; }
+        cursor.advance();
+        if !is_trivia { end_key = cursor.at(); }
+    }
+    source_text.truncate(end - start);
+    return format!("{}..{} {}..{} {:?}", start_key, end_key, start, end,
+        String::from_utf8_lossy(&source_text));
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_trace {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::parse::parse_traced;
+    use crate::tok::lex::lex;
+    use crate::util::str_interner::StrInterner;
+
+    fn render(source_text: &str) -> String {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(source_text.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let (_, trace) = parse_traced(&tokbuf, 0, &mut diagnostics);
+        return trace.render(&tokbuf);
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("proc f(a: int): int { let x = a == 1; }"), "\
+            ProcDefinition 0.0..16.0 0..39 \"proc f(a: int): int { let x = a == 1; }\"\n\
+            \x20 Parameters 2.0..6.1 6..14 \"(a: int)\"\n\
+            \x20   Parameter 3.0..6.0 7..13 \"a: int\"\n\
+            \x20     NamedType 5.0..6.0 10..13 \"int\"\n\
+            \x20 NamedType 7.0..8.0 16..19 \"int\"\n\
+            \x20 ImperativeBlock 8.1..16.0 20..39 \"{ let x = a == 1; }\"\n\
+            \x20   LetStatement 9.0..15.1 22..37 \"let x = a == 1;\"\n\
+            \x20     InfixExpr 12.0..15.0 30..36 \"a == 1\"\n\
+            \x20       IdentExpr 12.0..13.0 30..31 \"a\"\n\
+            \x20       LiteralExpr 14.0..15.0 35..36 \"1\"\n");
+    }
+
+    #[test]
+    fn test_render_chain() {
+        // The left operand of each infix expression is printed within it.
+        let rendered = render("proc f(): int { let x = a == b != c; }");
+        let body: Vec<&str> = rendered.lines().skip(4).collect();
+        assert_eq!(body, [
+            "    LetStatement 6.0..14.1 16..36 \"let x = a == b != c;\"",
+            "      InfixExpr 9.0..14.0 24..35 \"a == b != c\"",
+            "        InfixExpr 9.0..12.0 24..30 \"a == b\"",
+            "          IdentExpr 9.0..10.0 24..25 \"a\"",
+            "          IdentExpr 11.0..12.0 29..30 \"b\"",
+            "        IdentExpr 13.0..14.0 34..35 \"c\"",
+        ]);
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert_eq!(render("struct 1 {}\nproc f(1: int): int {}"), "\
+            StructDefinition 0.0..0.1 0..6 \"struct\" abandoned\n\
+            \x20 error at 7: expected identifier\n\
+            skipped 1.0..3.0 7..11 \"1 {}\"\n\
+            ProcDefinition 4.0..13.0 12..34 \"proc f(1: int): int {}\"\n\
+            \x20 Parameters 6.0..10.1 18..26 \"(1: int)\"\n\
+            \x20   Error 7.0..10.0 19..25 \"1: int\"\n\
+            \x20     error at 19: expected identifier\n\
+            \x20     skipped 7.0..10.0 19..25 \"1: int\"\n\
+            \x20 NamedType 11.0..12.0 28..31 \"int\"\n\
+            \x20 ImperativeBlock 12.1..13.0 32..34 \"{}\"\n");
+    }
+}
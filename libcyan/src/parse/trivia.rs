@@ -0,0 +1,309 @@
+//! Attachment of trivia (comments and blank lines) to AST nodes.
+//!
+//! The parser skips trivia entirely, so the AST does not hold any. Tools which must preserve
+//! comments, such as a formatter, recover them with [`attach_trivia`], which assigns each comment
+//! to the node it most plausibly describes.
+//!
+//! - A comment on the same line as, and following, the last token of a node **trails** the
+//!   outermost node ending at that token.
+//! - Otherwise, a comment **leads** the outermost node beginning at the next token.
+//! - If no node begins at the next token, the comment **dangles** in the innermost node which
+//!   contains the next token, for instance a comment inside of an otherwise empty block.
+//!
+//! Blank lines preceding a node are recorded among its leading trivia.
+
+use std::collections::HashMap;
+use crate::parse::ast::*;
+use crate::parse::span::Span;
+use crate::parse::visit::{self, Visitor};
+use crate::tok::class::{LineComment, TokClass, TokRef};
+use crate::tok::tok::{StaticTok, Tok};
+use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+use crate::util::side_table::SideTable;
+
+#[derive(Clone, Copy)]
+pub enum TriviaPiece {
+    Comment(TokRef<LineComment>),
+
+    /// One or more consecutive blank lines.
+    BlankLines(u32)
+}
+
+/// The trivia attached to a single node, each list in source order.
+#[derive(Default)]
+pub struct NodeTrivia {
+    pub leading: Vec<TriviaPiece>,
+    pub trailing: Vec<TriviaPiece>,
+    pub dangling: Vec<TriviaPiece>
+}
+
+pub struct TriviaMap {
+    pub nodes: SideTable<NodeId, NodeTrivia>,
+
+    /// Comments which could not be attached to any node, in source order. For instance, comments
+    /// following the last top-level item.
+    pub detached: Vec<TriviaPiece>
+}
+
+/// Attaches the trivia in `tokbuf` to the nodes of `ast`, which must have been parsed from it.
+pub fn attach_trivia(ast: &Ast, tokbuf: &TokBuf) -> TriviaMap {
+    let mut bounds = NodeBounds::default();
+    visit::visit_ast(&mut bounds, ast);
+    bounds.sort_spans();
+
+    let mut map = TriviaMap { nodes: ast.side_table(), detached: Vec::new() };
+    let mut pending: Vec<TriviaPiece> = Vec::new();
+    // The last token which is not trivia.
+    let mut prev: Option<Key> = None;
+    // The number of linebreaks since the last token which is not a space.
+    let mut linebreaks: u32 = 0;
+
+    let mut cursor = TokCursor::new(tokbuf);
+    while let Some(tok) = cursor.read_tok() {
+        let key = cursor.at();
+        match tok {
+            Tok::Linebreak => linebreaks += 1,
+            Tok::Static(StaticTok::Space) | Tok::Align(_) => {},
+            Tok::LineComment(_) => {
+                let comment = TriviaPiece::Comment(cursor.match_ref().unwrap());
+                let trails = prev.filter(|_| linebreaks == 0)
+                    .and_then(|prev| bounds.ends.get(&prev));
+                match trails {
+                    Some(id) => map.nodes.get_or_insert_with(*id, NodeTrivia::default)
+                        .trailing.push(comment),
+                    None => {
+                        push_blank_lines(&mut pending, prev, linebreaks);
+                        pending.push(comment);
+                    }
+                }
+                linebreaks = 0;
+            },
+            _ => {
+                push_blank_lines(&mut pending, prev, linebreaks);
+                if let Some(id) = bounds.starts.get(&key).filter(|_| !pending.is_empty()) {
+                    map.nodes.get_or_insert_with(*id, NodeTrivia::default)
+                        .leading.append(&mut pending);
+                }
+                if pending.iter().any(is_comment) {
+                    match bounds.innermost_containing(key) {
+                        Some(id) => map.nodes.get_or_insert_with(id, NodeTrivia::default)
+                            .dangling.append(&mut pending),
+                        None => map.detached.append(&mut pending)
+                    }
+                }
+                pending.clear();
+                prev = Some(key);
+                linebreaks = 0;
+            }
+        }
+        cursor.advance();
+    }
+    map.detached.extend(pending.into_iter().filter(is_comment));
+    return map;
+}
+
+fn is_comment(piece: &TriviaPiece) -> bool { return matches!(piece, TriviaPiece::Comment(_)); }
+
+/// Records the blank lines implied by `linebreaks` consecutive linebreaks, unless they precede
+/// everything else in the file.
+fn push_blank_lines(pending: &mut Vec<TriviaPiece>, prev: Option<Key>, linebreaks: u32) {
+    if prev.is_none() && pending.is_empty() { return; }
+    if linebreaks >= 2 { pending.push(TriviaPiece::BlankLines(linebreaks - 1)); }
+}
+
+/// Maps the first and last token of each node to the node.
+#[derive(Default)]
+struct NodeBounds {
+    /// The outermost node beginning at each token.
+    starts: HashMap<Key, NodeId>,
+
+    /// The outermost node ending at each token.
+    ends: HashMap<Key, NodeId>,
+
+    /// The span of each node, in post-order until [`NodeBounds::sort_spans`] is called, and
+    /// afterwards by first token, outermost first.
+    spans: Vec<(Span, NodeId)>,
+
+    /// The index in `spans` of the next node whose first token is yet to be queried.
+    next_span: usize,
+
+    /// The nodes containing the last key queried, or which began before it, outermost first.
+    containing: Vec<(Span, NodeId)>,
+
+    /// The nodes currently being visited, outermost first, along with the span of the tokens
+    /// visited within each so far.
+    stack: Vec<(NodeId, Option<Span>)>
+}
+
+impl NodeBounds {
+    fn enter(&mut self, id: NodeId) {
+        self.stack.push((id, None));
+    }
+
+    /// Nodes are exited in post-order, so an outer node overwrites the entries of any inner node
+    /// which begins or ends at the same token.
+    fn exit(&mut self) {
+        let (id, span) = self.stack.pop().unwrap();
+        let Some(span) = span else { return; };
+        if let Some((_, parent_span)) = self.stack.last_mut() {
+            *parent_span = Some(parent_span.map_or(span, |parent_span| parent_span.join(span)));
+        }
+        self.starts.insert(span.first, id);
+        self.ends.insert(span.last, id);
+        self.spans.push((span, id));
+    }
+
+    /// Orders the spans for [`NodeBounds::innermost_containing`]. The sort is stable, so of two
+    /// nodes with the same span, the outer one, which was exited last, comes last.
+    fn sort_spans(&mut self) {
+        self.spans.sort_by(|(a, _), (b, _)| a.first.cmp(&b.first).then(b.last.cmp(&a.last)));
+    }
+
+    /// Returns the innermost node containing `key`. Each key queried must follow the last, so
+    /// that the nodes are swept across once, in the order of their first token.
+    fn innermost_containing(&mut self, key: Key) -> Option<NodeId> {
+        while let Some(&(span, id)) = self.spans.get(self.next_span)
+            .filter(|(span, _)| span.first <= key)
+        {
+            while self.containing.last().is_some_and(|(open, _)| open.last < span.first) {
+                self.containing.pop();
+            }
+            self.containing.push((span, id));
+            self.next_span += 1;
+        }
+        while self.containing.last().is_some_and(|(open, _)| open.last < key) {
+            self.containing.pop();
+        }
+        return self.containing.last().map(|(_, id)| *id);
+    }
+}
+
+macro_rules! bound {
+    ($($visit:ident($node:ty) via $walk:ident),* $(,)?) => {
+        $(
+            fn $visit(&mut self, ast: &'ast Ast, node: &'ast $node) {
+                self.enter(node.id);
+                visit::$walk(self, ast, node);
+                self.exit();
+            }
+        )*
+    };
+}
+
+impl<'ast> Visitor<'ast> for NodeBounds {
+    bound! {
+        visit_proc_definition(ProcDefinition) via walk_proc_definition,
+        visit_struct_definition(StructDefinition) via walk_struct_definition,
+        visit_import_declaration(ImportDeclaration) via walk_import_declaration,
+        visit_field(Field) via walk_field,
+        visit_generic_parameters(GenericParameters) via walk_generic_parameters,
+        visit_generic_parameter(GenericParameter) via walk_generic_parameter,
+        visit_generic_bound(GenericBound) via walk_generic_bound,
+        visit_parameters(Parameters) via walk_parameters,
+        visit_parameter(Parameter) via walk_parameter,
+        visit_named_type(NamedType) via walk_named_type,
+        visit_reference_type(ReferenceType) via walk_reference_type,
+        visit_array_type(ArrayType) via walk_array_type,
+        visit_slice_type(SliceType) via walk_slice_type,
+        visit_tuple_type(TupleType) via walk_tuple_type,
+        visit_proc_type(ProcType) via walk_proc_type,
+        visit_type_arguments(TypeArguments) via walk_type_arguments,
+        visit_type_argument(TypeArgument) via walk_type_argument,
+        visit_imperative_block(ImperativeBlock) via walk_imperative_block,
+        visit_let_statement(LetStatement) via walk_let_statement,
+        visit_expr_statement(ExprStatement) via walk_expr_statement,
+    }
+
+    fn visit_expr(&mut self, ast: &'ast Ast, expr: &'ast ExprNode) {
+        self.enter(expr.id());
+        visit::walk_expr(self, ast, expr);
+        self.exit();
+    }
+
+    fn visit_tok_ref<C: TokClass>(&mut self, _ast: &'ast Ast, tok_ref: TokRef<C>) {
+        let Some((_, span)) = self.stack.last_mut() else { return; };
+        let tok_span = Span::from(tok_ref);
+        *span = Some(span.map_or(tok_span, |span| span.join(tok_span)));
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_trivia {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::AnyTopLevelItem;
+    use crate::parse::parse::parse;
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::TokBuf;
+    use crate::util::str_interner::StrInterner;
+    use super::{attach_trivia, TriviaPiece};
+
+    fn render(tokbuf: &TokBuf, pieces: &[TriviaPiece]) -> Vec<String> {
+        return pieces.iter().map(|piece| match piece {
+            TriviaPiece::Comment(comment) => {
+                String::from_utf8_lossy(comment.view(tokbuf).value.str_ref.get()).into_owned()
+            },
+            TriviaPiece::BlankLines(count) => format!("<{} blank>", count),
+        }).collect();
+    }
+
+    #[test]
+    fn test_attach_trivia() {
+        const SOURCE_TEXT: &'static str = "\
+            // Entry point.\n\
+            \n\
+            proc main(\n    \
+                argc: int, // count\n    \
+                // the vector\n    \
+                argv: List<str>,\n\
+            ): int {\n    \
+                // nothing yet\n\
+            } // end\n\
+            // eof\n";
+
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+        let trivia = attach_trivia(&ast, &tokbuf);
+
+        let Some(AnyTopLevelItem::Proc(proc_def)) = ast.root.items(&ast).next() else { panic!(); };
+        let proc_trivia = &trivia.nodes[proc_def.id];
+        assert_eq!(render(&tokbuf, &proc_trivia.leading), [" Entry point.", "<1 blank>"]);
+        assert_eq!(render(&tokbuf, &proc_trivia.trailing), [" end"]);
+        let mut params = proc_def.parameters.iter(&ast);
+        let argc = params.next().unwrap();
+        assert_eq!(render(&tokbuf, &trivia.nodes[argc.id].trailing), [" count"]);
+        let argv = params.next().unwrap();
+        assert_eq!(render(&tokbuf, &trivia.nodes[argv.id].leading), [" the vector"]);
+        assert_eq!(render(&tokbuf, &trivia.nodes[proc_def.body.id].dangling), [" nothing yet"]);
+        assert_eq!(render(&tokbuf, &trivia.detached), [" eof"]);
+        assert!(trivia.nodes.get(proc_def.parameters.id).is_none());
+    }
+
+    #[test]
+    fn test_dangling() {
+        const SOURCE_TEXT: &'static str = "\
+            proc a(): int {\n    // in a\n}\n\
+            struct S {\n    // in S\n}\n\
+            proc b(): int {\n    // in b\n}\n";
+
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+        let trivia = attach_trivia(&ast, &tokbuf);
+
+        let dangling: Vec<Vec<String>> = ast.root.items(&ast).map(|item| {
+            let id = match item {
+                AnyTopLevelItem::Proc(proc_def) => proc_def.body.id,
+                _ => item.id(),
+            };
+            return render(&tokbuf, &trivia.nodes[id].dangling);
+        }).collect();
+        assert_eq!(dangling, [[" in a"], [" in S"], [" in b"]]);
+    }
+}
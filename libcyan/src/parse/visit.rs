@@ -0,0 +1,803 @@
+//! Traversal of the AST.
+//!
+//! A pass implements [`Visitor`] (or [`VisitorMut`]) and overrides only the `visit_*` methods
+//! for the nodes it is interested in. The default implementation of each `visit_*` method calls
+//! the corresponding `walk_*` function, which visits the children of the node. An overriding
+//! method should call the `walk_*` function itself if it wishes to descend into the children.
+//!
+//! [`VisitorMut`] copies each node out of the arena, visits the copy, and writes it back once
+//! all of its children have been visited. Consequently, while a node is being visited, the
+//! arena still holds its original value.
+
+use crate::parse::ast::*;
+use crate::tok::class::{TokClass, TokRef};
+use crate::util::bump_allocator::LLNode;
+
+// -- Visitor ------------------------------------------------------------------------------------
+
+pub trait Visitor<'ast>: Sized {
+    fn visit_root(&mut self, ast: &'ast Ast, root: &'ast Root) {
+        walk_root(self, ast, root);
+    }
+
+    fn visit_top_level_item(&mut self, ast: &'ast Ast, item: &'ast AnyTopLevelItem) {
+        walk_top_level_item(self, ast, item);
+    }
+
+    fn visit_proc_definition(&mut self, ast: &'ast Ast, proc_def: &'ast ProcDefinition) {
+        walk_proc_definition(self, ast, proc_def);
+    }
+
+    fn visit_struct_definition(&mut self, ast: &'ast Ast, struct_def: &'ast StructDefinition) {
+        walk_struct_definition(self, ast, struct_def);
+    }
+
+    fn visit_import_declaration(&mut self, ast: &'ast Ast, import: &'ast ImportDeclaration) {
+        walk_import_declaration(self, ast, import);
+    }
+
+    fn visit_field(&mut self, ast: &'ast Ast, field: &'ast Field) {
+        walk_field(self, ast, field);
+    }
+
+    fn visit_generic_parameters(&mut self, ast: &'ast Ast, generics: &'ast GenericParameters) {
+        walk_generic_parameters(self, ast, generics);
+    }
+
+    fn visit_generic_parameter(&mut self, ast: &'ast Ast, generic: &'ast GenericParameter) {
+        walk_generic_parameter(self, ast, generic);
+    }
+
+    fn visit_generic_bound(&mut self, ast: &'ast Ast, bound: &'ast GenericBound) {
+        walk_generic_bound(self, ast, bound);
+    }
+
+    fn visit_parameters(&mut self, ast: &'ast Ast, parameters: &'ast Parameters) {
+        walk_parameters(self, ast, parameters);
+    }
+
+    fn visit_parameter(&mut self, ast: &'ast Ast, parameter: &'ast Parameter) {
+        walk_parameter(self, ast, parameter);
+    }
+
+    fn visit_type(&mut self, ast: &'ast Ast, ty: &'ast Type) {
+        walk_type(self, ast, ty);
+    }
+
+    fn visit_named_type(&mut self, ast: &'ast Ast, named_type: &'ast NamedType) {
+        walk_named_type(self, ast, named_type);
+    }
+
+    fn visit_reference_type(&mut self, ast: &'ast Ast, reference: &'ast ReferenceType) {
+        walk_reference_type(self, ast, reference);
+    }
+
+    fn visit_array_type(&mut self, ast: &'ast Ast, array: &'ast ArrayType) {
+        walk_array_type(self, ast, array);
+    }
+
+    fn visit_slice_type(&mut self, ast: &'ast Ast, slice: &'ast SliceType) {
+        walk_slice_type(self, ast, slice);
+    }
+
+    fn visit_tuple_type(&mut self, ast: &'ast Ast, tuple: &'ast TupleType) {
+        walk_tuple_type(self, ast, tuple);
+    }
+
+    fn visit_proc_type(&mut self, ast: &'ast Ast, proc_type: &'ast ProcType) {
+        walk_proc_type(self, ast, proc_type);
+    }
+
+    fn visit_type_arguments(&mut self, ast: &'ast Ast, arguments: &'ast TypeArguments) {
+        walk_type_arguments(self, ast, arguments);
+    }
+
+    fn visit_type_argument(&mut self, ast: &'ast Ast, argument: &'ast TypeArgument) {
+        walk_type_argument(self, ast, argument);
+    }
+
+    fn visit_imperative_block(&mut self, ast: &'ast Ast, block: &'ast ImperativeBlock) {
+        walk_imperative_block(self, ast, block);
+    }
+
+    fn visit_statement(&mut self, ast: &'ast Ast, statement: &'ast AnyStatement) {
+        walk_statement(self, ast, statement);
+    }
+
+    fn visit_let_statement(&mut self, ast: &'ast Ast, statement: &'ast LetStatement) {
+        walk_let_statement(self, ast, statement);
+    }
+
+    fn visit_expr_statement(&mut self, ast: &'ast Ast, statement: &'ast ExprStatement) {
+        walk_expr_statement(self, ast, statement);
+    }
+
+    fn visit_expr(&mut self, ast: &'ast Ast, expr: &'ast ExprNode) {
+        walk_expr(self, ast, expr);
+    }
+
+    /// Called for every token referenced by the AST, in source order.
+    fn visit_tok_ref<C: TokClass>(&mut self, _ast: &'ast Ast, _tok_ref: TokRef<C>) {}
+}
+
+/// Visits every node of `ast`, beginning at its root.
+pub fn visit_ast<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast) {
+    visitor.visit_root(ast, &ast.root);
+}
+
+pub fn walk_root<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast, root: &'ast Root) {
+    for item in root.items(ast) { visitor.visit_top_level_item(ast, item); }
+}
+
+pub fn walk_top_level_item<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    item: &'ast AnyTopLevelItem)
+{
+    match item {
+        AnyTopLevelItem::Proc(proc_def) => visitor.visit_proc_definition(ast, proc_def),
+        AnyTopLevelItem::Struct(struct_def) => visitor.visit_struct_definition(ast, struct_def),
+        AnyTopLevelItem::Import(import) => visitor.visit_import_declaration(ast, import),
+    }
+}
+
+pub fn walk_proc_definition<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    proc_def: &'ast ProcDefinition)
+{
+    visitor.visit_tok_ref(ast, proc_def.proc_keyword);
+    visitor.visit_tok_ref(ast, proc_def.ident);
+    if let Some(generics) = &proc_def.generics { visitor.visit_generic_parameters(ast, generics); }
+    visitor.visit_parameters(ast, &proc_def.parameters);
+    if let Some(colon) = proc_def.return_type_separator { visitor.visit_tok_ref(ast, colon); }
+    visitor.visit_type(ast, &proc_def.return_type);
+    visitor.visit_imperative_block(ast, &proc_def.body);
+}
+
+pub fn walk_struct_definition<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    struct_def: &'ast StructDefinition)
+{
+    visitor.visit_tok_ref(ast, struct_def.struct_keyword);
+    visitor.visit_tok_ref(ast, struct_def.ident);
+    if let Some(generics) = &struct_def.generics {
+        visitor.visit_generic_parameters(ast, generics);
+    }
+    if let Some(open) = struct_def.open_curly { visitor.visit_tok_ref(ast, open); }
+    for field in struct_def.fields(ast) { visitor.visit_field(ast, field); }
+    if let Some(close) = struct_def.close_curly { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_import_declaration<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    import: &'ast ImportDeclaration)
+{
+    visitor.visit_tok_ref(ast, import.import_keyword);
+    visitor.visit_tok_ref(ast, import.path);
+    if let Some(semicolon) = import.semicolon { visitor.visit_tok_ref(ast, semicolon); }
+}
+
+pub fn walk_field<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast, field: &'ast Field) {
+    visitor.visit_tok_ref(ast, field.ident);
+    if let Some(colon) = field.colon { visitor.visit_tok_ref(ast, colon); }
+    visitor.visit_type(ast, &field.ty);
+    if let Some(comma) = field.comma { visitor.visit_tok_ref(ast, comma); }
+}
+
+pub fn walk_generic_parameters<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    generics: &'ast GenericParameters)
+{
+    visitor.visit_tok_ref(ast, generics.open_angle);
+    for generic in generics.iter(ast) { visitor.visit_generic_parameter(ast, generic); }
+    if let Some(close) = generics.close_angle { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_generic_parameter<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    generic: &'ast GenericParameter)
+{
+    visitor.visit_tok_ref(ast, generic.ident);
+    if let Some(bound) = &generic.bound { visitor.visit_generic_bound(ast, bound); }
+    if let Some(comma) = generic.comma { visitor.visit_tok_ref(ast, comma); }
+}
+
+pub fn walk_generic_bound<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    bound: &'ast GenericBound)
+{
+    visitor.visit_tok_ref(ast, bound.colon);
+    visitor.visit_type(ast, &bound.ty);
+}
+
+pub fn walk_parameters<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    parameters: &'ast Parameters)
+{
+    visitor.visit_tok_ref(ast, parameters.open_paren);
+    for parameter in parameters.iter(ast) { visitor.visit_parameter(ast, parameter); }
+    if let Some(close) = parameters.close_paren { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_parameter<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    parameter: &'ast Parameter)
+{
+    visitor.visit_tok_ref(ast, parameter.ident);
+    if let Some(colon) = parameter.colon { visitor.visit_tok_ref(ast, colon); }
+    visitor.visit_type(ast, &parameter.ty);
+    if let Some(comma) = parameter.comma { visitor.visit_tok_ref(ast, comma); }
+}
+
+pub fn walk_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast, ty: &'ast Type) {
+    match ty {
+        Type::NamedType(named_type) => visitor.visit_named_type(ast, named_type),
+        Type::Reference(reference) => visitor.visit_reference_type(ast, reference),
+        Type::Array(array) => visitor.visit_array_type(ast, array),
+        Type::Slice(slice) => visitor.visit_slice_type(ast, slice),
+        Type::Tuple(tuple) => visitor.visit_tuple_type(ast, tuple),
+        Type::Proc(proc_type) => visitor.visit_proc_type(ast, proc_type),
+        Type::Error(error) => walk_error_node(visitor, ast, error),
+    }
+}
+
+/// Visits the tokens of an error node. There are no nodes within it.
+pub fn walk_error_node<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    error: &'ast ErrorNode)
+{
+    visitor.visit_tok_ref(ast, error.at);
+    if let Some(last) = error.last { visitor.visit_tok_ref(ast, last); }
+}
+
+pub fn walk_named_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    named_type: &'ast NamedType)
+{
+    visitor.visit_tok_ref(ast, named_type.ident);
+    if let Some(arguments) = &named_type.arguments { visitor.visit_type_arguments(ast, arguments); }
+}
+
+pub fn walk_reference_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    reference: &'ast ReferenceType)
+{
+    visitor.visit_tok_ref(ast, reference.ampersand);
+    if let Some(mut_keyword) = reference.mut_keyword { visitor.visit_tok_ref(ast, mut_keyword); }
+    visitor.visit_type(ast, ast.get(reference.referent));
+}
+
+pub fn walk_array_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    array: &'ast ArrayType)
+{
+    visitor.visit_tok_ref(ast, array.open_square);
+    visitor.visit_type(ast, ast.get(array.element));
+    visitor.visit_tok_ref(ast, array.semicolon);
+    visitor.visit_tok_ref(ast, array.length);
+    if let Some(close) = array.close_square { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_slice_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    slice: &'ast SliceType)
+{
+    visitor.visit_tok_ref(ast, slice.open_square);
+    visitor.visit_type(ast, ast.get(slice.element));
+    if let Some(close) = slice.close_square { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_tuple_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    tuple: &'ast TupleType)
+{
+    visitor.visit_tok_ref(ast, tuple.open_paren);
+    for element in tuple.elements(ast) { visitor.visit_type_argument(ast, element); }
+    if let Some(close) = tuple.close_paren { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_proc_type<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    proc_type: &'ast ProcType)
+{
+    visitor.visit_tok_ref(ast, proc_type.proc_keyword);
+    visitor.visit_tok_ref(ast, proc_type.open_paren);
+    for parameter in proc_type.parameters(ast) { visitor.visit_type_argument(ast, parameter); }
+    if let Some(close) = proc_type.close_paren { visitor.visit_tok_ref(ast, close); }
+    visitor.visit_tok_ref(ast, proc_type.return_type_separator);
+    visitor.visit_type(ast, ast.get(proc_type.return_type));
+}
+
+pub fn walk_type_arguments<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    arguments: &'ast TypeArguments)
+{
+    visitor.visit_tok_ref(ast, arguments.open_angle);
+    for argument in arguments.iter(ast) { visitor.visit_type_argument(ast, argument); }
+    if let Some(close) = arguments.close_angle { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_type_argument<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    argument: &'ast TypeArgument)
+{
+    visitor.visit_type(ast, &argument.ty);
+    if let Some(comma) = argument.comma { visitor.visit_tok_ref(ast, comma); }
+}
+
+pub fn walk_imperative_block<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    block: &'ast ImperativeBlock)
+{
+    if let Some(open) = block.open_curly { visitor.visit_tok_ref(ast, open); }
+    for statement in block.statements(ast) { visitor.visit_statement(ast, statement); }
+    if let Some(close) = block.close_curly { visitor.visit_tok_ref(ast, close); }
+}
+
+pub fn walk_statement<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    statement: &'ast AnyStatement)
+{
+    match statement {
+        AnyStatement::Let(statement) => visitor.visit_let_statement(ast, statement),
+        AnyStatement::Expr(statement) => visitor.visit_expr_statement(ast, statement),
+        AnyStatement::Error(error) => walk_error_node(visitor, ast, error),
+    }
+}
+
+pub fn walk_let_statement<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    statement: &'ast LetStatement)
+{
+    visitor.visit_tok_ref(ast, statement.let_keyword);
+    visitor.visit_tok_ref(ast, statement.ident);
+    if let Some(annotation) = &statement.annotation {
+        visitor.visit_tok_ref(ast, annotation.colon);
+        visitor.visit_type(ast, &annotation.ty);
+    }
+    visitor.visit_tok_ref(ast, statement.eq);
+    visitor.visit_expr(ast, &statement.value);
+    if let Some(semicolon) = statement.semicolon { visitor.visit_tok_ref(ast, semicolon); }
+}
+
+pub fn walk_expr_statement<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast,
+    statement: &'ast ExprStatement)
+{
+    visitor.visit_expr(ast, &statement.expr);
+    if let Some(semicolon) = statement.semicolon { visitor.visit_tok_ref(ast, semicolon); }
+}
+
+pub fn walk_expr<'ast>(visitor: &mut impl Visitor<'ast>, ast: &'ast Ast, expr: &'ast ExprNode) {
+    match expr {
+        ExprNode::Ident(ident_expr) => visitor.visit_tok_ref(ast, ident_expr.ident),
+        ExprNode::Infix(infix_expr) => {
+            visitor.visit_expr(ast, ast.get(infix_expr.left_operand));
+            visitor.visit_tok_ref(ast, infix_expr.operator);
+            visitor.visit_expr(ast, ast.get(infix_expr.right_operand));
+        },
+        ExprNode::Literal(literal_expr) => visitor.visit_tok_ref(ast, literal_expr.tok),
+        ExprNode::Error(error) => walk_error_node(visitor, ast, error),
+    }
+}
+
+
+// -- VisitorMut ---------------------------------------------------------------------------------
+
+pub trait VisitorMut: Sized {
+    fn visit_root_mut(&mut self, ast: &mut Ast, root: &mut Root) {
+        walk_root_mut(self, ast, root);
+    }
+
+    fn visit_top_level_item_mut(&mut self, ast: &mut Ast, item: &mut AnyTopLevelItem) {
+        walk_top_level_item_mut(self, ast, item);
+    }
+
+    fn visit_proc_definition_mut(&mut self, ast: &mut Ast, proc_def: &mut ProcDefinition) {
+        walk_proc_definition_mut(self, ast, proc_def);
+    }
+
+    fn visit_struct_definition_mut(&mut self, ast: &mut Ast, struct_def: &mut StructDefinition) {
+        walk_struct_definition_mut(self, ast, struct_def);
+    }
+
+    fn visit_import_declaration_mut(&mut self, ast: &mut Ast, import: &mut ImportDeclaration) {
+        walk_import_declaration_mut(self, ast, import);
+    }
+
+    fn visit_field_mut(&mut self, ast: &mut Ast, field: &mut Field) {
+        walk_field_mut(self, ast, field);
+    }
+
+    fn visit_generic_parameters_mut(&mut self, ast: &mut Ast, generics: &mut GenericParameters) {
+        walk_generic_parameters_mut(self, ast, generics);
+    }
+
+    fn visit_generic_parameter_mut(&mut self, ast: &mut Ast, generic: &mut GenericParameter) {
+        walk_generic_parameter_mut(self, ast, generic);
+    }
+
+    fn visit_generic_bound_mut(&mut self, ast: &mut Ast, bound: &mut GenericBound) {
+        walk_generic_bound_mut(self, ast, bound);
+    }
+
+    fn visit_parameters_mut(&mut self, ast: &mut Ast, parameters: &mut Parameters) {
+        walk_parameters_mut(self, ast, parameters);
+    }
+
+    fn visit_parameter_mut(&mut self, ast: &mut Ast, parameter: &mut Parameter) {
+        walk_parameter_mut(self, ast, parameter);
+    }
+
+    fn visit_type_mut(&mut self, ast: &mut Ast, ty: &mut Type) {
+        walk_type_mut(self, ast, ty);
+    }
+
+    fn visit_named_type_mut(&mut self, ast: &mut Ast, named_type: &mut NamedType) {
+        walk_named_type_mut(self, ast, named_type);
+    }
+
+    fn visit_reference_type_mut(&mut self, ast: &mut Ast, reference: &mut ReferenceType) {
+        walk_reference_type_mut(self, ast, reference);
+    }
+
+    fn visit_array_type_mut(&mut self, ast: &mut Ast, array: &mut ArrayType) {
+        walk_array_type_mut(self, ast, array);
+    }
+
+    fn visit_slice_type_mut(&mut self, ast: &mut Ast, slice: &mut SliceType) {
+        walk_slice_type_mut(self, ast, slice);
+    }
+
+    fn visit_tuple_type_mut(&mut self, ast: &mut Ast, tuple: &mut TupleType) {
+        walk_tuple_type_mut(self, ast, tuple);
+    }
+
+    fn visit_proc_type_mut(&mut self, ast: &mut Ast, proc_type: &mut ProcType) {
+        walk_proc_type_mut(self, ast, proc_type);
+    }
+
+    fn visit_type_arguments_mut(&mut self, ast: &mut Ast, arguments: &mut TypeArguments) {
+        walk_type_arguments_mut(self, ast, arguments);
+    }
+
+    fn visit_type_argument_mut(&mut self, ast: &mut Ast, argument: &mut TypeArgument) {
+        walk_type_argument_mut(self, ast, argument);
+    }
+
+    fn visit_imperative_block_mut(&mut self, ast: &mut Ast, block: &mut ImperativeBlock) {
+        walk_imperative_block_mut(self, ast, block);
+    }
+
+    fn visit_statement_mut(&mut self, ast: &mut Ast, statement: &mut AnyStatement) {
+        walk_statement_mut(self, ast, statement);
+    }
+
+    fn visit_let_statement_mut(&mut self, ast: &mut Ast, statement: &mut LetStatement) {
+        walk_let_statement_mut(self, ast, statement);
+    }
+
+    fn visit_expr_statement_mut(&mut self, ast: &mut Ast, statement: &mut ExprStatement) {
+        walk_expr_statement_mut(self, ast, statement);
+    }
+
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: &mut ExprNode) {
+        walk_expr_mut(self, ast, expr);
+    }
+
+    /// Called for every token referenced by the AST, in source order.
+    fn visit_tok_ref_mut<C: TokClass>(&mut self, _ast: &mut Ast, _tok_ref: &mut TokRef<C>) {}
+}
+
+/// Visits every node of `ast`, beginning at its root.
+pub fn visit_ast_mut(visitor: &mut impl VisitorMut, ast: &mut Ast) {
+    let mut root = ast.root;
+    visitor.visit_root_mut(ast, &mut root);
+    ast.root = root;
+}
+
+/// Visits a copy of the node referred to by `node`, then writes the copy back into the arena.
+fn visit_node_mut<V: VisitorMut, T: Copy + 'static>(visitor: &mut V, ast: &mut Ast,
+    node: AstRef<T>, visit: impl FnOnce(&mut V, &mut Ast, &mut T))
+{
+    let mut value = *ast.get(node);
+    visit(visitor, ast, &mut value);
+    *ast.get_mut(node) = value;
+}
+
+/// Visits each value of the list beginning at `first`, as [`visit_node_mut`] does.
+fn visit_list_mut<V: VisitorMut, T: Copy + 'static>(visitor: &mut V, ast: &mut Ast,
+    first: Option<AstRef<LLNode<T>>>, mut visit: impl FnMut(&mut V, &mut Ast, &mut T))
+{
+    let mut next = first;
+    while let Some(node) = next {
+        visit_node_mut(visitor, ast, node, |v, ast, node| visit(v, ast, &mut node.value));
+        next = ast.get(node).next;
+    }
+}
+
+pub fn walk_root_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, root: &mut Root) {
+    visit_list_mut(visitor, ast, root.ll_head,
+        |v, ast, item| v.visit_top_level_item_mut(ast, item));
+}
+
+pub fn walk_top_level_item_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    item: &mut AnyTopLevelItem)
+{
+    match item {
+        AnyTopLevelItem::Proc(proc_def) => visitor.visit_proc_definition_mut(ast, proc_def),
+        AnyTopLevelItem::Struct(struct_def) => {
+            visitor.visit_struct_definition_mut(ast, struct_def)
+        },
+        AnyTopLevelItem::Import(import) => visitor.visit_import_declaration_mut(ast, import),
+    }
+}
+
+pub fn walk_proc_definition_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    proc_def: &mut ProcDefinition)
+{
+    visitor.visit_tok_ref_mut(ast, &mut proc_def.proc_keyword);
+    visitor.visit_tok_ref_mut(ast, &mut proc_def.ident);
+    if let Some(generics) = &mut proc_def.generics {
+        visitor.visit_generic_parameters_mut(ast, generics);
+    }
+    visitor.visit_parameters_mut(ast, &mut proc_def.parameters);
+    if let Some(colon) = &mut proc_def.return_type_separator {
+        visitor.visit_tok_ref_mut(ast, colon);
+    }
+    visitor.visit_type_mut(ast, &mut proc_def.return_type);
+    visitor.visit_imperative_block_mut(ast, &mut proc_def.body);
+}
+
+pub fn walk_struct_definition_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    struct_def: &mut StructDefinition)
+{
+    visitor.visit_tok_ref_mut(ast, &mut struct_def.struct_keyword);
+    visitor.visit_tok_ref_mut(ast, &mut struct_def.ident);
+    if let Some(generics) = &mut struct_def.generics {
+        visitor.visit_generic_parameters_mut(ast, generics);
+    }
+    if let Some(open) = &mut struct_def.open_curly { visitor.visit_tok_ref_mut(ast, open); }
+    visit_list_mut(visitor, ast, struct_def.first, |v, ast, field| v.visit_field_mut(ast, field));
+    if let Some(close) = &mut struct_def.close_curly { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_import_declaration_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    import: &mut ImportDeclaration)
+{
+    visitor.visit_tok_ref_mut(ast, &mut import.import_keyword);
+    visitor.visit_tok_ref_mut(ast, &mut import.path);
+    if let Some(semicolon) = &mut import.semicolon { visitor.visit_tok_ref_mut(ast, semicolon); }
+}
+
+pub fn walk_field_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, field: &mut Field) {
+    visitor.visit_tok_ref_mut(ast, &mut field.ident);
+    if let Some(colon) = &mut field.colon { visitor.visit_tok_ref_mut(ast, colon); }
+    visitor.visit_type_mut(ast, &mut field.ty);
+    if let Some(comma) = &mut field.comma { visitor.visit_tok_ref_mut(ast, comma); }
+}
+
+pub fn walk_generic_parameters_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    generics: &mut GenericParameters)
+{
+    visitor.visit_tok_ref_mut(ast, &mut generics.open_angle);
+    visit_list_mut(visitor, ast, generics.first,
+        |v, ast, generic| v.visit_generic_parameter_mut(ast, generic));
+    if let Some(close) = &mut generics.close_angle { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_generic_parameter_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    generic: &mut GenericParameter)
+{
+    visitor.visit_tok_ref_mut(ast, &mut generic.ident);
+    if let Some(bound) = &mut generic.bound { visitor.visit_generic_bound_mut(ast, bound); }
+    if let Some(comma) = &mut generic.comma { visitor.visit_tok_ref_mut(ast, comma); }
+}
+
+pub fn walk_generic_bound_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    bound: &mut GenericBound)
+{
+    visitor.visit_tok_ref_mut(ast, &mut bound.colon);
+    visitor.visit_type_mut(ast, &mut bound.ty);
+}
+
+pub fn walk_parameters_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    parameters: &mut Parameters)
+{
+    visitor.visit_tok_ref_mut(ast, &mut parameters.open_paren);
+    visit_list_mut(visitor, ast, parameters.first,
+        |v, ast, param| v.visit_parameter_mut(ast, param));
+    if let Some(close) = &mut parameters.close_paren { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_parameter_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    parameter: &mut Parameter)
+{
+    visitor.visit_tok_ref_mut(ast, &mut parameter.ident);
+    if let Some(colon) = &mut parameter.colon { visitor.visit_tok_ref_mut(ast, colon); }
+    visitor.visit_type_mut(ast, &mut parameter.ty);
+    if let Some(comma) = &mut parameter.comma { visitor.visit_tok_ref_mut(ast, comma); }
+}
+
+pub fn walk_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, ty: &mut Type) {
+    match ty {
+        Type::NamedType(named_type) => visitor.visit_named_type_mut(ast, named_type),
+        Type::Reference(reference) => visitor.visit_reference_type_mut(ast, reference),
+        Type::Array(array) => visitor.visit_array_type_mut(ast, array),
+        Type::Slice(slice) => visitor.visit_slice_type_mut(ast, slice),
+        Type::Tuple(tuple) => visitor.visit_tuple_type_mut(ast, tuple),
+        Type::Proc(proc_type) => visitor.visit_proc_type_mut(ast, proc_type),
+        Type::Error(error) => walk_error_node_mut(visitor, ast, error),
+    }
+}
+
+pub fn walk_error_node_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, error: &mut ErrorNode) {
+    visitor.visit_tok_ref_mut(ast, &mut error.at);
+    if let Some(last) = &mut error.last { visitor.visit_tok_ref_mut(ast, last); }
+}
+
+pub fn walk_named_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    named_type: &mut NamedType)
+{
+    visitor.visit_tok_ref_mut(ast, &mut named_type.ident);
+    if let Some(arguments) = &mut named_type.arguments {
+        visitor.visit_type_arguments_mut(ast, arguments);
+    }
+}
+
+pub fn walk_reference_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    reference: &mut ReferenceType)
+{
+    visitor.visit_tok_ref_mut(ast, &mut reference.ampersand);
+    if let Some(mut_keyword) = &mut reference.mut_keyword {
+        visitor.visit_tok_ref_mut(ast, mut_keyword);
+    }
+    visit_node_mut(visitor, ast, reference.referent, |v, ast, ty| v.visit_type_mut(ast, ty));
+}
+
+pub fn walk_array_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, array: &mut ArrayType) {
+    visitor.visit_tok_ref_mut(ast, &mut array.open_square);
+    visit_node_mut(visitor, ast, array.element, |v, ast, ty| v.visit_type_mut(ast, ty));
+    visitor.visit_tok_ref_mut(ast, &mut array.semicolon);
+    visitor.visit_tok_ref_mut(ast, &mut array.length);
+    if let Some(close) = &mut array.close_square { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_slice_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, slice: &mut SliceType) {
+    visitor.visit_tok_ref_mut(ast, &mut slice.open_square);
+    visit_node_mut(visitor, ast, slice.element, |v, ast, ty| v.visit_type_mut(ast, ty));
+    if let Some(close) = &mut slice.close_square { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_tuple_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, tuple: &mut TupleType) {
+    visitor.visit_tok_ref_mut(ast, &mut tuple.open_paren);
+    visit_list_mut(visitor, ast, tuple.first,
+        |v, ast, element| v.visit_type_argument_mut(ast, element));
+    if let Some(close) = &mut tuple.close_paren { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_proc_type_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    proc_type: &mut ProcType)
+{
+    visitor.visit_tok_ref_mut(ast, &mut proc_type.proc_keyword);
+    visitor.visit_tok_ref_mut(ast, &mut proc_type.open_paren);
+    visit_list_mut(visitor, ast, proc_type.first,
+        |v, ast, parameter| v.visit_type_argument_mut(ast, parameter));
+    if let Some(close) = &mut proc_type.close_paren { visitor.visit_tok_ref_mut(ast, close); }
+    visitor.visit_tok_ref_mut(ast, &mut proc_type.return_type_separator);
+    visit_node_mut(visitor, ast, proc_type.return_type, |v, ast, ty| v.visit_type_mut(ast, ty));
+}
+
+pub fn walk_type_arguments_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    arguments: &mut TypeArguments)
+{
+    visitor.visit_tok_ref_mut(ast, &mut arguments.open_angle);
+    visit_list_mut(visitor, ast, arguments.first,
+        |v, ast, arg| v.visit_type_argument_mut(ast, arg));
+    if let Some(close) = &mut arguments.close_angle { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_type_argument_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    argument: &mut TypeArgument)
+{
+    visitor.visit_type_mut(ast, &mut argument.ty);
+    if let Some(comma) = &mut argument.comma { visitor.visit_tok_ref_mut(ast, comma); }
+}
+
+pub fn walk_imperative_block_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    block: &mut ImperativeBlock)
+{
+    if let Some(open) = &mut block.open_curly { visitor.visit_tok_ref_mut(ast, open); }
+    visit_list_mut(visitor, ast, block.first, |v, ast, stmt| v.visit_statement_mut(ast, stmt));
+    if let Some(close) = &mut block.close_curly { visitor.visit_tok_ref_mut(ast, close); }
+}
+
+pub fn walk_statement_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    statement: &mut AnyStatement)
+{
+    match statement {
+        AnyStatement::Let(statement) => visitor.visit_let_statement_mut(ast, statement),
+        AnyStatement::Expr(statement) => visitor.visit_expr_statement_mut(ast, statement),
+        AnyStatement::Error(error) => walk_error_node_mut(visitor, ast, error),
+    }
+}
+
+pub fn walk_let_statement_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    statement: &mut LetStatement)
+{
+    visitor.visit_tok_ref_mut(ast, &mut statement.let_keyword);
+    visitor.visit_tok_ref_mut(ast, &mut statement.ident);
+    if let Some(annotation) = &mut statement.annotation {
+        visitor.visit_tok_ref_mut(ast, &mut annotation.colon);
+        visitor.visit_type_mut(ast, &mut annotation.ty);
+    }
+    visitor.visit_tok_ref_mut(ast, &mut statement.eq);
+    visitor.visit_expr_mut(ast, &mut statement.value);
+    if let Some(semicolon) = &mut statement.semicolon { visitor.visit_tok_ref_mut(ast, semicolon); }
+}
+
+pub fn walk_expr_statement_mut(visitor: &mut impl VisitorMut, ast: &mut Ast,
+    statement: &mut ExprStatement)
+{
+    visitor.visit_expr_mut(ast, &mut statement.expr);
+    if let Some(semicolon) = &mut statement.semicolon { visitor.visit_tok_ref_mut(ast, semicolon); }
+}
+
+pub fn walk_expr_mut(visitor: &mut impl VisitorMut, ast: &mut Ast, expr: &mut ExprNode) {
+    match expr {
+        ExprNode::Ident(ident_expr) => visitor.visit_tok_ref_mut(ast, &mut ident_expr.ident),
+        ExprNode::Infix(infix_expr) => {
+            visit_node_mut(visitor, ast, infix_expr.left_operand,
+                |v, ast, operand| v.visit_expr_mut(ast, operand));
+            visitor.visit_tok_ref_mut(ast, &mut infix_expr.operator);
+            visit_node_mut(visitor, ast, infix_expr.right_operand,
+                |v, ast, operand| v.visit_expr_mut(ast, operand));
+        },
+        ExprNode::Literal(literal_expr) => visitor.visit_tok_ref_mut(ast, &mut literal_expr.tok),
+        ExprNode::Error(error) => walk_error_node_mut(visitor, ast, error),
+    }
+}
+
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_visit {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::{Ast, NamedType};
+    use crate::parse::parse::parse;
+    use crate::tok::class::{TokClass, TokRef};
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::{Key, TokBuf};
+    use crate::util::str_interner::StrInterner;
+    use super::{visit_ast, visit_ast_mut, walk_named_type, Visitor, VisitorMut};
+
+    struct TypeNames<'a> { tokbuf: &'a TokBuf<'a>, names: Vec<String> }
+
+    impl<'ast> Visitor<'ast> for TypeNames<'_> {
+        fn visit_named_type(&mut self, ast: &'ast Ast, named_type: &'ast NamedType) {
+            let name = named_type.ident.view(self.tokbuf);
+            self.names.push(String::from_utf8_lossy(name.source_text.get()).into_owned());
+            walk_named_type(self, ast, named_type);
+        }
+    }
+
+    #[derive(Default)]
+    struct TokKeys { keys: Vec<Key> }
+
+    impl Visitor<'_> for TokKeys {
+        fn visit_tok_ref<C: TokClass>(&mut self, _ast: &Ast, tok_ref: TokRef<C>) {
+            self.keys.push(tok_ref.key());
+        }
+    }
+
+    /// Erases the arguments of every named type.
+    struct EraseTypeArguments;
+
+    impl VisitorMut for EraseTypeArguments {
+        fn visit_named_type_mut(&mut self, _ast: &mut Ast, named_type: &mut NamedType) {
+            named_type.arguments = None;
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        const SOURCE_TEXT: &'static str = "proc main(a: List<int>, b: bool): int {}";
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let mut ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+
+        let mut type_names = TypeNames { tokbuf: &tokbuf, names: Vec::new() };
+        visit_ast(&mut type_names, &ast);
+        assert_eq!(type_names.names, ["List", "int", "bool", "int"]);
+
+        let mut tok_keys = TokKeys::default();
+        visit_ast(&mut tok_keys, &ast);
+        assert_eq!(tok_keys.keys.len(), 18);
+        assert!(tok_keys.keys.is_sorted());
+
+        visit_ast_mut(&mut EraseTypeArguments, &mut ast);
+        let mut type_names = TypeNames { tokbuf: &tokbuf, names: Vec::new() };
+        visit_ast(&mut type_names, &ast);
+        assert_eq!(type_names.names, ["List", "bool", "int"]);
+    }
+}
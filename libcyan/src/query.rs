@@ -0,0 +1,473 @@
+//! A demand-driven engine which memoizes the phases of compilation and tracks their dependencies,
+//! so that after an edit only the work which depends on the edited text is redone.
+//!
+//! Each phase is a query, identified by a [`QueryKey`], whose result is a function of the
+//! source texts and of the results of the other queries it reads. The source texts are the
+//! inputs. Setting one begins a new revision. A query's result is memoized along with the keys
+//! of the queries it read, the revision in which it was last verified, and the revision in which
+//! it last changed.
+//!
+//! When a memoized result is requested in a later revision, its dependencies are brought up to
+//! date first, in the order they were read. If none of them changed since the result was last
+//! verified, the result is reused without running the query. Otherwise the query is run again,
+//! and if its new result equals the old one, the result is not considered changed. This cutoff
+//! keeps an edit from spreading past the first query whose result it does not affect.
+//!
+//! Top-level items are identified by an [`ItemId`] which does not change when other items are
+//! edited. The text of each item, and the names and arities of the structs of each unit, are
+//! separate queries. Checking an item depends on those only, so editing the body of a procedure
+//! reparses its unit, but rechecks only that procedure. When the items of a unit are recomputed,
+//! the memos of the items which no longer exist, such as one which was renamed, are dropped.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::diagnostic::AnyDiagnostic;
+use crate::parse::ast::{AnyTopLevelItem, Ast};
+use crate::parse::fragment;
+use crate::parse::parse::parse;
+use crate::parse::span::Spanned;
+use crate::sema::resolve;
+use crate::source_unit::SourceUnitId;
+use crate::tok::lex::lex;
+use crate::tok::tokbuf::TokBuf;
+use crate::util::str_interner::StrInterner;
+
+/// Numbers the states of the inputs. Every change to an input begins a new revision.
+pub type Revision = u64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QueryKey {
+    /// The text of a source unit. This is an input, see [`Database::set_source_text`].
+    SourceText(SourceUnitId),
+    Parsed(SourceUnitId),
+    Items(SourceUnitId),
+    ItemText(SourceUnitId, ItemId),
+    StructArities(SourceUnitId),
+    CheckItem(SourceUnitId, ItemId)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ItemKind { Proc, Struct, Import }
+
+/// Identifies a top-level item of a source unit by its kind and name, which for an import is its
+/// path. Items of the same kind and name are told apart by their order.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ItemId {
+    pub kind: ItemKind,
+    pub name: Vec<u8>,
+
+    /// The number of items of the same kind and name which precede this item.
+    pub index: u32
+}
+
+/// The tokens and AST of a source unit, along with the diagnostics reported while parsing it.
+pub struct Parsed<'a> {
+    pub text: Rc<[u8]>,
+    pub tokbuf: TokBuf<'a>,
+    pub ast: Ast,
+    pub diagnostics: Vec<AnyDiagnostic>
+}
+
+/// Parsing is deterministic, so parses of equal texts are equal.
+impl PartialEq for Parsed<'_> {
+    fn eq(&self, other: &Self) -> bool { return self.text == other.text; }
+}
+
+/// A named type in an item whose number of type arguments differs from the number of generic
+/// parameters of the struct it names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WrongArity {
+    /// The byte offset of the type's identifier from the beginning of the item's text.
+    pub offset: usize,
+    pub expected: u32,
+    pub found: u32
+}
+
+/// A syntax error reported while reparsing an item from its text alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ItemSyntaxError {
+    /// The byte offset of the error's subject from the beginning of the item's text.
+    pub offset: usize,
+    pub title: &'static str
+}
+
+/// The result of checking an item.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckedItem {
+    pub wrong_arities: Vec<WrongArity>,
+
+    /// The syntax errors in the item's text. An item with syntax errors is still checked, as far
+    /// as it could be parsed.
+    pub syntax_errors: Vec<ItemSyntaxError>
+}
+
+struct Memo<V> {
+    value: V,
+
+    /// The queries which were read to compute `value`, in the order they were read.
+    deps: Vec<QueryKey>,
+    verified_at: Revision,
+    changed_at: Revision
+}
+
+type Table<V> = HashMap<QueryKey, Memo<V>>;
+
+pub struct Database<'a> {
+    str_interner: &'a StrInterner,
+    revision: Revision,
+
+    source_texts: Table<Rc<[u8]>>,
+    parsed: Table<Rc<Parsed<'a>>>,
+    items: Table<Rc<[ItemId]>>,
+    item_texts: Table<Rc<[u8]>>,
+    struct_arities: Table<Rc<HashMap<Vec<u8>, usize>>>,
+    checked_items: Table<Rc<CheckedItem>>,
+
+    /// The queries being run, innermost last, each with the queries it has read so far.
+    active: Vec<(QueryKey, Vec<QueryKey>)>,
+
+    /// The queries which have been run, in order.
+    #[cfg(test)]
+    executed: Vec<QueryKey>
+}
+
+impl<'a> Database<'a> {
+    pub fn new(str_interner: &'a StrInterner) -> Self {
+        return Self {
+            str_interner,
+            revision: 0,
+            source_texts: HashMap::new(),
+            parsed: HashMap::new(),
+            items: HashMap::new(),
+            item_texts: HashMap::new(),
+            struct_arities: HashMap::new(),
+            checked_items: HashMap::new(),
+            active: Vec::new(),
+            #[cfg(test)]
+            executed: Vec::new()
+        };
+    }
+
+    pub fn revision(&self) -> Revision { return self.revision; }
+
+    /// Sets the text of `unit`, beginning a new revision unless the text is unchanged.
+    pub fn set_source_text(&mut self, unit: SourceUnitId, text: impl Into<Rc<[u8]>>) {
+        let key = QueryKey::SourceText(unit);
+        let text = text.into();
+        if self.source_texts.get(&key).is_some_and(|memo| memo.value == text) { return; }
+        self.revision += 1;
+        let memo = Memo { value: text, deps: Vec::new(), verified_at: self.revision,
+            changed_at: self.revision };
+        self.source_texts.insert(key, memo);
+    }
+
+    /// Panics if the text of `unit` was never set.
+    pub fn source_text(&mut self, unit: SourceUnitId) -> Rc<[u8]> {
+        let key = QueryKey::SourceText(unit);
+        self.record_read(&key);
+        return self.source_texts.get(&key).expect("source text was never set").value.clone();
+    }
+
+    pub fn parsed(&mut self, unit: SourceUnitId) -> Rc<Parsed<'a>> {
+        return self.fetch(QueryKey::Parsed(unit), |db| &mut db.parsed, Self::compute_parsed);
+    }
+
+    /// Returns the ids of the top-level items of `unit`, in order.
+    pub fn items(&mut self, unit: SourceUnitId) -> Rc<[ItemId]> {
+        return self.fetch(QueryKey::Items(unit), |db| &mut db.items, Self::compute_items);
+    }
+
+    /// Returns the source text of the tokens of an item, or an empty text if `unit` has no
+    /// such item.
+    pub fn item_text(&mut self, unit: SourceUnitId, item: &ItemId) -> Rc<[u8]> {
+        return self.fetch(QueryKey::ItemText(unit, item.clone()), |db| &mut db.item_texts,
+            Self::compute_item_text);
+    }
+
+    /// Returns the number of generic parameters of each struct of `unit`, by name.
+    pub fn struct_arities(&mut self, unit: SourceUnitId) -> Rc<HashMap<Vec<u8>, usize>> {
+        return self.fetch(QueryKey::StructArities(unit), |db| &mut db.struct_arities,
+            Self::compute_struct_arities);
+    }
+
+    /// Checks the number of type arguments of every named type in an item, see
+    /// [`resolve::check_type_arguments`].
+    pub fn check_item(&mut self, unit: SourceUnitId, item: &ItemId) -> Rc<CheckedItem> {
+        return self.fetch(QueryKey::CheckItem(unit, item.clone()), |db| &mut db.checked_items,
+            Self::compute_check_item);
+    }
+
+    // -- Queries --------------------------------------------------------------------------------
+
+    fn compute_parsed(&mut self, key: &QueryKey) -> Rc<Parsed<'a>> {
+        let QueryKey::Parsed(unit) = *key else { unreachable!(); };
+        let text = self.source_text(unit);
+        let tokbuf = lex(&text, self.str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, unit, &mut diagnostics);
+        return Rc::new(Parsed { text, tokbuf, ast, diagnostics });
+    }
+
+    /// Also drops the memos of the items of the unit which no longer exist, since their ids are
+    /// not expected to be asked for again.
+    fn compute_items(&mut self, key: &QueryKey) -> Rc<[ItemId]> {
+        let QueryKey::Items(unit) = *key else { unreachable!(); };
+        let parsed = self.parsed(unit);
+        let items: Rc<[ItemId]> = item_ids(&parsed).into_iter().map(|(id, _)| id).collect();
+        let exists = |key: &QueryKey| match key {
+            QueryKey::ItemText(item_unit, item) | QueryKey::CheckItem(item_unit, item) => {
+                *item_unit != unit || items.contains(item)
+            },
+            _ => true
+        };
+        self.item_texts.retain(|key, _| exists(key));
+        self.checked_items.retain(|key, _| exists(key));
+        return items;
+    }
+
+    fn compute_item_text(&mut self, key: &QueryKey) -> Rc<[u8]> {
+        let QueryKey::ItemText(unit, item) = key else { unreachable!(); };
+        let parsed = self.parsed(*unit);
+        let Some((_, node)) = item_ids(&parsed).into_iter().find(|(id, _)| id == item) else {
+            return Rc::from([]);
+        };
+        return Rc::from(&parsed.text[node.span(&parsed.ast).byte_range(&parsed.tokbuf)]);
+    }
+
+    fn compute_struct_arities(&mut self, key: &QueryKey) -> Rc<HashMap<Vec<u8>, usize>> {
+        let QueryKey::StructArities(unit) = *key else { unreachable!(); };
+        let parsed = self.parsed(unit);
+        let ast = &parsed.ast;
+        let mut arities: HashMap<Vec<u8>, usize> = HashMap::new();
+        for item in ast.root.items(ast) {
+            let AnyTopLevelItem::Struct(struct_def) = item else { continue; };
+            let name = struct_def.ident.view(&parsed.tokbuf).source_text.get().to_vec();
+            let arity = struct_def.generics.map_or(0, |generics| generics.iter(ast).count());
+            arities.entry(name).or_insert(arity);
+        }
+        return Rc::new(arities);
+    }
+
+    /// Reparses the item from its text alone, so that the check does not depend on the rest of
+    /// the unit.
+    fn compute_check_item(&mut self, key: &QueryKey) -> Rc<CheckedItem> {
+        let QueryKey::CheckItem(unit, item) = key else { unreachable!(); };
+        let text = self.item_text(*unit, item);
+        let arities = self.struct_arities(*unit);
+        let tokbuf = lex(&text, self.str_interner);
+        let fragment = fragment::parse_item(&tokbuf, tokbuf.key_range(), *unit);
+        let offset = |key| tokbuf.byte_range(key).map_or(text.len(), |range| range.start);
+        let syntax_errors = fragment.diagnostics.iter().map(|diagnostic| ItemSyntaxError {
+            offset: offset(diagnostic.subject()),
+            title: diagnostic.view().title()
+        }).collect();
+        let mut checked = CheckedItem { wrong_arities: Vec::new(), syntax_errors };
+        let Some(node) = fragment.node else { return Rc::new(checked); };
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        resolve::check_type_arguments(&fragment.ast, &node, &tokbuf, *unit, &arities,
+            &mut diagnostics);
+        checked.wrong_arities = diagnostics.iter().filter_map(|diagnostic| match diagnostic {
+            AnyDiagnostic::WrongTypeArgCount(diag) => Some(WrongArity {
+                offset: offset(diag.at()),
+                expected: diag.expected(),
+                found: diag.found()
+            }),
+            _ => None
+        }).collect();
+        return Rc::new(checked);
+    }
+
+    // -- Memoization ----------------------------------------------------------------------------
+
+    /// Returns the result of the query `key`, running it with `compute` unless its memo in
+    /// `table` is up to date, and records that the query being run read it.
+    fn fetch<V: Clone + PartialEq>(&mut self, key: QueryKey,
+        table: fn(&mut Self) -> &mut Table<V>, compute: fn(&mut Self, &QueryKey) -> V) -> V
+    {
+        self.record_read(&key);
+        return self.refresh(key, table, compute).0;
+    }
+
+    /// Brings the memo of `key` up to date, and returns its value and the revision in which the
+    /// value last changed.
+    fn refresh<V: Clone + PartialEq>(&mut self, key: QueryKey,
+        table: fn(&mut Self) -> &mut Table<V>, compute: fn(&mut Self, &QueryKey) -> V)
+        -> (V, Revision)
+    {
+        let revision = self.revision;
+        if let Some(memo) = table(self).get(&key) {
+            if memo.verified_at == revision { return (memo.value.clone(), memo.changed_at); }
+            let (deps, verified_at) = (memo.deps.clone(), memo.verified_at);
+            if !deps.iter().any(|dep| self.changed_after(dep, verified_at)) {
+                let memo = table(self).get_mut(&key).unwrap();
+                memo.verified_at = revision;
+                return (memo.value.clone(), memo.changed_at);
+            }
+        }
+
+        assert!(!self.active.iter().any(|(active, _)| *active == key), "query cycle at {:?}",
+            key);
+        self.active.push((key.clone(), Vec::new()));
+        let value = compute(self, &key);
+        let (_, deps) = self.active.pop().unwrap();
+        #[cfg(test)]
+        self.executed.push(key.clone());
+
+        let changed_at = match table(self).get(&key) {
+            Some(old) if old.value == value => old.changed_at,
+            _ => revision
+        };
+        let memo = Memo { value: value.clone(), deps, verified_at: revision, changed_at };
+        table(self).insert(key, memo);
+        return (value, changed_at);
+    }
+
+    /// Brings the memo of `key` up to date, and returns whether its value changed after
+    /// `revision`.
+    fn changed_after(&mut self, key: &QueryKey, revision: Revision) -> bool {
+        let key = key.clone();
+        let changed_at = match key {
+            QueryKey::SourceText(_) => {
+                self.source_texts.get(&key).map_or(self.revision, |memo| memo.changed_at)
+            },
+            QueryKey::Parsed(_) => {
+                self.refresh(key, |db| &mut db.parsed, Self::compute_parsed).1
+            },
+            QueryKey::Items(_) => self.refresh(key, |db| &mut db.items, Self::compute_items).1,
+            QueryKey::ItemText(_, _) => {
+                self.refresh(key, |db| &mut db.item_texts, Self::compute_item_text).1
+            },
+            QueryKey::StructArities(_) => {
+                self.refresh(key, |db| &mut db.struct_arities, Self::compute_struct_arities).1
+            },
+            QueryKey::CheckItem(_, _) => {
+                self.refresh(key, |db| &mut db.checked_items, Self::compute_check_item).1
+            },
+        };
+        return changed_at > revision;
+    }
+
+    fn record_read(&mut self, key: &QueryKey) {
+        let Some((_, deps)) = self.active.last_mut() else { return; };
+        if !deps.contains(key) { deps.push(key.clone()); }
+    }
+}
+
+/// Returns the id of every top-level item of `parsed`, along with the item.
+fn item_ids<'p>(parsed: &'p Parsed) -> Vec<(ItemId, &'p AnyTopLevelItem)> {
+    let ast = &parsed.ast;
+    let mut counts: HashMap<(ItemKind, Vec<u8>), u32> = HashMap::new();
+    return ast.root.items(ast).map(|item| {
+        let (kind, name) = match item {
+            AnyTopLevelItem::Proc(proc_def) => {
+                (ItemKind::Proc, proc_def.ident.view(&parsed.tokbuf).source_text.get())
+            },
+            AnyTopLevelItem::Struct(struct_def) => {
+                (ItemKind::Struct, struct_def.ident.view(&parsed.tokbuf).source_text.get())
+            },
+            AnyTopLevelItem::Import(import) => {
+                (ItemKind::Import, import.path.view(&parsed.tokbuf).content())
+            },
+        };
+        let count = counts.entry((kind, name.to_vec())).or_insert(0);
+        let id = ItemId { kind, name: name.to_vec(), index: *count };
+        *count += 1;
+        (id, item)
+    }).collect();
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_query {
+    use crate::util::str_interner::StrInterner;
+    use super::{Database, ItemId, ItemKind, ItemSyntaxError, QueryKey, WrongArity};
+
+    const SOURCE_TEXT: &'static str = "\
+        struct Pair<A, B> { first: A, second: B }\n\
+        proc f(p: Pair<int>): int {}\n\
+        proc g(): int {\n    let x = 1;\n}\n";
+
+    fn proc_id(name: &str) -> ItemId {
+        return ItemId { kind: ItemKind::Proc, name: name.as_bytes().to_vec(), index: 0 };
+    }
+
+    /// Checks every item of unit 0, and returns the queries which were run to do so.
+    fn check_all(db: &mut Database) -> Vec<QueryKey> {
+        db.executed.clear();
+        for item in db.items(0).iter() { db.check_item(0, item); }
+        return std::mem::take(&mut db.executed);
+    }
+
+    #[test]
+    fn test_check_item() {
+        let str_interner = StrInterner::default();
+        let mut db = Database::new(&str_interner);
+        db.set_source_text(0, SOURCE_TEXT.as_bytes());
+        let items = db.items(0);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1], proc_id("f"));
+        assert_eq!(&*db.item_text(0, &proc_id("f")), b"proc f(p: Pair<int>): int {}");
+        assert_eq!(db.check_item(0, &proc_id("f")).wrong_arities,
+            [WrongArity { offset: 10, expected: 2, found: 1 }]);
+        assert!(db.check_item(0, &proc_id("g")).wrong_arities.is_empty());
+        assert!(db.check_item(0, &proc_id("g")).syntax_errors.is_empty());
+    }
+
+    #[test]
+    fn test_check_item_syntax_errors() {
+        let str_interner = StrInterner::default();
+        let mut db = Database::new(&str_interner);
+        db.set_source_text(0, SOURCE_TEXT.replace("x = 1", "x = 1 1").as_bytes());
+        let checked = db.check_item(0, &proc_id("g"));
+        // The `;` is missing before the second `1`.
+        assert_eq!(checked.syntax_errors, [ItemSyntaxError { offset: 30, title: "Missing token" }]);
+        assert!(checked.wrong_arities.is_empty());
+    }
+
+    #[test]
+    fn test_evict() {
+        let str_interner = StrInterner::default();
+        let mut db = Database::new(&str_interner);
+        db.set_source_text(0, SOURCE_TEXT.as_bytes());
+        check_all(&mut db);
+        // Renaming `g` drops the memos of the old name.
+        for name in ["h", "i", "j"] {
+            db.set_source_text(0, SOURCE_TEXT.replace("proc g", &format!("proc {}", name))
+                .as_bytes());
+            check_all(&mut db);
+        }
+        assert_eq!(db.item_texts.len(), 3);
+        assert_eq!(db.checked_items.len(), 3);
+        assert!(!db.checked_items.contains_key(&QueryKey::CheckItem(0, proc_id("g"))));
+        assert!(db.checked_items.contains_key(&QueryKey::CheckItem(0, proc_id("j"))));
+    }
+
+    #[test]
+    fn test_reuse() {
+        let str_interner = StrInterner::default();
+        let mut db = Database::new(&str_interner);
+        db.set_source_text(0, SOURCE_TEXT.as_bytes());
+        assert_eq!(check_all(&mut db).len(), 1 + 1 + 3 + 1 + 3);
+        // Nothing changed, so nothing is run again.
+        assert!(check_all(&mut db).is_empty());
+        db.set_source_text(0, SOURCE_TEXT.as_bytes());
+        assert!(check_all(&mut db).is_empty());
+
+        // Editing the body of `g` reparses the unit, but rechecks only `g`.
+        db.set_source_text(0, SOURCE_TEXT.replace("x = 1", "x = 2").as_bytes());
+        let executed = check_all(&mut db);
+        assert!(executed.contains(&QueryKey::Parsed(0)));
+        let checked: Vec<&QueryKey> = executed.iter()
+            .filter(|key| matches!(key, QueryKey::CheckItem(_, _)))
+            .collect();
+        assert_eq!(checked, [&QueryKey::CheckItem(0, proc_id("g"))]);
+
+        // Changing the arity of `Pair` rechecks every item.
+        db.set_source_text(0, SOURCE_TEXT.replace("<A, B>", "<A>").as_bytes());
+        let executed = check_all(&mut db);
+        assert_eq!(executed.iter().filter(|key| matches!(key, QueryKey::CheckItem(_, _))).count(),
+            3);
+        assert!(db.check_item(0, &proc_id("f")).wrong_arities.is_empty());
+    }
+}
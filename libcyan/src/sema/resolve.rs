@@ -0,0 +1,220 @@
+//! Resolution of type names.
+//!
+//! Each [`NamedType`] whose identifier names a struct, or a generic parameter in scope, is
+//! resolved to the declaration of that struct or parameter. Generic parameters are in scope
+//! within the item which declares them, and shadow structs of the same name.
+//!
+//! The number of type arguments of every resolved named type is checked against the number of
+//! generic parameters its declaration has. Generic parameters never accept type arguments.
+//!
+//! Names which do not resolve, such as those of builtin types, are left unresolved.
+//!
+//! [`check_type_arguments`] performs the same check for a single item, against structs which
+//! are given by name, so that an item can be checked apart from the rest of its source unit.
+
+use std::collections::HashMap;
+use crate::diagnostic::{self, AnyDiagnostic};
+use crate::parse::ast::*;
+use crate::parse::visit::{self, Visitor};
+use crate::source_unit::SourceUnitId;
+use crate::tok::class::{Ident, TokRef};
+use crate::tok::tokbuf::TokBuf;
+use crate::util::side_table::SideTable;
+
+/// The declaration which a named type refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The id of a [`StructDefinition`].
+    Struct(NodeId),
+
+    /// The id of a [`GenericParameter`].
+    GenericParameter(NodeId)
+}
+
+/// Resolves the named types of `ast`, which must have been parsed from `tokbuf`. The returned
+/// table maps the id of each resolved [`NamedType`] to its resolution.
+pub fn resolve_types(ast: &Ast, tokbuf: &TokBuf, source_unit: SourceUnitId,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> SideTable<NodeId, Resolution>
+{
+    let mut resolver = Resolver { tokbuf, source_unit, diagnostics, structs: HashMap::new(),
+        scope: Vec::new(), resolutions: ast.side_table() };
+
+    // Structs may be referred to before they are declared, so they are collected first.
+    for item in ast.root.items(ast) {
+        let AnyTopLevelItem::Struct(struct_def) = item else { continue; };
+        let name = resolver.name(struct_def.ident);
+        let arity = struct_def.generics.map_or(0, |generics| generics.iter(ast).count());
+        resolver.structs.entry(name).or_insert((Some(struct_def.id), arity));
+    }
+
+    visit::visit_ast(&mut resolver, ast);
+    return resolver.resolutions;
+}
+
+/// Checks the number of type arguments of every named type in `item`, like [`resolve_types`],
+/// except that the structs in scope are those of `arities`, which maps the name of each struct
+/// to its number of generic parameters. Those structs need not be declared in `ast`, so named
+/// types referring to them are not resolved. The returned table holds the resolutions of named
+/// types referring to generic parameters.
+pub fn check_type_arguments(ast: &Ast, item: &AnyTopLevelItem, tokbuf: &TokBuf,
+    source_unit: SourceUnitId, arities: &HashMap<Vec<u8>, usize>,
+    diagnostics: &mut Vec<AnyDiagnostic>) -> SideTable<NodeId, Resolution>
+{
+    let structs = arities.iter().map(|(name, arity)| (name.as_slice(), (None, *arity)))
+        .collect();
+    let mut resolver = Resolver { tokbuf, source_unit, diagnostics, structs,
+        scope: Vec::new(), resolutions: ast.side_table() };
+    resolver.visit_top_level_item(ast, item);
+    return resolver.resolutions;
+}
+
+struct Resolver<'a, 'b> {
+    tokbuf: &'a TokBuf<'a>,
+    source_unit: SourceUnitId,
+    diagnostics: &'b mut Vec<AnyDiagnostic>,
+
+    /// The id, if it is declared in the AST being resolved, and the number of generic parameters
+    /// of each struct, by name.
+    structs: HashMap<&'a [u8], (Option<NodeId>, usize)>,
+
+    /// The generic parameters of the item being visited.
+    scope: Vec<(&'a [u8], NodeId)>,
+    resolutions: SideTable<NodeId, Resolution>
+}
+
+impl<'a> Resolver<'a, '_> {
+    fn name(&self, ident: TokRef<Ident>) -> &'a [u8] {
+        return ident.view(self.tokbuf).source_text.get();
+    }
+
+    /// Brings the generic parameters of an item into scope for the duration of `walk`.
+    fn with_generics(&mut self, ast: &Ast, generics: Option<GenericParameters>,
+        walk: impl FnOnce(&mut Self))
+    {
+        self.scope.clear();
+        for generic in generics.iter().flat_map(|generics| generics.iter(ast)) {
+            self.scope.push((self.name(generic.ident), generic.id));
+        }
+        walk(self);
+        self.scope.clear();
+    }
+
+    /// Returns the resolution of `name`, if it is known, and the number of type arguments which
+    /// it accepts. Returns `None` if `name` is not in scope.
+    fn lookup(&self, name: &[u8]) -> Option<(Option<Resolution>, usize)> {
+        if let Some((_, id)) = self.scope.iter().rev().find(|(param, _)| *param == name) {
+            return Some((Some(Resolution::GenericParameter(*id)), 0));
+        }
+        let (id, arity) = self.structs.get(name)?;
+        return Some((id.map(Resolution::Struct), *arity));
+    }
+}
+
+impl<'ast> Visitor<'ast> for Resolver<'_, '_> {
+    fn visit_proc_definition(&mut self, ast: &'ast Ast, proc_def: &'ast ProcDefinition) {
+        self.with_generics(ast, proc_def.generics,
+            |resolver| visit::walk_proc_definition(resolver, ast, proc_def));
+    }
+
+    fn visit_struct_definition(&mut self, ast: &'ast Ast, struct_def: &'ast StructDefinition) {
+        self.with_generics(ast, struct_def.generics,
+            |resolver| visit::walk_struct_definition(resolver, ast, struct_def));
+    }
+
+    fn visit_named_type(&mut self, ast: &'ast Ast, named_type: &'ast NamedType) {
+        let name = self.name(named_type.ident);
+        if let Some((resolution, arity)) = self.lookup(name) {
+            if let Some(resolution) = resolution {
+                self.resolutions.insert(named_type.id, resolution);
+            }
+            let found = named_type.arguments.map_or(0, |arguments| arguments.iter(ast).count());
+            if found != arity {
+                let diagnostic = diagnostic::WrongTypeArgCount::new(self.source_unit,
+                    named_type.ident.key(), u32::try_from(arity).unwrap(),
+                    u32::try_from(found).unwrap());
+                self.diagnostics.push(AnyDiagnostic::WrongTypeArgCount(diagnostic));
+            }
+        }
+        visit::walk_named_type(self, ast, named_type);
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_resolve {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::parse::ast::{Ast, AnyTopLevelItem, NamedType, NodeId, Type};
+    use crate::parse::parse::parse;
+    use crate::parse::visit::{self, Visitor};
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::TokBuf;
+    use crate::util::str_interner::StrInterner;
+    use super::{resolve_types, Resolution};
+
+    /// Collects the name and id of every named type, in source order.
+    struct NamedTypes<'a> { tokbuf: &'a TokBuf<'a>, types: Vec<(String, NodeId)> }
+
+    impl<'ast> Visitor<'ast> for NamedTypes<'_> {
+        fn visit_named_type(&mut self, ast: &'ast Ast, named_type: &'ast NamedType) {
+            let name = named_type.ident.view(self.tokbuf).source_text.get();
+            self.types.push((String::from_utf8_lossy(name).into_owned(), named_type.id));
+            visit::walk_named_type(self, ast, named_type);
+        }
+    }
+
+    #[test]
+    fn test_resolve_types() {
+        const SOURCE_TEXT: &'static str = "\
+            proc map<T, U: Pair<T, T>>(xs: Pair<T, U>): Pair<U> {}\n\
+            struct Pair<A, B> { first: A, second: B<int> }\n\
+            struct T { pair: Pair<T, int> }\n";
+
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let ast = parse(&tokbuf, 0, &mut diagnostics);
+        assert!(diagnostics.is_empty());
+        let resolutions = resolve_types(&ast, &tokbuf, 0, &mut diagnostics);
+
+        let mut items = ast.root.items(&ast);
+        let Some(AnyTopLevelItem::Proc(proc_def)) = items.next() else { panic!(); };
+        let Some(AnyTopLevelItem::Struct(pair_def)) = items.next() else { panic!(); };
+        let Some(AnyTopLevelItem::Struct(t_def)) = items.next() else { panic!(); };
+        let proc_generics: Vec<NodeId> = proc_def.generics.unwrap().iter(&ast)
+            .map(|generic| generic.id).collect();
+        let pair_generics: Vec<NodeId> = pair_def.generics.unwrap().iter(&ast)
+            .map(|generic| generic.id).collect();
+
+        let mut named_types = NamedTypes { tokbuf: &tokbuf, types: Vec::new() };
+        visit::visit_ast(&mut named_types, &ast);
+        let resolved: Vec<(&str, Option<Resolution>)> = named_types.types.iter()
+            .map(|(name, id)| (name.as_str(), resolutions.get(*id).copied())).collect();
+        use Resolution::*;
+        assert_eq!(resolved, [
+            // proc map<T, U: Pair<T, T>>(xs: Pair<T, U>): Pair<U>
+            ("Pair", Some(Struct(pair_def.id))),
+            ("T", Some(GenericParameter(proc_generics[0]))),
+            ("T", Some(GenericParameter(proc_generics[0]))),
+            ("Pair", Some(Struct(pair_def.id))),
+            ("T", Some(GenericParameter(proc_generics[0]))),
+            ("U", Some(GenericParameter(proc_generics[1]))),
+            ("Pair", Some(Struct(pair_def.id))),
+            ("U", Some(GenericParameter(proc_generics[1]))),
+            // struct Pair<A, B> { first: A, second: B<int> }
+            ("A", Some(GenericParameter(pair_generics[0]))),
+            ("B", Some(GenericParameter(pair_generics[1]))),
+            ("int", None),
+            // struct T { pair: Pair<T, int> }
+            ("Pair", Some(Struct(pair_def.id))),
+            ("T", Some(Struct(t_def.id))),
+            ("int", None),
+        ]);
+
+        let counts: Vec<(u32, u32)> = diagnostics.iter().map(|diagnostic| match diagnostic {
+            AnyDiagnostic::WrongTypeArgCount(diag) => (diag.expected(), diag.found()),
+            _ => panic!()
+        }).collect();
+        assert_eq!(counts, [(2, 1), (0, 1)]);
+    }
+}
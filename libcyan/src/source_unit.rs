@@ -1 +1,210 @@
+//! The registry of the source units of a build.
+//!
+//! A [`SourceMap`] owns the path, text, [`TokBuf`], and [`Ast`] of every source unit, and hands
+//! out the [`SourceUnitId`]s by which diagnostics and other passes refer to them. Together with
+//! a [`Key`], an id identifies a position in the build, which the map resolves to a path, line,
+//! and column for display.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::diagnostic::AnyDiagnostic;
+use crate::fs::FileSystem;
+use crate::parse::ast::Ast;
+use crate::parse::parse::parse;
+use crate::tok::lex::lex;
+use crate::tok::tokbuf::{Key, TokBuf};
+use crate::util::str_interner::StrInterner;
+
+/// The index of a source unit in its [`SourceMap`].
 pub type SourceUnitId = u32;
+
+pub struct SourceUnit<'a> {
+    path: PathBuf,
+    text: Vec<u8>,
+    tokbuf: TokBuf<'a>,
+    ast: Ast,
+
+    /// The byte offset in `text` at which each line begins.
+    line_starts: Vec<usize>
+}
+
+impl<'a> SourceUnit<'a> {
+    /// Lexes and parses `text` as the source unit `id` at `path`.
+    pub(crate) fn new(id: SourceUnitId, path: PathBuf, text: Vec<u8>,
+        str_interner: &'a StrInterner, diagnostics: &mut Vec<AnyDiagnostic>) -> Self
+    {
+        let tokbuf = lex(&text, str_interner);
+        let ast = parse(&tokbuf, id, diagnostics);
+        let line_starts = std::iter::once(0)
+            .chain(text.iter().enumerate().filter(|(_, ch)| **ch == b'\n').map(|(i, _)| i + 1))
+            .collect();
+        return Self { path, text, tokbuf, ast, line_starts };
+    }
+
+    pub fn path(&self) -> &Path { return &self.path; }
+    pub fn text(&self) -> &[u8] { return &self.text; }
+    pub fn tokbuf(&self) -> &TokBuf<'a> { return &self.tokbuf; }
+    pub fn ast(&self) -> &Ast { return &self.ast; }
+
+    /// Returns the text of the 0-based line `line_idx`, excluding the linebreak which ends it.
+    pub fn line_text(&self, line_idx: usize) -> &[u8] {
+        let start = self.line_starts[line_idx];
+        let end = self.line_starts.get(line_idx + 1).map_or(self.text.len(), |next| next - 1);
+        return &self.text[start..end];
+    }
+}
+
+/// A position in a source unit, as displayed to the user. Lines and columns are 1-based, and
+/// columns count bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub path: &'a Path,
+    pub line: usize,
+    pub column: usize
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}:{}:{}", self.path.display(), self.line, self.column);
+    }
+}
+
+pub struct SourceMap<'a> {
+    str_interner: &'a StrInterner,
+    units: Vec<SourceUnit<'a>>,
+
+    /// The id of the first unit added at each path.
+    by_path: HashMap<PathBuf, SourceUnitId>
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(str_interner: &'a StrInterner) -> Self {
+        return Self { str_interner, units: Vec::new(), by_path: HashMap::new() };
+    }
+
+    /// Lexes and parses `text` as the source unit at `path`, and returns the unit's id. The
+    /// diagnostics of the unit refer to it by that id.
+    pub fn add(&mut self, path: impl Into<PathBuf>, text: Vec<u8>,
+        diagnostics: &mut Vec<AnyDiagnostic>) -> SourceUnitId
+    {
+        let unit = SourceUnit::new(self.next_id(), path.into(), text, self.str_interner,
+            diagnostics);
+        return self.insert(unit);
+    }
+
+    /// Returns the id which the next unit added will receive.
+    pub(crate) fn next_id(&self) -> SourceUnitId {
+        return SourceUnitId::try_from(self.units.len()).unwrap();
+    }
+
+    pub(crate) fn str_interner(&self) -> &'a StrInterner { return self.str_interner; }
+
+    /// Adds `unit`, which must have been created with the id returned by [`Self::next_id`].
+    pub(crate) fn insert(&mut self, unit: SourceUnit<'a>) -> SourceUnitId {
+        let id = self.next_id();
+        self.by_path.entry(unit.path.clone()).or_insert(id);
+        self.units.push(unit);
+        return id;
+    }
+
+    /// Reads the source unit at `path` from `fs` and adds it, unless a unit at `path` was
+    /// already added. Returns the id of the unit at `path`.
+    pub fn load(&mut self, fs: &dyn FileSystem, path: &Path,
+        diagnostics: &mut Vec<AnyDiagnostic>) -> io::Result<SourceUnitId>
+    {
+        if let Some(id) = self.find(path) { return Ok(id); }
+        let text = fs.read(path)?;
+        return Ok(self.add(path, text, diagnostics));
+    }
+
+    /// Panics if `id` was not handed out by this map.
+    pub fn get(&self, id: SourceUnitId) -> &SourceUnit<'a> {
+        return &self.units[usize::try_from(id).unwrap()];
+    }
+
+    /// Returns the id of the unit at `path`, if one was added.
+    pub fn find(&self, path: &Path) -> Option<SourceUnitId> {
+        return self.by_path.get(path).copied();
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = (SourceUnitId, &SourceUnit<'a>)> + '_ {
+        return (0..).zip(self.units.iter());
+    }
+
+    pub fn len(&self) -> usize { return self.units.len(); }
+    pub fn is_empty(&self) -> bool { return self.units.is_empty(); }
+
+    /// Resolves the token at `key` in unit `id` to the location where it begins. The end key of
+    /// the unit's buffer resolves to the end of its text.
+    pub fn locate(&self, id: SourceUnitId, key: Key) -> Location<'_> {
+        let unit = self.get(id);
+        let offset = unit.tokbuf.byte_range(key).map_or(unit.text.len(), |range| range.start);
+        let line_idx = unit.line_starts.partition_point(|start| *start <= offset) - 1;
+        return Location {
+            path: &unit.path,
+            line: line_idx + 1,
+            column: offset - unit.line_starts[line_idx] + 1
+        };
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_source_map {
+    use std::path::Path;
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::util::str_interner::StrInterner;
+    use super::{Location, SourceMap};
+
+    #[test]
+    fn test_locate() {
+        let str_interner = StrInterner::default();
+        let mut source_map = SourceMap::new(&str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let a = source_map.add("a.cyan", b"struct A {}\n".to_vec(), &mut diagnostics);
+        let b = source_map.add("dir/b.cyan", b"struct B {}\n\nproc f(: int): int {}".to_vec(),
+            &mut diagnostics);
+        assert_eq!((a, b), (0, 1));
+        assert_eq!(source_map.find(Path::new("dir/b.cyan")), Some(b));
+        assert_eq!(source_map.get(b).line_text(2), b"proc f(: int): int {}");
+
+        // The missing identifier of the parameter is reported in `b`.
+        let [AnyDiagnostic::MissingTok(missing)] = diagnostics.as_slice() else { panic!(); };
+        let location = source_map.locate(missing.source_unit(), missing.at());
+        assert_eq!(location, Location { path: Path::new("dir/b.cyan"), line: 3, column: 8 });
+        assert_eq!(location.to_string(), "dir/b.cyan:3:8");
+
+        let end = source_map.get(a).tokbuf().key_range().end;
+        assert_eq!(source_map.locate(a, end).line, 2);
+    }
+
+    #[test]
+    fn test_render() {
+        let str_interner = StrInterner::default();
+        let mut source_map = SourceMap::new(&str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        source_map.add("a.cyan", b"struct A {}\n".to_vec(), &mut diagnostics);
+        source_map.add("b.cyan", b"proc f(): int {\n    a == (b;\n}\n".to_vec(), &mut diagnostics);
+        let rendered: Vec<String> = diagnostics.iter()
+            .map(|diagnostic| diagnostic.view().render(&source_map))
+            .collect();
+        assert_eq!(rendered, ["\
+            error: Unclosed `(`\n \
+            --> b.cyan:2:10\n  \
+            |     a == (b;\n  \
+            |          ^\n  \
+            = opened here, but not closed before\n \
+            --> b.cyan:3:1\n  \
+            | }\n  \
+            | ^\n",
+            "\
+            error: Missing token\n \
+            --> b.cyan:2:10\n  \
+            |     a == (b;\n  \
+            |          ^\n  \
+            = expected identifier, string literal, or integer literal\n"]);
+    }
+}
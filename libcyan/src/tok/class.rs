@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
-use crate::tok::tok::{self, StaticTok, Tok, StrLiteral, DecIntLiteral};
-use crate::tok::tokbuf::{TokCursor, Key};
+use crate::tok::tok::{self, StaticTok, Tok, TokKind, StrLiteral, DecIntLiteral};
+use crate::tok::tokbuf::{TokBuf, TokBufEdit, TokCursor, Key};
 
 /// A 32-bit pointer to a token inside of the token buffer. In an abstract sense, `TokRef`s are
 /// the leaves of the AST.
@@ -12,23 +12,174 @@ pub struct TokRef<C: TokClass> {
 }
 
 /// Represents a class of tokens. For example "binary operators", "keywords", "boolean literals".
+///
+/// Classes which are a plain union of token kinds should be declared with [`tok_class`] rather
+/// than implemented by hand.
 pub trait TokClass {
     type View<'a>;
 
+    /// The kinds of tokens which are members of this class.
+    const MEMBERS: &'static [TokKind];
+
     /// If `tok` is a member of this class, returns `Some(Self::View)`, otherwise returns `None`.
     fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>>;
+
+    /// Returns the static tokens which are members of this class.
+    fn static_toks() -> impl Iterator<Item = StaticTok> {
+        return Self::MEMBERS.iter().filter_map(|kind| match kind {
+            TokKind::Static(stok) => Some(*stok),
+            _ => None
+        });
+    }
+
+    /// Returns a human-readable description of the members of this class, suitable for
+    /// completing the sentence "expected ...".
+    fn description() -> String { return describe_kinds(Self::MEMBERS); }
+}
+
+/// Lists `kinds` in prose, for instance "`(`, identifier, or string literal".
+pub fn describe_kinds(kinds: &[TokKind]) -> String {
+    let descriptions: Vec<String> = kinds.iter().map(|kind| match kind {
+        TokKind::Static(stok) => format!("`{}`", String::from_utf8_lossy(stok.source_text())),
+        TokKind::StrLiteral => String::from("string literal"),
+        TokKind::DecIntLiteral => String::from("integer literal"),
+        TokKind::Ident => String::from("identifier"),
+        TokKind::Linebreak => String::from("linebreak"),
+        TokKind::Align => String::from("alignment"),
+        TokKind::LineComment => String::from("line comment"),
+        TokKind::Unexpected => String::from("unexpected character"),
+    }).collect();
+    return match descriptions.as_slice() {
+        [] => String::from("nothing"),
+        [only] => only.clone(),
+        [first, second] => format!("{} or {}", first, second),
+        [init @ .., last] => format!("{}, or {}", init.join(", "), last)
+    };
 }
 
+/// Declares a [`TokClass`] whose members are a union of [`TokKind`]s, along with an enum view
+/// which has one variant per member.
+///
+/// Each member is written `Variant = Kind`, where `Kind` is a variant of [`TokKind`]. Static
+/// tokens are written `Variant = Static(StaticTok)`. A member may capture the state of its token
+/// by giving its variant the payload of the corresponding [`Tok`] variant, `Variant(T) = Kind`.
+///
+/// If no member has a payload, the enum is its own view.
+///
+/// ```ignore
+/// tok_class! {
+///     pub enum Square { Open = Static(OpenSquare), Close = Static(CloseSquare) }
+/// }
+/// ```
+///
+/// Otherwise, the class is a unit struct whose view is the enum.
+///
+/// ```ignore
+/// tok_class! {
+///     pub struct Literal;
+///     pub enum AnyLiteral<'a> { Str(StrLiteral<'a>) = StrLiteral }
+/// }
+/// ```
+macro_rules! tok_class {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $class:ident {
+            $($variant:ident = $kind:ident $(($stok:ident))?),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $class { $($variant),* }
+
+        impl TokClass for $class {
+            type View<'a> = Self;
+
+            const MEMBERS: &'static [TokKind] = &[$(tok_class!(@kind $kind [$($stok)?])),*];
+
+            fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
+                $(tok_class!(@arm tok, $class, $variant, [], $kind [$($stok)?]);)*
+                return None;
+            }
+        }
+    };
+    (
+        $(#[$class_meta:meta])*
+        $class_vis:vis struct $class:ident;
+        $(#[$meta:meta])*
+        $vis:vis enum $view:ident<$lt:lifetime> {
+            $($variant:ident $(($payload:ty))? = $kind:ident $(($stok:ident))?),* $(,)?
+        }
+    ) => {
+        $(#[$class_meta])*
+        $class_vis struct $class;
+
+        $(#[$meta])*
+        $vis enum $view<$lt> { $($variant $(($payload))?),* }
+
+        impl TokClass for $class {
+            type View<$lt> = $view<$lt>;
+
+            const MEMBERS: &'static [TokKind] = &[$(tok_class!(@kind $kind [$($stok)?])),*];
+
+            fn r#match<$lt>(tok: &Tok<$lt>) -> Option<Self::View<$lt>> {
+                $(tok_class!(@arm tok, $view, $variant, [$($payload)?], $kind [$($stok)?]);)*
+                return None;
+            }
+        }
+    };
+    (@kind Static [$stok:ident]) => { TokKind::Static(StaticTok::$stok) };
+    (@kind $kind:ident []) => { TokKind::$kind };
+    (@arm $tok:ident, $view:ident, $variant:ident, [], $kind:ident [$($stok:ident)?]) => {
+        if $tok.kind() == tok_class!(@kind $kind [$($stok)?]) { return Some($view::$variant); }
+    };
+    (@arm $tok:ident, $view:ident, $variant:ident, [$payload:ty], $kind:ident []) => {
+        if let Tok::$kind(value) = *$tok { return Some($view::$variant(value)); }
+    };
+}
+
+pub(crate) use tok_class;
+
 impl<C: TokClass> Clone for TokRef<C> {
     fn clone(&self) -> Self { *self }
 }
 
 impl<C: TokClass> Copy for TokRef<C> {}
 
+impl<C: TokClass> TokRef<C> {
+    /// Returns a reference to the token at `key`, or `None` if there is no such token or it is
+    /// not a member of `C`.
+    pub fn new(tokbuf: &TokBuf, key: Key) -> Option<Self> {
+        C::r#match(&tokbuf.get(key)?)?;
+        return Some(TokRef { pd: PhantomData, key });
+    }
+
+    /// Returns the key of the referenced token within its [`TokBuf`].
+    pub fn key(self) -> Key { return self.key; }
+
+    /// Returns a reference to the same token after `edit` was applied to its buffer, or `None`
+    /// if the token was replaced by the edit.
+    pub fn rebase(self, edit: &TokBufEdit) -> Option<Self> {
+        return Some(TokRef { pd: PhantomData, key: edit.rebase(self.key)? });
+    }
+
+    /// Reads the referenced token from `tokbuf` and returns its view.
+    ///
+    /// Panics if `tokbuf` is not the buffer this reference was taken from, or was edited since.
+    pub fn view<'a>(self, tokbuf: &'a TokBuf<'a>) -> C::View<'a> {
+        let tok = tokbuf.get(self.key).expect("TokRef does not point into this TokBuf");
+        return C::r#match(&tok).expect("TokRef does not point to a member of its TokClass");
+    }
+}
+
 impl<'a> TokCursor<'a> {
+    /// Returns a reference to the position of the cursor, which is the next token, or the end
+    /// of the cursor's range if no tokens remain.
+    pub fn position(&self) -> TokRef<AnyTok> {
+        return TokRef { pd: PhantomData, key: self.at() };
+    }
+
     pub fn match_ref<C: TokClass>(&self) -> Option<TokRef<C>> {
         let next = self.read_tok()?;
-        if C::r#match(&next).is_none() { return None; }
+        C::r#match(&next)?;
         return Some(TokRef { pd: PhantomData, key: self.at() });
     }
 
@@ -40,32 +191,17 @@ impl<'a> TokCursor<'a> {
 
 // -- Binary Operators ---------------------------------------------------------------------------
 
-#[repr(u8)]
-#[derive(Clone, Copy)]
-pub enum BinaryOperator {
-    LessThan,
-    LessThanEq,
-    GreaterThan,
-    GreaterThanEq,
-    EqEq,
-    NotEq,
-    Eq,
-}
-
-impl TokClass for BinaryOperator {
-    type View<'a> = Self;
-
-    fn r#match<'a>(tok: &'a Tok<'a>) -> Option<Self::View<'a>> {
-        match tok {
-            Tok::Static(StaticTok::LessThan) => Some(Self::LessThan),
-            Tok::Static(StaticTok::LessThanEq) => Some(Self::LessThanEq),
-            Tok::Static(StaticTok::GreaterThan) => Some(Self::GreaterThan),
-            Tok::Static(StaticTok::GreaterThanEq) => Some(Self::GreaterThanEq),
-            Tok::Static(StaticTok::EqEq) => Some(Self::EqEq),
-            Tok::Static(StaticTok::NotEq) => Some(Self::NotEq),
-            Tok::Static(StaticTok::Eq) => Some(Self::Eq),
-            _ => None
-        }
+tok_class! {
+    #[repr(u8)]
+    #[derive(Clone, Copy)]
+    pub enum BinaryOperator {
+        LessThan = Static(LessThan),
+        LessThanEq = Static(LessThanEq),
+        GreaterThan = Static(GreaterThan),
+        GreaterThanEq = Static(GreaterThanEq),
+        EqEq = Static(EqEq),
+        NotEq = Static(NotEq),
+        Eq = Static(Eq),
     }
 }
 
@@ -76,6 +212,8 @@ pub struct Ident;
 impl TokClass for Ident {
     type View<'a> = crate::tok::ident::Ident<'a>;
 
+    const MEMBERS: &'static [TokKind] = &[TokKind::Ident];
+
     fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
         match tok {
             Tok::Ident(ident) => Some(*ident),
@@ -86,20 +224,44 @@ impl TokClass for Ident {
 
 // -- Literal -----------------------------------------------------------------------------------
 
-#[derive(Clone, Copy, Debug)]
-pub enum AnyLiteral<'a> {
-    Str(StrLiteral<'a>),
-    DecInt(DecIntLiteral<'a>)
+tok_class! {
+    pub struct Literal;
+    #[derive(Clone, Copy, Debug)]
+    pub enum AnyLiteral<'a> {
+        Str(StrLiteral<'a>) = StrLiteral,
+        DecInt(DecIntLiteral<'a>) = DecIntLiteral,
+    }
 }
 
-pub struct Literal;
+/// String literals, for positions in which an integer literal would be meaningless, such as the
+/// path of an import.
+pub struct StringLiteral;
+
+impl TokClass for StringLiteral {
+    type View<'a> = StrLiteral<'a>;
+
+    const MEMBERS: &'static [TokKind] = &[TokKind::StrLiteral];
 
-impl TokClass for Literal {
-    type View<'a> = AnyLiteral<'a>;
     fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
         match tok {
-            Tok::StrLiteral(lit) => Some(AnyLiteral::Str(*lit)),
-            Tok::DecIntLiteral(lit) => Some(AnyLiteral::DecInt(*lit)),
+            Tok::StrLiteral(literal) => Some(*literal),
+            _ => None
+        }
+    }
+}
+
+/// Decimal integer literals, for positions in which a string literal would be meaningless, such
+/// as the length of an array type.
+pub struct IntLiteral;
+
+impl TokClass for IntLiteral {
+    type View<'a> = DecIntLiteral<'a>;
+
+    const MEMBERS: &'static [TokKind] = &[TokKind::DecIntLiteral];
+
+    fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
+        match tok {
+            Tok::DecIntLiteral(literal) => Some(*literal),
             _ => None
         }
     }
@@ -107,80 +269,163 @@ impl TokClass for Literal {
 
 // -- Delimiters --------------------------------------------------------------------------------
 
+/// One class for each [`StaticTok`], whose only member is that token.
 pub mod delims {
-    use crate::tok::tok::{StaticTok, Tok};
+    use crate::tok::tok::{StaticTok, Tok, TokKind};
     use super::TokClass;
     
-    macro_rules! make_delim_class {
-        ($id:ident) => {
-            pub struct $id;
-    
-            impl TokClass for $id {
-                type View<'a> = Self;
-                
-                fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
-                    match tok {
-                        Tok::Static(StaticTok::$id) => Some(Self),
-                        _ => None
+    macro_rules! make_delim_classes {
+        ($($id:ident),*) => {
+            $(
+                pub struct $id;
+
+                impl TokClass for $id {
+                    type View<'a> = Self;
+
+                    const MEMBERS: &'static [TokKind] = &[TokKind::Static(StaticTok::$id)];
+
+                    fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
+                        match tok {
+                            Tok::Static(StaticTok::$id) => Some(Self),
+                            _ => None
+                        }
                     }
                 }
-            }
+            )*
+
+            #[cfg(test)]
+            pub(super) const ALL: &[StaticTok] = &[$(StaticTok::$id),*];
         };
     }
-    
-    make_delim_class!(OpenCurly);
-    make_delim_class!(CloseCurly);
-    make_delim_class!(OpenParen);
-    make_delim_class!(CloseParen);
-    make_delim_class!(LessThan);
-    make_delim_class!(GreaterThan);
-    make_delim_class!(Proc);
-    make_delim_class!(Comma);
-    make_delim_class!(Colon);
+
+    make_delim_classes!(If, For, Let, Struct, Enum, Namespace, Import, Break, Continue, Proc,
+        OpenParen, CloseParen, OpenCurly, CloseCurly, OpenSquare, CloseSquare, LessThan,
+        LessThanEq, GreaterThan, GreaterThanEq, EqEq, NotEq, Eq, Colon, ColonColon, Percent,
+        Exclamation, Ampersand, Semicolon, Space, Comma, Mut);
 }
 
 
 // -- TL Item Declarators -----------------------------------------------------------------------
 
-pub enum ItemDeclarator {
-    Proc,
-    Struct,
-    Enum,
-    LineComment
+tok_class! {
+    pub enum ItemDeclarator {
+        Proc = Static(Proc),
+        Struct = Static(Struct),
+        Enum = Static(Enum),
+        Import = Static(Import),
+    }
 }
 
-impl TokClass for ItemDeclarator {
-    type View<'a> = Self;
+// -- Type Introducers --------------------------------------------------------------------------
 
-    fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
-        match tok {
-            Tok::Static(StaticTok::Proc) => Some(Self::Proc),
-            Tok::Static(StaticTok::Struct) => Some(Self::Struct),
-            Tok::Static(StaticTok::Enum) => Some(Self::Enum),
-            Tok::LineComment(_) => Some(Self::LineComment),
-            _ => None
-        }
+tok_class! {
+    /// Tokens which may begin a type.
+    pub enum TypeIntroducer {
+        Ident = Ident,
+        Ampersand = Static(Ampersand),
+        OpenSquare = Static(OpenSquare),
+        OpenParen = Static(OpenParen),
+        Proc = Static(Proc),
+    }
+}
+
+// -- Expression Introducers --------------------------------------------------------------------
+
+tok_class! {
+    /// Tokens which may begin an expression.
+    pub enum ExprIntroducer {
+        Ident = Ident,
+        StrLiteral = StrLiteral,
+        DecIntLiteral = DecIntLiteral,
+    }
+}
+
+// -- Recovery -----------------------------------------------------------------------------------
+
+tok_class! {
+    /// Tokens at which the parser resumes after an error within a statement.
+    pub enum StatementSync {
+        Semicolon = Static(Semicolon),
+        CloseCurly = Static(CloseCurly),
+        Let = Static(Let),
+        Proc = Static(Proc),
+        Struct = Static(Struct),
+        Enum = Static(Enum),
+        Import = Static(Import),
+    }
+}
+
+tok_class! {
+    /// Tokens at which the parser resumes after an error within a parameter.
+    pub enum ParameterSync {
+        Comma = Static(Comma),
+        CloseParen = Static(CloseParen),
+        OpenCurly = Static(OpenCurly),
+        CloseCurly = Static(CloseCurly),
+        Semicolon = Static(Semicolon),
     }
-    
 }
 
 // -- Formatting ---------------------------------------------------------------------------------
 
-pub struct Formatting;
+tok_class! {
+    pub enum Formatting {
+        Space = Static(Space),
+        Linebreak = Linebreak,
+        Align = Align,
+    }
+}
 
-impl TokClass for Formatting {
-    type View<'a> = Self;
+// -- Trivia -------------------------------------------------------------------------------------
 
-    fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
-        match tok {
-            Tok::Static(StaticTok::Space) => Some(Self),
-            Tok::Linebreak => Some(Self),
-            Tok::Align(_) => Some(Self),
-            _ => None
-        }
+tok_class! {
+    /// Tokens which may appear between any two tokens without affecting the meaning of the
+    /// program. The parser skips these, see `parse::trivia` for how they are recovered.
+    pub enum Trivia {
+        Space = Static(Space),
+        Linebreak = Linebreak,
+        Align = Align,
+        LineComment = LineComment,
     }
 }
 
+// -- AnyTok -------------------------------------------------------------------------------------
+
+/// Tokens of every kind, for references to tokens whose kind does not matter, such as those
+/// skipped while recovering from a syntax error.
+///
+/// A reference to an `AnyTok` may also be a position, see [`TokCursor::position`]. Such a
+/// reference points past the last token when the position is the end of the buffer, and
+/// viewing it panics.
+pub struct AnyTok;
+
+impl TokClass for AnyTok {
+    type View<'a> = Tok<'a>;
+
+    const MEMBERS: &'static [TokKind] = &[
+        TokKind::Static(StaticTok::If), TokKind::Static(StaticTok::For),
+        TokKind::Static(StaticTok::Let), TokKind::Static(StaticTok::Struct),
+        TokKind::Static(StaticTok::Enum), TokKind::Static(StaticTok::Namespace),
+        TokKind::Static(StaticTok::Import), TokKind::Static(StaticTok::Break),
+        TokKind::Static(StaticTok::Continue), TokKind::Static(StaticTok::Proc),
+        TokKind::Static(StaticTok::OpenParen), TokKind::Static(StaticTok::CloseParen),
+        TokKind::Static(StaticTok::OpenCurly), TokKind::Static(StaticTok::CloseCurly),
+        TokKind::Static(StaticTok::OpenSquare), TokKind::Static(StaticTok::CloseSquare),
+        TokKind::Static(StaticTok::LessThan), TokKind::Static(StaticTok::LessThanEq),
+        TokKind::Static(StaticTok::GreaterThan), TokKind::Static(StaticTok::GreaterThanEq),
+        TokKind::Static(StaticTok::EqEq), TokKind::Static(StaticTok::NotEq),
+        TokKind::Static(StaticTok::Eq), TokKind::Static(StaticTok::Colon),
+        TokKind::Static(StaticTok::ColonColon), TokKind::Static(StaticTok::Percent),
+        TokKind::Static(StaticTok::Exclamation), TokKind::Static(StaticTok::Ampersand),
+        TokKind::Static(StaticTok::Semicolon), TokKind::Static(StaticTok::Space),
+        TokKind::Static(StaticTok::Comma), TokKind::Static(StaticTok::Mut),
+        TokKind::StrLiteral, TokKind::DecIntLiteral, TokKind::Ident, TokKind::Linebreak,
+        TokKind::Align, TokKind::LineComment, TokKind::Unexpected
+    ];
+
+    fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> { return Some(*tok); }
+}
+
 // -- LineComment -------------------------------------------------------------------------------
 
 pub struct LineComment;
@@ -190,6 +435,8 @@ pub struct LineCommentView<'a> { pub value: tok::LineComment<'a> }
 impl TokClass for LineComment {
     type View<'a> = LineCommentView<'a>;
 
+    const MEMBERS: &'static [TokKind] = &[TokKind::LineComment];
+
     fn r#match<'a>(tok: &Tok<'a>) -> Option<Self::View<'a>> {
         match *tok {
             Tok::LineComment(value) => Some(LineCommentView { value }),
@@ -197,3 +444,66 @@ impl TokClass for LineComment {
         }
     }
 }
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_class {
+    use crate::tok::lex::lex;
+    use crate::tok::tok::{DecIntLiteral, StaticTok, Tok, TokKind, Align};
+    use crate::tok::tokbuf::TokCursor;
+    use crate::util::str_interner::StrInterner;
+    use crate::util::str_list::StrRef;
+    use super::{delims, AnyLiteral, AnyTok, BinaryOperator, Formatting, Ident, Literal, TokClass};
+
+    #[test]
+    fn test_delims_cover_static_toks() {
+        assert_eq!(delims::ALL, StaticTok::variants());
+        assert!(StaticTok::variants().iter()
+            .all(|stok| AnyTok::MEMBERS.contains(&TokKind::Static(*stok))));
+        assert_eq!(AnyTok::MEMBERS.len(), StaticTok::variants().len() + 7);
+    }
+
+    #[test]
+    fn test_members() {
+        assert_eq!(BinaryOperator::MEMBERS.len(), 7);
+        assert!(BinaryOperator::static_toks().any(|stok| stok == StaticTok::NotEq));
+        assert_eq!(Literal::static_toks().count(), 0);
+        assert_eq!(Formatting::MEMBERS, &[TokKind::Static(StaticTok::Space), TokKind::Linebreak,
+            TokKind::Align]);
+    }
+
+    #[test]
+    fn test_match() {
+        let lit = Tok::DecIntLiteral(DecIntLiteral { str_ref: StrRef::Slice("42".as_bytes()) });
+        let Some(AnyLiteral::DecInt(view)) = Literal::r#match(&lit) else { panic!(); };
+        assert_eq!(view.str_ref.get(), "42".as_bytes());
+        assert!(Literal::r#match(&Tok::Linebreak).is_none());
+        assert!(Formatting::r#match(&Tok::Align(Align { count: 4 })).is_some());
+        assert!(matches!(BinaryOperator::r#match(&Tok::Static(StaticTok::Eq)),
+            Some(BinaryOperator::Eq)));
+    }
+
+    #[test]
+    fn test_tok_ref_view() {
+        let interner = StrInterner::default();
+        let tokbuf = lex("x <= 42".as_bytes(), &interner);
+        let mut cursor = TokCursor::new(&tokbuf);
+        let ident = cursor.match_ref::<Ident>().unwrap();
+        for _ in 0..4 { cursor.advance(); }
+        let literal = cursor.match_ref::<Literal>().unwrap();
+        assert!(cursor.match_ref::<BinaryOperator>().is_none());
+
+        assert_eq!(ident.view(&tokbuf).source_text.get(), "x".as_bytes());
+        let AnyLiteral::DecInt(digits) = literal.view(&tokbuf) else { panic!(); };
+        assert_eq!(digits.str_ref.get(), "42".as_bytes());
+        assert_eq!(literal.key(), cursor.at());
+    }
+
+    #[test]
+    fn test_description() {
+        assert_eq!(delims::Comma::description(), "`,`");
+        assert_eq!(Literal::description(), "string literal or integer literal");
+        assert_eq!(Formatting::description(), "` `, linebreak, or alignment");
+    }
+}
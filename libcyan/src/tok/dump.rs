@@ -0,0 +1,134 @@
+//! A stable, human-readable textual representation of a [`TokBuf`].
+//!
+//! The dump lists one token per line. Each line holds the 1-based line and column (in bytes)
+//! where the token begins, the token's kind, and its quoted and escaped source text.
+//!
+//! ```txt
+//! 1:1 Proc "proc"
+//! 1:5 Space " "
+//! 1:6 Ident "main"
+//! ```
+
+use std::fmt::Write;
+use crate::tok::tok::{Tok, TokKind};
+use crate::tok::tokbuf::TokBuf;
+use crate::util::ascii;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpEntry {
+    pub line: u32,
+    pub col: u32,
+    pub kind: TokKind,
+    pub source_text: Vec<u8>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpParseError {
+    /// The 1-based line number in the dump of the malformed entry.
+    pub line_no: usize,
+    pub reason: &'static str
+}
+
+pub fn dump(tokbuf: &TokBuf) -> String {
+    let mut out = String::new();
+    for (tok, line, col) in iter_positioned(tokbuf) {
+        writeln!(out, "{}:{} {}", line, col, tok).unwrap();
+    }
+    return out;
+}
+
+/// Returns the entries that [`parse_dump`] would produce when given [`dump`] of `tokbuf`.
+pub fn entries(tokbuf: &TokBuf) -> Vec<DumpEntry> {
+    return iter_positioned(tokbuf)
+        .map(|(tok, line, col)| {
+            let mut source_text: Vec<u8> = Vec::new();
+            tok.write_source_text(&mut source_text);
+            DumpEntry { line, col, kind: tok.kind(), source_text }
+        })
+        .collect();
+}
+
+pub fn parse_dump(dump: &str) -> Result<Vec<DumpEntry>, DumpParseError> {
+    let mut entries: Vec<DumpEntry> = Vec::new();
+    for (idx, text) in dump.lines().enumerate() {
+        let error = |reason: &'static str| DumpParseError { line_no: idx + 1, reason };
+        let (pos, rem) = text.split_once(' ').ok_or(error("missing position"))?;
+        let (line, col) = pos.split_once(':').ok_or(error("malformed position"))?;
+        let line: u32 = line.parse().map_err(|_| error("malformed line number"))?;
+        let col: u32 = col.parse().map_err(|_| error("malformed column number"))?;
+        let (kind, quoted) = rem.split_once(' ').ok_or(error("missing source text"))?;
+        let kind = TokKind::from_name(kind).ok_or(error("unknown token kind"))?;
+        let escaped = quoted.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+            .ok_or(error("source text is not quoted"))?;
+        let source_text = ascii::unescape(escaped.as_bytes())
+            .ok_or(error("malformed escape sequence"))?;
+        entries.push(DumpEntry { line, col, kind, source_text });
+    }
+    return Ok(entries);
+}
+
+/// Iterates over the tokens in `tokbuf` along with the 1-based line and column at which each
+/// token begins.
+fn iter_positioned<'a>(tokbuf: &'a TokBuf<'a>) -> impl Iterator<Item = (Tok<'a>, u32, u32)> + 'a {
+    let mut line: u32 = 1;
+    let mut col: u32 = 1;
+    return tokbuf.iter().map(move |tok| {
+        let pos = (tok, line, col);
+        let mut source_text: Vec<u8> = Vec::new();
+        tok.write_source_text(&mut source_text);
+        for ch in source_text {
+            if ch == ascii::LINEBREAK {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+        return pos;
+    });
+}
+
+#[cfg(test)]
+mod test_dump {
+    use crate::tok::lex::lex;
+    use crate::tok::tok::{StaticTok, TokKind};
+    use crate::util::str_interner::StrInterner;
+    use super::{dump, entries, parse_dump, DumpEntry};
+
+    #[test]
+    fn test_format() {
+        let interner = StrInterner::default();
+        let tokbuf = lex("proc main\n  \"a\\\"\n\"".as_bytes(), &interner);
+        assert_eq!(dump(&tokbuf), "\
+            1:1 Proc \"proc\"\n\
+            1:5 Space \" \"\n\
+            1:6 Ident \"main\"\n\
+            1:10 Linebreak \"\\n\"\n\
+            2:1 Align \"  \"\n\
+            2:3 StrLiteral \"\\\"a\\\\\\\"\"\n\
+            2:7 Linebreak \"\\n\"\n\
+            3:1 StrLiteral \"\\\"\"\n\
+        ");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let interner = StrInterner::default();
+        let source_text = "// \u{e9}\tcomment\nlet x=\"a\nb\";$\n".as_bytes();
+        let tokbuf = lex(source_text, &interner);
+        let parsed = parse_dump(&dump(&tokbuf)).unwrap();
+        assert_eq!(parsed, entries(&tokbuf));
+        let reassembled: Vec<u8> = parsed.iter()
+            .flat_map(|entry| entry.source_text.iter().copied())
+            .collect();
+        assert_eq!(reassembled, source_text);
+        assert_eq!(parsed[5], DumpEntry { line: 2, col: 6, kind: TokKind::Static(StaticTok::Eq),
+            source_text: "=".as_bytes().to_vec() });
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = parse_dump("1:1 Proc \"proc\"\n1:5 Bogus \" \"\n").unwrap_err();
+        assert_eq!(error.line_no, 2);
+    }
+}
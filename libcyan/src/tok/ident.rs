@@ -37,11 +37,11 @@ pub const fn is_ident_prefix_ch(ch: u8) -> bool {
 pub const fn is_ident_str(s: &[u8]) -> bool {
     let Some(first_ch) = s.first() else { return false; };
     if !is_ident_prefix_ch(*first_ch) { return false; }
-    return is_ident_chs(&s);
+    return is_ident_chs(s);
 }
 
 pub fn iter_ident_prefix_chs() -> impl Iterator<Item = u8> {
     let underscore = std::iter::once(ascii::UNDERSCORE);
-    let alphabet = ascii::ALPHABET.into_iter();
+    let alphabet = ascii::UPPERCASE.chain(ascii::LOWERCASE);
     return underscore.chain(alphabet);
 }
@@ -94,8 +94,8 @@ pub fn lex<'a>(source_text: &[u8], interner: &'a StrInterner) -> TokBuf<'a> {
     return tokbuf;
 }
 
-fn lex_loop<'a>(ctx: &mut LexContext) {
-    while ctx.stream.rem().len() > 0 {
+fn lex_loop(ctx: &mut LexContext) {
+    while !ctx.stream.rem().is_empty() {
         match PREFIX_TREE.get(ctx.stream.rem().iter().copied()) {
             Some(Prefix::DoubleQuote) => lex_double_quote(ctx),
             Some(Prefix::Digit) => lex_digit(ctx),
@@ -122,7 +122,7 @@ fn lex_double_quote(ctx: &mut LexContext) {
 }
 
 fn lex_digit(ctx: &mut LexContext) {
-    let digits = ctx.stream.advance_while(|ch| ascii::is_numeric_ch(ch));
+    let digits = ctx.stream.advance_while(ascii::is_numeric_ch);
     let str_ref = StrRef::Slice(digits);
     ctx.tokbuf.push(Tok::DecIntLiteral(DecIntLiteral { str_ref }));
 }
@@ -173,7 +173,7 @@ fn lex_ident_prefix_ch(ctx: &mut LexContext, assume_n: usize) {
     let begin = ctx.stream.pos;
     assert!(is_ident_prefix_ch(ctx.stream.advance()));
     ctx.stream.advance_n(assume_n);
-    ctx.stream.advance_while(|ch| is_ident_ch(ch));
+    ctx.stream.advance_while(is_ident_ch);
     let source_text = &ctx.stream.bytes[begin..ctx.stream.pos];
     ctx.tokbuf.push(Tok::Ident(Ident::new(source_text)));
 }
@@ -188,7 +188,9 @@ mod test_lex {
     use crate::tok::tok::{Tok, StaticTok};
     use crate::tok::tokbuf::TokBuf;
     use crate::util::str_interner::StrInterner;
+    use crate::tok::dump::dump;
     use crate::util::misc::assert_matches;
+    use crate::util::golden;
     use super::lex;
 
     #[test]
@@ -240,4 +242,12 @@ mod test_lex {
         assert_matches!(toks[0], Tok::Ident(ident));
         assert_eq!(ident.source_text.get(), source_text);
     }
+
+    #[test]
+    fn test_golden() {
+        golden::check_dir("testdata/lex", "cyan", "toks", |source_text| {
+            let string_interner = StrInterner::default();
+            return dump(&lex(source_text, &string_interner));
+        });
+    }
 }
@@ -3,3 +3,5 @@ pub mod tok;
 pub mod class;
 pub mod ident;
 pub mod lex;
+pub mod dump;
+pub mod tree;
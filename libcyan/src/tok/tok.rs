@@ -15,7 +15,7 @@ pub enum Tok<'a> {
     Unexpected(Unexpected),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum StaticTok {
     If = 1,
@@ -64,7 +64,8 @@ pub enum StaticTok {
     ///    }
     /// ```
     Space = 30,
-    Comma = 31
+    Comma = 31,
+    Mut = 32
 }
 
 impl StaticTok {
@@ -100,7 +101,8 @@ impl StaticTok {
             Self::Ampersand,
             Self::Semicolon,
             Self::Space,
-            Self::Comma
+            Self::Comma,
+            Self::Mut
         ];
     }
     
@@ -113,6 +115,43 @@ impl StaticTok {
         return Self::variants().get(usize::from(id - 1)).copied();
     }
 
+    pub fn name(self) -> &'static str {
+        return match self {
+            StaticTok::If => "If",
+            StaticTok::For => "For",
+            StaticTok::Let => "Let",
+            StaticTok::Struct => "Struct",
+            StaticTok::Enum => "Enum",
+            StaticTok::Namespace => "Namespace",
+            StaticTok::Import => "Import",
+            StaticTok::Break => "Break",
+            StaticTok::Continue => "Continue",
+            StaticTok::Proc => "Proc",
+            StaticTok::OpenParen => "OpenParen",
+            StaticTok::CloseParen => "CloseParen",
+            StaticTok::OpenCurly => "OpenCurly",
+            StaticTok::CloseCurly => "CloseCurly",
+            StaticTok::OpenSquare => "OpenSquare",
+            StaticTok::CloseSquare => "CloseSquare",
+            StaticTok::LessThan => "LessThan",
+            StaticTok::LessThanEq => "LessThanEq",
+            StaticTok::GreaterThan => "GreaterThan",
+            StaticTok::GreaterThanEq => "GreaterThanEq",
+            StaticTok::EqEq => "EqEq",
+            StaticTok::NotEq => "NotEq",
+            StaticTok::Eq => "Eq",
+            StaticTok::Colon => "Colon",
+            StaticTok::ColonColon => "ColonColon",
+            StaticTok::Percent => "Percent",
+            StaticTok::Exclamation => "Exclamation",
+            StaticTok::Ampersand => "Ampersand",
+            StaticTok::Semicolon => "Semicolon",
+            StaticTok::Space => "Space",
+            StaticTok::Comma => "Comma",
+            StaticTok::Mut => "Mut",
+        };
+    }
+
     pub fn source_text(self) -> &'static [u8] {
         return match self {
             StaticTok::If => "if",
@@ -146,10 +185,114 @@ impl StaticTok {
             StaticTok::Semicolon => ";",
             StaticTok::Space => " ",
             StaticTok::Comma => ",",
+            StaticTok::Mut => "mut",
         }.as_bytes();
     }
 }
 
+impl<'a> Tok<'a> {
+    pub fn kind(&self) -> TokKind {
+        return match self {
+            Tok::Static(stok) => TokKind::Static(*stok),
+            Tok::StrLiteral(_) => TokKind::StrLiteral,
+            Tok::DecIntLiteral(_) => TokKind::DecIntLiteral,
+            Tok::Ident(_) => TokKind::Ident,
+            Tok::Linebreak => TokKind::Linebreak,
+            Tok::Align(_) => TokKind::Align,
+            Tok::LineComment(_) => TokKind::LineComment,
+            Tok::Unexpected(_) => TokKind::Unexpected,
+        };
+    }
+
+    /// Returns the length in bytes of the source text this token was lexed from.
+    pub fn source_len(&self) -> usize {
+        return match self {
+            Tok::Static(stok) => stok.source_text().len(),
+            Tok::StrLiteral(lit) => lit.str_ref.get().len(),
+            Tok::DecIntLiteral(lit) => lit.str_ref.get().len(),
+            Tok::Ident(ident) => ident.source_text.get().len(),
+            Tok::Linebreak => 1,
+            Tok::Align(align) => usize::try_from(align.count).unwrap(),
+            Tok::LineComment(lc) => 2 + lc.str_ref.get().len(),
+            Tok::Unexpected(_) => 1,
+        };
+    }
+
+    /// Appends the source text this token was lexed from to `out`.
+    pub fn write_source_text(&self, out: &mut Vec<u8>) {
+        match self {
+            Tok::Static(stok) => out.extend_from_slice(stok.source_text()),
+            Tok::StrLiteral(lit) => out.extend_from_slice(lit.str_ref.get()),
+            Tok::DecIntLiteral(lit) => out.extend_from_slice(lit.str_ref.get()),
+            Tok::Ident(ident) => out.extend_from_slice(ident.source_text.get()),
+            Tok::Linebreak => out.push(ascii::LINEBREAK),
+            Tok::Align(align) => {
+                let count = usize::try_from(align.count).unwrap();
+                out.resize(out.len() + count, ascii::SPACE);
+            },
+            Tok::LineComment(lc) => {
+                out.extend_from_slice(&[ascii::FORWARDSLASH, ascii::FORWARDSLASH]);
+                out.extend_from_slice(lc.str_ref.get());
+            },
+            Tok::Unexpected(unexpected) => out.push(unexpected.ch),
+        }
+    }
+}
+
+/// Formats the token as its kind followed by its quoted source text, e.g. `Ident "main"`.
+impl<'a> std::fmt::Display for Tok<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut source_text: Vec<u8> = Vec::with_capacity(self.source_len());
+        self.write_source_text(&mut source_text);
+        write!(f, "{} \"", self.kind().name())?;
+        for ch in source_text { write!(f, "{}", ascii::escape(ch))?; }
+        return write!(f, "\"");
+    }
+}
+
+// -- TokKind ------------------------------------------------------------------------------------
+
+/// The variant of a [`Tok`] without any of its state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokKind {
+    Static(StaticTok),
+    StrLiteral,
+    DecIntLiteral,
+    Ident,
+    Linebreak,
+    Align,
+    LineComment,
+    Unexpected
+}
+
+impl TokKind {
+    const NON_STATIC: [(Self, &'static str); 7] = [
+        (Self::StrLiteral, "StrLiteral"),
+        (Self::DecIntLiteral, "DecIntLiteral"),
+        (Self::Ident, "Ident"),
+        (Self::Linebreak, "Linebreak"),
+        (Self::Align, "Align"),
+        (Self::LineComment, "LineComment"),
+        (Self::Unexpected, "Unexpected")
+    ];
+
+    /// Returns the name of the kind. Static tokens are named after their [`StaticTok`] variant.
+    pub fn name(self) -> &'static str {
+        if let Self::Static(stok) = self { return stok.name(); }
+        let (_, name) = Self::NON_STATIC.iter().find(|(kind, _)| *kind == self).unwrap();
+        return name;
+    }
+
+    /// The inverse of [`TokKind::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some((kind, _)) = Self::NON_STATIC.iter().find(|(_, n)| *n == name) {
+            return Some(*kind);
+        }
+        return StaticTok::variants().iter().find(|stok| stok.name() == name).copied()
+            .map(Self::Static);
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct StrLiteral<'a> {
     // The entirety of the source text of this string literal including the
@@ -157,6 +300,15 @@ pub struct StrLiteral<'a> {
     pub str_ref: StrRef<'a>
 }
 
+impl<'a> StrLiteral<'a> {
+    /// Returns the text between the quotes. String literals have no escape sequences.
+    pub fn content(&self) -> &'a [u8] {
+        let text = self.str_ref.get();
+        let text = text.strip_prefix(b"\"").unwrap_or(text);
+        return text.strip_suffix(b"\"").unwrap_or(text);
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct DecIntLiteral<'a> {
     /// The character-encoded decimal digits from the source text.
@@ -1,17 +1,23 @@
-///! The token representation is modeled after Google's Carbon Language compiler as described by
-///! Chandler Carruth in his talk "Modernizing Compiler Design for Carbon Toolchain" at CppNow 2023.
-///! See https://www.youtube.com/watch?v=ZI198eFghJk&t=2817s.
+//! The token representation is modeled after Google's Carbon Language compiler as described by
+//! Chandler Carruth in his talk "Modernizing Compiler Design for Carbon Toolchain" at CppNow 2023.
+//! See https://www.youtube.com/watch?v=ZI198eFghJk&t=2817s.
 
+use std::fmt;
 use std::num::{NonZeroU32, NonZeroU8};
+use std::ops::Range;
 use crate::util::str_interner::StrInterner;
 use crate::util::str_list::{StrRef, StrList, StrListKey, StrListRef};
 use crate::util::bits::Truncate;
 use crate::util::ascii;
 use crate::tok::ident::Ident;
+use crate::tok::lex::lex;
 use crate::tok::tok::{Tok, DecIntLiteral, StaticTok, StrLiteral, LineComment, Align, Unexpected};
 
+pub mod cache;
+
+/// The ordering of keys is consistent with the order of the tokens in the buffer.
 #[repr(transparent)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Key { data: NonZeroU32 }
 
 impl Key {
@@ -26,6 +32,14 @@ impl Key {
     }
 }
 
+/// Formats the key as the address of its entry and its index among the tokens packed into that
+/// entry, such as `12.1`.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}.{}", self.addr(), self.pack_idx());
+    }
+}
+
 /// A dense representation of a source file.
 ///
 /// Unlike the character-encoded source file, the [`TokBuf`] arranges the information in the 
@@ -48,9 +62,46 @@ pub struct TokBuf<'a> {
     /// However narrow tokens (static tokens) are packed together into triplets. That is, up to 
     /// three static tokens per one [`TokBufEntry`]. 
     buf: Vec<TokBufEntry>,
+
+    /// The byte offset in the source text of the first token in each [`TokBufEntry`].
+    offsets: Vec<u32>,
     
     lines: Vec<u32>,
-    len: usize
+    len: usize,
+
+    /// The number of strings in `str_table` which belong to tokens replaced by
+    /// [`TokBuf::apply_edit`], see [`TokBuf::compact_str_table`].
+    dead_strs: usize,
+
+    /// The length in bytes of the source text represented by this buffer.
+    source_len: usize
+}
+
+/// Describes the tokens replaced by [`TokBuf::apply_edit`].
+///
+/// The tokens in `old` (keys into the buffer before the edit) were replaced by the tokens in `new`
+/// (keys into the buffer after the edit). Both ranges begin at the same key. Tokens preceding the
+/// range keep their keys. The keys of tokens following the range are shifted by the difference
+/// in length between `new` and `old`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokBufEdit {
+    pub old: Range<Key>,
+    pub new: Range<Key>
+}
+
+impl TokBufEdit {
+    /// Returns the key after the edit of the token which had `key` before the edit, or `None` if
+    /// that token was replaced.
+    pub fn rebase(&self, key: Key) -> Option<Key> {
+        if key < self.old.start { return Some(key); }
+        if key < self.old.end { return None; }
+        let addr = i64::from(key.addr()) + i64::from(self.new.end.addr())
+            - i64::from(self.old.end.addr());
+        return Some(Key::new(u32::try_from(addr).unwrap(), key.pack_idx()));
+    }
+
+    /// Returns whether the keys of the tokens following the range were shifted.
+    pub fn shifts_keys(&self) -> bool { return self.new.end.addr() != self.old.end.addr(); }
 }
 
 impl<'a> TokBuf<'a> {
@@ -63,15 +114,19 @@ impl<'a> TokBuf<'a> {
             // Worst case there are no packable tokens, therefore every token occupies an entire
             // TokBufEntry.
             buf: Vec::with_capacity(capacity),
+            offsets: Vec::with_capacity(capacity),
             // Worst case the file is comprised completely of linebreaks with no other tokens.
             // Therefore, for `capacity` tokens there are `capacity` lines.
             lines: Vec::with_capacity(capacity),
-            len: 0
+            len: 0,
+            dead_strs: 0,
+            source_len: 0
         };
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.buf.shrink_to_fit();
+        self.offsets.shrink_to_fit();
         self.lines.shrink_to_fit();
         self.str_table.shrink_to_fit();
     }
@@ -115,7 +170,7 @@ impl<'a> TokBuf<'a> {
 
     fn push_ident(&mut self, ident: Ident) {
         let intern_key = self.string_interner.intern(ident.source_text.get());
-        let etc = u32::try_from(intern_key.get()).unwrap();
+        let etc = intern_key.get();
         let entry = TokBufEntry::new(EntryType::Ident, etc);
         self.buf.push(entry);
     }
@@ -145,6 +200,7 @@ impl<'a> TokBuf<'a> {
     }
     
     pub fn push(&mut self, tok: Tok) {
+        let entry_count = self.buf.len();
         match tok {
             Tok::Static(stok) => self.push_static_tok(stok),
             Tok::StrLiteral(lit) => self.push_str_literal(lit),
@@ -155,13 +211,30 @@ impl<'a> TokBuf<'a> {
             Tok::LineComment(lc) => self.push_line_comment(lc),
             Tok::Unexpected(unexpected) => self.push_unexpected(unexpected),
         }
+        if self.buf.len() > entry_count {
+            self.offsets.push(u32::try_from(self.source_len).unwrap());
+        }
         self.len += 1;
+        self.source_len += tok.source_len();
     }
 
     pub fn len(&self) -> usize { return self.len; }
 
+    /// Returns the length in bytes of the source text this buffer was lexed from.
+    pub fn source_len(&self) -> usize { return self.source_len; }
+
+    pub fn is_empty(&self) -> bool { return self.len == 0; }
+
+    /// Returns the range of keys spanning every token in the buffer. The end of the range is the
+    /// key immediately past the last token.
+    pub fn key_range(&self) -> Range<Key> {
+        return Key::new(0, 0)..self.end_key();
+    }
+
+    fn end_key(&self) -> Key { return Key::new(u32::try_from(self.buf.len()).unwrap(), 0); }
+
     pub fn iter(&'a self) -> impl Iterator<Item = Tok<'a>> + 'a {
-        return TokBufIterator { cursor: TokCursor::new(&self) };
+        return TokBufIterator { cursor: TokCursor::new(self) };
     }
 
     pub fn get(&'a self, key: Key) -> Option<Tok<'a>> {
@@ -211,9 +284,178 @@ impl<'a> TokBuf<'a> {
         }
     }
 
+    /// Returns the range of bytes in the source text occupied by the token at `key`.
+    pub fn byte_range(&self, key: Key) -> Option<Range<usize>> {
+        let tok = self.get(key)?;
+        let mut begin = usize::try_from(self.offsets[usize::try_from(key.addr()).ok()?]).ok()?;
+        for pack_idx in 0..key.pack_idx() {
+            begin += self.get(Key::new(key.addr(), pack_idx)).unwrap().source_len();
+        }
+        return Some(begin..(begin + tok.source_len()));
+    }
+
+    /// Returns the address of the entry containing the byte at `offset` in the source text.
+    /// If `offset` is the length of the source text, the last entry is returned.
+    fn entry_at_offset(&self, offset: usize) -> Option<usize> {
+        let offset = u32::try_from(offset).unwrap();
+        return self.offsets.partition_point(|entry_offset| *entry_offset <= offset).checked_sub(1);
+    }
+
+    /// Returns the tokens held by the entry at `addr`.
+    fn entry_toks(&self, addr: usize) -> impl Iterator<Item = Tok<'_>> + '_ {
+        let addr = u32::try_from(addr).unwrap();
+        return (0..3).map_while(move |pack_idx| self.get(Key::new(addr, pack_idx)));
+    }
+
+    /// Replaces the bytes `range` of the source text with `new_text`, relexing only the affected
+    /// region of the buffer.
+    ///
+    /// Lexing restarts immediately after the last linebreak preceding the edit. At that point
+    /// the lexer holds no state, since a linebreak never occurs inside a token other than a
+    /// string literal (which is represented by a single entry). Lexing continues until
+    /// it produces a linebreak token at the position of a linebreak token of the old buffer
+    /// which follows the edit. From there on the old tokens are still valid, so they are kept.
+    ///
+    /// The linebreaks around the edit are found by binary search, and the candidates for the
+    /// sync point are tried lazily. The entries following the edit are still moved, and their
+    /// offsets and line addresses shifted, which is linear in their number.
+    ///
+    /// The strings in the string table belonging to replaced tokens are reclaimed once there
+    /// are as many of them as entries in the buffer, see [`TokBuf::compact_str_table`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is not a range of bytes within the source text.
+    pub fn apply_edit(&mut self, range: Range<usize>, new_text: &[u8]) -> TokBufEdit {
+        assert!(range.start <= range.end && range.end <= self.source_len,
+            "edit range {:?} is out of bounds for a source text of length {}", range,
+            self.source_len);
+
+        let restart_addr = match self.entry_at_offset(range.start) {
+            Some(edit_addr) => {
+                let edit_addr = u32::try_from(edit_addr).unwrap();
+                let lines_before = self.lines.partition_point(|addr| *addr < edit_addr);
+                self.lines[..lines_before].iter().rev()
+                    .map(|addr| usize::try_from(*addr).unwrap())
+                    .find(|addr| self.buf[*addr].kind() == EntryType::Linebreak)
+                    .map_or(0, |addr| addr + 1)
+            },
+            None => 0
+        };
+        let restart_offset = self.offsets.get(restart_addr)
+            .map_or(self.source_len, |offset| usize::try_from(*offset).unwrap());
+
+        // The index into `lines` of the first candidate for the sync point.
+        let sync_from = match self.entry_at_offset(range.end) {
+            Some(edit_end_addr) => {
+                let edit_end_addr = u32::try_from(edit_end_addr).unwrap();
+                self.lines.partition_point(|addr| *addr < edit_end_addr)
+            },
+            None => self.lines.len()
+        };
+
+        // Relex a window of the new source text ending at a linebreak of the old source text.
+        // If the window does not end with a linebreak token, the edit has changed the meaning of
+        // the linebreak (by opening a string literal, for instance), so the window must grow.
+        let mut skip: usize = 0;
+        let (old_end_addr, scratch) = loop {
+            let sync_candidate = self.lines[sync_from..].iter()
+                .map(|addr| usize::try_from(*addr).unwrap())
+                .filter(|addr| self.buf[*addr].kind() == EntryType::Linebreak)
+                .nth(skip);
+            let old_end_addr = match sync_candidate {
+                Some(linebreak_addr) => linebreak_addr + 1,
+                None => self.buf.len()
+            };
+            let mut window: Vec<u8> = Vec::new();
+            for addr in restart_addr..old_end_addr {
+                for tok in self.entry_toks(addr) { tok.write_source_text(&mut window); }
+            }
+            window.splice((range.start - restart_offset)..(range.end - restart_offset),
+                new_text.iter().copied());
+            let scratch = lex(&window, self.string_interner);
+            let in_sync = scratch.iter().last().is_some_and(|tok| matches!(tok, Tok::Linebreak));
+            if in_sync || old_end_addr == self.buf.len() { break (old_end_addr, scratch); }
+            skip = (skip + 1) * 2 - 1;
+        };
+
+        // Splice the relexed window into the buffer.
+        let old_tok_count: usize = (restart_addr..old_end_addr)
+            .map(|addr| self.entry_toks(addr).count())
+            .sum();
+        self.dead_strs += self.buf[restart_addr..old_end_addr].iter()
+            .filter(|entry| entry.holds_str())
+            .count();
+        let new_entries: Vec<TokBufEntry> = scratch.buf.iter()
+            .map(|entry| {
+                if !entry.holds_str() { return *entry; }
+                let content = scratch.str_table.get(StrListKey::try_from(entry.etc()).unwrap());
+                return TokBufEntry::new(entry.kind(), self.insert_str_table_entry(content));
+            })
+            .collect();
+        let new_end_addr = restart_addr + new_entries.len();
+        let entry_delta = new_end_addr as isize - old_end_addr as isize;
+        let byte_delta = new_text.len() as isize - range.len() as isize;
+        let restart_offset = u32::try_from(restart_offset).unwrap();
+
+        self.buf.splice(restart_addr..old_end_addr, new_entries);
+
+        let new_offsets = scratch.offsets.iter().map(|offset| offset + restart_offset);
+        self.offsets.splice(restart_addr..old_end_addr, new_offsets);
+        for offset in &mut self.offsets[new_end_addr..] {
+            *offset = u32::try_from(*offset as isize + byte_delta).unwrap();
+        }
+
+        let restart_addr_u32 = u32::try_from(restart_addr).unwrap();
+        let old_end_addr_u32 = u32::try_from(old_end_addr).unwrap();
+        let lines_begin = self.lines.partition_point(|addr| *addr < restart_addr_u32);
+        let lines_end = self.lines.partition_point(|addr| *addr < old_end_addr_u32);
+        let new_lines = scratch.lines.iter().map(|addr| addr + restart_addr_u32);
+        self.lines.splice(lines_begin..lines_end, new_lines);
+        for addr in &mut self.lines[(lines_begin + scratch.lines.len())..] {
+            *addr = u32::try_from(*addr as isize + entry_delta).unwrap();
+        }
+
+        self.len = self.len - old_tok_count + scratch.len;
+        self.source_len = usize::try_from(self.source_len as isize + byte_delta).unwrap();
+        if self.dead_strs >= self.buf.len() { self.compact_str_table(); }
+
+        let start = Key::new(restart_addr_u32, 0);
+        return TokBufEdit {
+            old: start..Key::new(old_end_addr_u32, 0),
+            new: start..Key::new(u32::try_from(new_end_addr).unwrap(), 0)
+        };
+    }
+
+    /// Recomputes [`TokBuf::offsets`] and [`TokBuf::source_len`] from the entries.
+    fn rebuild_offsets(&mut self) {
+        let mut offsets: Vec<u32> = Vec::with_capacity(self.buf.len());
+        let mut source_len: usize = 0;
+        for addr in 0..self.buf.len() {
+            offsets.push(u32::try_from(source_len).unwrap());
+            source_len += self.entry_toks(addr).map(|tok| tok.source_len()).sum::<usize>();
+        }
+        self.offsets = offsets;
+        self.source_len = source_len;
+    }
+
+    /// Rebuilds the string table from the strings of the entries in the buffer, reclaiming the
+    /// strings of the tokens replaced by edits. The cost is linear in the size of the buffer,
+    /// and is amortized over at least as many replaced strings.
+    fn compact_str_table(&mut self) {
+        let str_table = StrList::default();
+        for entry in &mut self.buf {
+            if !entry.holds_str() { continue; }
+            let content = self.str_table.get(StrListKey::try_from(entry.etc()).unwrap());
+            *entry = TokBufEntry::new(entry.kind(), str_table.push(content).get());
+        }
+        self.str_table = str_table;
+        self.dead_strs = 0;
+    }
+
     fn insert_str_table_entry(&mut self, entry: &[u8]) -> Etc {
         let str_table_key = self.str_table.push(entry);
-        let etc = u32::try_from(str_table_key.get()).unwrap();
+        let etc = str_table_key.get();
         return etc;
     }
 
@@ -230,11 +472,13 @@ impl<'a> TokBuf<'a> {
 
 #[cfg(test)]
 mod test_tok_buf {
+    use std::ops::Range;
     use crate::tok::ident::Ident;
+    use crate::tok::lex::lex;
     use crate::util::str_interner::StrInterner;
     use crate::tok::tok::{StaticTok, StrLiteral, Tok};
     use crate::util::str_list::StrRef;
-    use super::TokBuf;
+    use super::{Key, TokBuf, TokBufEdit};
 
     #[test]
     fn test_static_pack() {
@@ -272,6 +516,83 @@ mod test_tok_buf {
         let Tok::Ident(ident) = toks[0] else { panic!(); };
         assert_eq!(ident.source_text.get(), SOURCE_TEXT);
     }
+
+    /// Applies the edit to a lexed `source_text`, then asserts that the resulting buffer is
+    /// indistinguishable from one produced by lexing the edited source text from scratch.
+    fn check_edit(source_text: &str, range: Range<usize>, new_text: &str) -> TokBufEdit {
+        let interner = StrInterner::default();
+        let mut tokbuf = lex(source_text.as_bytes(), &interner);
+        let edit = tokbuf.apply_edit(range.clone(), new_text.as_bytes());
+
+        let mut edited_text = source_text.to_string();
+        edited_text.replace_range(range, new_text);
+        let expected = lex(edited_text.as_bytes(), &interner);
+
+        let actual_toks: Vec<Tok> = tokbuf.iter().collect();
+        let expected_toks: Vec<Tok> = expected.iter().collect();
+        assert_eq!(format!("{:?}", actual_toks), format!("{:?}", expected_toks));
+        assert_eq!(tokbuf.len(), expected.len());
+        assert_eq!(tokbuf.source_len(), expected.source_len());
+        assert_eq!(tokbuf.offsets, expected.offsets);
+        assert_eq!(tokbuf.lines, expected.lines);
+        return edit;
+    }
+
+    const SOURCE_TEXT: &'static str = "\
+        proc main(): int {\n    \
+            let x = 1;\n    \
+            let y = \"a\";\n\
+        }\n\
+        proc other(): int {}\n\
+    ";
+
+    #[test]
+    fn test_edit_within_line() {
+        let offset = SOURCE_TEXT.find("x =").unwrap();
+        let edit = check_edit(SOURCE_TEXT, offset..(offset + 1), "xyz");
+        // Only the edited line is relexed.
+        assert_eq!(edit.old.start, Key::new(7, 0));
+        assert_eq!(edit.old.end, Key::new(14, 0));
+        assert_eq!(edit.new.end, Key::new(14, 0));
+    }
+
+    #[test]
+    fn test_edit_joins_lines() {
+        let offset = SOURCE_TEXT.find(";\n").unwrap() + 1;
+        check_edit(SOURCE_TEXT, offset..(offset + 5), "");
+    }
+
+    #[test]
+    fn test_edit_opens_str_literal() {
+        let offset = SOURCE_TEXT.find("1;").unwrap();
+        check_edit(SOURCE_TEXT, offset..offset, "\"");
+        let offset = SOURCE_TEXT.find("}\nproc").unwrap();
+        check_edit(SOURCE_TEXT, offset..offset, "\"");
+    }
+
+    #[test]
+    fn test_edit_reclaims_strs() {
+        let interner = StrInterner::default();
+        let mut tokbuf = lex(SOURCE_TEXT.as_bytes(), &interner);
+        let offset = SOURCE_TEXT.find("\"a\"").unwrap();
+        for _ in 0..100 {
+            tokbuf.apply_edit(offset..(offset + 3), b"\"b\"");
+            assert!(tokbuf.dead_strs < tokbuf.buf.len());
+        }
+        let expected = lex(SOURCE_TEXT.replace("\"a\"", "\"b\"").as_bytes(), &interner);
+        let actual_toks: Vec<Tok> = tokbuf.iter().collect();
+        let expected_toks: Vec<Tok> = expected.iter().collect();
+        assert_eq!(format!("{:?}", actual_toks), format!("{:?}", expected_toks));
+    }
+
+    #[test]
+    fn test_edit_boundaries() {
+        check_edit(SOURCE_TEXT, 0..0, "// Hello\n");
+        check_edit(SOURCE_TEXT, 0..4, "struct");
+        check_edit(SOURCE_TEXT, SOURCE_TEXT.len()..SOURCE_TEXT.len(), "proc");
+        check_edit(SOURCE_TEXT, 0..SOURCE_TEXT.len(), "");
+        check_edit("", 0..0, "proc main");
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -355,37 +676,59 @@ impl TokBufEntry {
         let data = kind_bits | etc;
         return Self { data };
     }
+
+    /// Returns whether `etc` is the key of the entry's content in the string table.
+    fn holds_str(self) -> bool {
+        return matches!(self.kind(),
+            EntryType::StrLiteral | EntryType::DecIntLiteral | EntryType::LineComment);
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct TokCursor<'a> {
     pos_key: Key,
-    pos_idx: usize,
+
+    /// The key at which the cursor stops, as though the buffer ended there.
+    end_key: Key,
     tokbuf: &'a TokBuf<'a>
 }
 
 impl<'a> TokCursor<'a> {
     pub fn new(tokbuf: &'a TokBuf<'a>) -> Self {
-        Self { pos_key: Key::new(0, 0), pos_idx: 0, tokbuf }
+        Self { pos_key: Key::new(0, 0), end_key: tokbuf.end_key(), tokbuf }
+    }
+
+    /// Returns a cursor over the tokens in `range` only, positioned at its start. The range
+    /// must begin and end at keys which [`TokCursor::seek`] would accept.
+    pub fn with_range(tokbuf: &'a TokBuf<'a>, range: Range<Key>) -> Self {
+        return Self { pos_key: range.start, end_key: range.end, tokbuf };
     }
 
     /// Returns the [`Tok`] at the cursor's position, or None if the cursor is at the
     /// end of the buffer.
-    pub fn read_tok(&self) -> Option<Tok<'a>> { return self.tokbuf.get(self.pos_key); }
+    pub fn read_tok(&self) -> Option<Tok<'a>> {
+        if self.pos_key >= self.end_key { return None; }
+        return self.tokbuf.get(self.pos_key);
+    }
 
     /// Returns the [`Key`] of the next token in the buffer. 
     /// Or, if no tokens remain, the key points to a nonexistent token immediately past
     /// the last real token in the buffer.
     pub fn at(&self) -> Key { return self.pos_key; }
 
-    pub fn has_next(&self) -> bool { return self.pos_idx < self.tokbuf.len(); }
+    pub fn has_next(&self) -> bool { return self.read_tok().is_some(); }
+
+    /// Moves the cursor to `key`, which must be the key of a token in the buffer or the key
+    /// returned by [`TokCursor::at`] once no tokens remain.
+    pub fn seek(&mut self, key: Key) {
+        debug_assert!(key == self.tokbuf.end_key() || self.tokbuf.get(key).is_some());
+        self.pos_key = key;
+    }
 
     /// Advances the cursor past the next token in the buffer. If no tokens remain, this is a no-op.
     pub fn advance(&mut self)  {
         if !self.has_next() { return; }
 
-        self.pos_idx += 1;
-
         let next_pack_key = Key::new(self.pos_key.addr(), self.pos_key.pack_idx() + 1);
         if self.tokbuf.get(next_pack_key).is_some() {
             self.pos_key = next_pack_key;
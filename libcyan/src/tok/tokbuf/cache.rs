@@ -0,0 +1,330 @@
+//! A versioned binary serialization of [`TokBuf`], intended for an on-disk cache of token
+//! buffers so that unchanged source units need not be relexed on every build.
+//!
+//! # Format
+//! All integers are little-endian.
+//!
+//! ```txt
+//! magic          [u8; 4]   "CYTB"
+//! version        u32       FORMAT_VERSION
+//! source_digest  u64       `digest` of the source text the buffer was lexed from
+//! checksum       u64       `fnv1a_64` of everything after this field
+//! tok_count      u32
+//! entry_count    u32
+//! entries        [u32; entry_count]
+//! line_count     u32
+//! lines          [u32; line_count]
+//! str_count      u32
+//! strs           [(len: u32, bytes: [u8; len]); str_count]
+//! ident_count    u32
+//! idents         [(len: u32, bytes: [u8; len]); ident_count]
+//! ```
+//!
+//! The keys stored in a [`TokBuf`] are only meaningful relative to its own string table and
+//! the [`StrInterner`] it was lexed with. Therefore the etc-space of every entry which refers
+//! to a string is rewritten as an ordinal into `strs` (or `idents`) when encoding. When decoding,
+//! the strings are pushed into a fresh string table (idents are re-interned) and the ordinals are
+//! translated back into keys.
+//!
+//! The decoder never trusts its input. Every field is bounds-checked, so a stale, truncated,
+//! or otherwise corrupt cache is rejected with a [`CacheError`] instead of producing a
+//! malformed [`TokBuf`].
+
+use crate::util::bits::{fnv1a_64, Truncate};
+use crate::util::str_interner::StrInterner;
+use crate::util::str_list::{StrList, StrListKey};
+use crate::tok::tok::StaticTok;
+use super::{EntryType, Etc, TokBuf, TokBufEntry};
+
+const MAGIC: [u8; 4] = *b"CYTB";
+
+/// Incremented whenever the layout of the serialized [`TokBuf`] changes, or the lexer would
+/// produce different tokens from the same source text. Caches written by a different version are
+/// rejected.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The size in bytes of the fields preceding the checksummed payload.
+const HEADER_LEN: usize = 4 + 4 + 8 + 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheError {
+    /// The bytes do not begin with the cache magic number. They are not a cached [`TokBuf`].
+    BadMagic,
+    /// The cache was written by an incompatible version of the compiler.
+    UnsupportedVersion(u32),
+    /// The cache is intact but was produced from a different source text.
+    Stale,
+    /// The cache is truncated, fails its checksum, or is internally inconsistent.
+    Corrupt
+}
+
+/// Computes the digest of the source text which is recorded in the cache header.
+/// A cache is only accepted by [`decode`] if it was encoded with the same digest.
+pub fn digest(source_text: &[u8]) -> u64 { return fnv1a_64(source_text); }
+
+// -- Encoder ------------------------------------------------------------------------------------
+
+pub fn encode(tokbuf: &TokBuf, source_digest: u64) -> Vec<u8> {
+    let mut strs: Vec<&[u8]> = Vec::new();
+    let mut idents: Vec<&[u8]> = Vec::new();
+    let mut entries: Vec<u32> = Vec::with_capacity(tokbuf.buf.len());
+    for entry in &tokbuf.buf {
+        let etc = match entry.kind() {
+            EntryType::StrLiteral | EntryType::DecIntLiteral | EntryType::LineComment => {
+                strs.push(tokbuf.str_table.get(str_list_key(entry.etc())));
+                ordinal(strs.len() - 1)
+            },
+            EntryType::Ident => {
+                idents.push(tokbuf.string_interner.str_list().get(str_list_key(entry.etc())));
+                ordinal(idents.len() - 1)
+            },
+            _ => entry.etc()
+        };
+        entries.push(TokBufEntry::new(entry.kind(), etc).data);
+    }
+
+    let mut payload: Vec<u8> = Vec::new();
+    write_u32(&mut payload, u32::try_from(tokbuf.len).unwrap());
+    write_u32s(&mut payload, &entries);
+    write_u32s(&mut payload, &tokbuf.lines);
+    write_strs(&mut payload, &strs);
+    write_strs(&mut payload, &idents);
+
+    let mut out: Vec<u8> = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&MAGIC);
+    write_u32(&mut out, FORMAT_VERSION);
+    out.extend_from_slice(&source_digest.to_le_bytes());
+    out.extend_from_slice(&fnv1a_64(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    return out;
+}
+
+fn str_list_key(etc: Etc) -> StrListKey { return StrListKey::try_from(etc).unwrap(); }
+
+fn ordinal(idx: usize) -> Etc {
+    let ordinal = u32::try_from(idx).unwrap();
+    // Ordinals are bounded by the number of entries, which are themselves addressed by 24 bits.
+    assert!(ordinal < (1 << 24));
+    return ordinal;
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) { out.extend_from_slice(&value.to_le_bytes()); }
+
+fn write_u32s(out: &mut Vec<u8>, values: &[u32]) {
+    write_u32(out, u32::try_from(values.len()).unwrap());
+    for value in values { write_u32(out, *value); }
+}
+
+fn write_strs(out: &mut Vec<u8>, strs: &[&[u8]]) {
+    write_u32(out, u32::try_from(strs.len()).unwrap());
+    for s in strs {
+        write_u32(out, u32::try_from(s.len()).unwrap());
+        out.extend_from_slice(s);
+    }
+}
+
+// -- Decoder ------------------------------------------------------------------------------------
+
+/// Reconstructs the [`TokBuf`] serialized in `bytes`. Idents are interned into `interner`.
+///
+/// `bytes` need not be aligned, so it may point directly into a memory-mapped cache file.
+pub fn decode<'a>(bytes: &[u8], source_digest: u64, interner: &'a StrInterner)
+-> Result<TokBuf<'a>, CacheError>
+{
+    let mut reader = ByteReader { bytes, pos: 0 };
+    if reader.read_n(MAGIC.len()) != Ok(&MAGIC) { return Err(CacheError::BadMagic); }
+    let version = reader.read_u32()?;
+    if version != FORMAT_VERSION { return Err(CacheError::UnsupportedVersion(version)); }
+    let recorded_digest = reader.read_u64()?;
+    let checksum = reader.read_u64()?;
+    if fnv1a_64(reader.rem()) != checksum { return Err(CacheError::Corrupt); }
+    if recorded_digest != source_digest { return Err(CacheError::Stale); }
+
+    let tok_count = reader.read_len()?;
+    let entry_count = reader.read_len()?;
+    let mut entries: Vec<u32> = Vec::with_capacity(entry_count.min(reader.rem().len() / 4));
+    for _ in 0..entry_count { entries.push(reader.read_u32()?); }
+    let line_count = reader.read_len()?;
+    let mut lines: Vec<u32> = Vec::with_capacity(line_count.min(reader.rem().len() / 4));
+    for _ in 0..line_count { lines.push(reader.read_u32()?); }
+
+    let str_table = StrList::default();
+    let mut str_keys: Vec<StrListKey> = Vec::new();
+    for _ in 0..reader.read_len()? {
+        let len = reader.read_len()?;
+        str_keys.push(str_table.push(reader.read_n(len)?));
+    }
+    let mut ident_keys: Vec<StrListKey> = Vec::new();
+    for _ in 0..reader.read_len()? {
+        let len = reader.read_len()?;
+        ident_keys.push(interner.intern(reader.read_n(len)?));
+    }
+    if !reader.rem().is_empty() { return Err(CacheError::Corrupt); }
+
+    let mut buf: Vec<TokBufEntry> = Vec::with_capacity(entries.len());
+    let mut counted_toks: usize = 0;
+    for data in entries {
+        let kind_id: u8 = (data >> (3 * 8)).truncate();
+        let kind = EntryType::from_id(kind_id).ok_or(CacheError::Corrupt)?;
+        let etc = TokBufEntry { data }.etc();
+        let etc = match kind {
+            EntryType::StaticPack => {
+                counted_toks += count_static_pack(etc)?;
+                etc
+            },
+            EntryType::StrLiteral | EntryType::DecIntLiteral | EntryType::LineComment => {
+                counted_toks += 1;
+                resolve_ordinal(&str_keys, etc)?
+            },
+            EntryType::Ident => {
+                counted_toks += 1;
+                resolve_ordinal(&ident_keys, etc)?
+            },
+            EntryType::Unexpected => {
+                counted_toks += 1;
+                if etc > u32::from(u8::MAX) { return Err(CacheError::Corrupt); }
+                etc
+            },
+            EntryType::Linebreak | EntryType::Align => {
+                counted_toks += 1;
+                etc
+            }
+        };
+        buf.push(TokBufEntry::new(kind, etc));
+    }
+    if counted_toks != tok_count { return Err(CacheError::Corrupt); }
+
+    let entry_count = u32::try_from(buf.len()).map_err(|_| CacheError::Corrupt)?;
+    let lines_in_bounds = lines.iter().all(|addr| *addr < entry_count);
+    let lines_sorted = lines.windows(2).all(|pair| pair[0] <= pair[1]);
+    if !lines_in_bounds || !lines_sorted { return Err(CacheError::Corrupt); }
+
+    let mut tokbuf = TokBuf { string_interner: interner, str_table, buf, offsets: Vec::new(), lines,
+        len: tok_count, dead_strs: 0, source_len: 0 };
+    tokbuf.rebuild_offsets();
+    return Ok(tokbuf);
+}
+
+/// Validates a packed triplet of static tokens, returning the number of tokens in it.
+/// A well-formed pack holds between one and three valid [`StaticTok`] ids, filled from the
+/// least significant byte upward.
+fn count_static_pack(etc: Etc) -> Result<usize, CacheError> {
+    let mut count: usize = 0;
+    let mut ended = false;
+    for pack_idx in 0..3 {
+        let stok_id: u8 = (etc >> (pack_idx * 8)).truncate();
+        if stok_id == 0 {
+            ended = true;
+            continue;
+        }
+        if ended || StaticTok::from_id(stok_id).is_none() { return Err(CacheError::Corrupt); }
+        count += 1;
+    }
+    if count == 0 { return Err(CacheError::Corrupt); }
+    return Ok(count);
+}
+
+fn resolve_ordinal(keys: &[StrListKey], ordinal: Etc) -> Result<Etc, CacheError> {
+    let idx = usize::try_from(ordinal).map_err(|_| CacheError::Corrupt)?;
+    let key = keys.get(idx).ok_or(CacheError::Corrupt)?;
+    // Keys which do not fit into the etc-space cannot be represented by a `TokBufEntry`.
+    if key.get() >= (1 << 24) { return Err(CacheError::Corrupt); }
+    return Ok(key.get());
+}
+
+struct ByteReader<'a> { bytes: &'a [u8], pos: usize }
+
+impl<'a> ByteReader<'a> {
+    fn rem(&self) -> &'a [u8] { return &self.bytes[self.pos..]; }
+
+    fn read_n(&mut self, n: usize) -> Result<&'a [u8], CacheError> {
+        let slice = self.rem().get(..n).ok_or(CacheError::Corrupt)?;
+        self.pos += n;
+        return Ok(slice);
+    }
+
+    fn read_u32(&mut self) -> Result<u32, CacheError> {
+        let mut le_bytes = [0u8; 4];
+        le_bytes.copy_from_slice(self.read_n(4)?);
+        return Ok(u32::from_le_bytes(le_bytes));
+    }
+
+    fn read_u64(&mut self) -> Result<u64, CacheError> {
+        let mut le_bytes = [0u8; 8];
+        le_bytes.copy_from_slice(self.read_n(8)?);
+        return Ok(u64::from_le_bytes(le_bytes));
+    }
+
+    fn read_len(&mut self) -> Result<usize, CacheError> {
+        return usize::try_from(self.read_u32()?).map_err(|_| CacheError::Corrupt);
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_cache {
+    use crate::tok::lex::lex;
+    use crate::tok::tok::Tok;
+    use crate::util::str_interner::StrInterner;
+    use super::{decode, digest, encode, CacheError, HEADER_LEN};
+
+    const SOURCE_TEXT: &'static [u8] = "\
+        // Entry point\n\
+        proc main(): int {\n    \
+            std::println(\"Hello\nWorld\", 42);\n\
+        }\n\
+    ".as_bytes();
+
+    #[test]
+    fn test_round_trip() {
+        let lex_interner = StrInterner::default();
+        let tokbuf = lex(SOURCE_TEXT, &lex_interner);
+        let bytes = encode(&tokbuf, digest(SOURCE_TEXT));
+
+        // Decode into a different interner so that ident keys must be translated.
+        let cache_interner = StrInterner::default();
+        cache_interner.intern("unrelated".as_bytes());
+        let decoded = decode(&bytes, digest(SOURCE_TEXT), &cache_interner).unwrap();
+
+        assert_eq!(decoded.len(), tokbuf.len());
+        assert_eq!(decoded.lines, tokbuf.lines);
+        assert_eq!(decoded.offsets, tokbuf.offsets);
+        let expected: Vec<Tok> = tokbuf.iter().collect();
+        let actual: Vec<Tok> = decoded.iter().collect();
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+    }
+
+    #[test]
+    fn test_reject_stale() {
+        let interner = StrInterner::default();
+        let bytes = encode(&lex(SOURCE_TEXT, &interner), digest(SOURCE_TEXT));
+        let result = decode(&bytes, digest("proc main".as_bytes()), &interner);
+        assert_eq!(result.err(), Some(CacheError::Stale));
+    }
+
+    #[test]
+    fn test_reject_corrupt() {
+        let interner = StrInterner::default();
+        let bytes = encode(&lex(SOURCE_TEXT, &interner), digest(SOURCE_TEXT));
+
+        for idx in HEADER_LEN..bytes.len() {
+            let mut flipped = bytes.clone();
+            flipped[idx] ^= 0x40;
+            let result = decode(&flipped, digest(SOURCE_TEXT), &interner);
+            assert_eq!(result.err(), Some(CacheError::Corrupt));
+        }
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len], digest(SOURCE_TEXT), &interner).is_err());
+        }
+    }
+
+    #[test]
+    fn test_reject_version() {
+        let interner = StrInterner::default();
+        let mut bytes = encode(&lex(SOURCE_TEXT, &interner), digest(SOURCE_TEXT));
+        bytes[4] = bytes[4].wrapping_add(1);
+        let result = decode(&bytes, digest(SOURCE_TEXT), &interner);
+        assert!(matches!(result, Err(CacheError::UnsupportedVersion(_))));
+    }
+}
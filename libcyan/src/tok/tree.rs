@@ -0,0 +1,268 @@
+//! An index pairing the opening and closing delimiters of a [`TokBuf`] into token trees.
+//!
+//! The index is built in a single pass before parsing. Groups delimited by `(`, `[`, and `{`
+//! must be closed. An opening delimiter which is not closed before the end of the buffer, or
+//! before a closing delimiter of an enclosing group, is reported once with the locations of
+//! both. Of the groups left open at the same point, only the outermost few are reported, see
+//! `MAX_REPORTED_UNCLOSED`. The parser then trusts the index instead of reporting a missing
+//! closer of its own.
+//!
+//! `<` and `>` are also comparison operators, so angle brackets are paired tentatively. A `<`
+//! is paired with the next `>` at the same depth, unless a `;`, `{`, or closing delimiter of an
+//! enclosing group comes first. Then the `<` was a comparison, and is forgotten silently.
+//!
+//! A `)` or `]` never closes a group outside of the innermost `{`. A closing delimiter which
+//! does not close any open group is left to the parser to report.
+
+use crate::diagnostic::{self, AnyDiagnostic};
+use crate::source_unit::SourceUnitId;
+use crate::tok::tok::{StaticTok, Tok};
+use std::ops::Range;
+use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+
+pub struct TokTrees {
+    /// The keys of the opening and closing delimiter of every group, ordered by opener.
+    groups: Vec<(Key, Key)>,
+
+    /// The keys of the opening delimiters which are never closed, in order.
+    unclosed: Vec<Key>,
+
+    /// The ranges of keys within which some group, or tentative `<`, is open, in order. Each
+    /// range begins at the opener and ends after the token at which no group is open anymore.
+    enclosed: Vec<Range<Key>>,
+
+    /// Whether a group, or tentative `<`, is open at the end of the paired tokens.
+    left_open: bool
+}
+
+impl TokTrees {
+    /// Pairs the delimiters of `tokbuf`, reporting every group which is not closed.
+    pub fn build(tokbuf: &TokBuf, source_unit: SourceUnitId,
+        diagnostics: &mut Vec<AnyDiagnostic>) -> Self
+    {
+        return Self::build_in(TokCursor::new(tokbuf), source_unit, diagnostics);
+    }
+
+    /// Pairs the delimiters among the tokens in `range` of `tokbuf`, as though the buffer held
+    /// only those tokens.
+    pub fn build_range(tokbuf: &TokBuf, range: Range<Key>, source_unit: SourceUnitId,
+        diagnostics: &mut Vec<AnyDiagnostic>) -> Self
+    {
+        return Self::build_in(TokCursor::with_range(tokbuf, range), source_unit, diagnostics);
+    }
+
+    fn build_in(mut cursor: TokCursor, source_unit: SourceUnitId,
+        diagnostics: &mut Vec<AnyDiagnostic>) -> Self
+    {
+        let mut trees = TokTrees { groups: Vec::new(), unclosed: Vec::new(), enclosed: Vec::new(),
+            left_open: false };
+
+        // The opening delimiters of the groups enclosing the cursor, innermost last.
+        let mut open: Vec<(Key, StaticTok)> = Vec::new();
+        // The key at which the outermost of the groups enclosing the cursor was opened.
+        let mut enclosed_from: Option<Key> = None;
+
+        while let Some(tok) = cursor.read_tok() {
+            let at = cursor.at();
+            if open.is_empty() {
+                if let Some(from) = enclosed_from.take() { trees.enclosed.push(from..at); }
+            } else if enclosed_from.is_none() {
+                enclosed_from = Some(open[0].0);
+            }
+            cursor.advance();
+            let Tok::Static(stok) = tok else { continue; };
+            match stok {
+                StaticTok::OpenParen | StaticTok::OpenSquare | StaticTok::OpenCurly
+                | StaticTok::LessThan =>
+                {
+                    if stok == StaticTok::OpenCurly { forget_angles(&mut open); }
+                    open.push((at, stok));
+                },
+                StaticTok::GreaterThan => {
+                    if let Some((open_angle, StaticTok::LessThan)) = open.last().copied() {
+                        open.pop();
+                        trees.groups.push((open_angle, at));
+                    }
+                },
+                StaticTok::CloseParen | StaticTok::CloseSquare | StaticTok::CloseCurly => {
+                    forget_angles(&mut open);
+                    let opener = opener_of(stok);
+                    // Braces delimit items and blocks, so a `)` or `]` does not close a group
+                    // outside of the innermost `{`. A closer which closes no open group is left
+                    // to the parser.
+                    let Some(depth) = open.iter().rposition(|(_, open)| {
+                        *open == opener || *open == StaticTok::OpenCurly
+                    }) else { continue; };
+                    if open[depth].1 != opener { continue; }
+                    trees.report_unclosed(source_unit, &open[(depth + 1)..], at, diagnostics);
+                    open.truncate(depth + 1);
+                    let (open_key, _) = open.pop().unwrap();
+                    trees.groups.push((open_key, at));
+                },
+                StaticTok::Semicolon => forget_angles(&mut open),
+                _ => {}
+            }
+        }
+
+        let end = cursor.at();
+        if let Some(from) = enclosed_from.or(open.first().map(|(open, _)| *open)) {
+            trees.enclosed.push(from..end);
+        }
+        trees.left_open = !open.is_empty();
+        trees.report_unclosed(source_unit, &open, end, diagnostics);
+        trees.groups.sort_unstable();
+        trees.unclosed.sort_unstable();
+        return trees;
+    }
+
+    /// Records the groups `open`, outermost first, as never closed at `at`, and reports the
+    /// outermost of them. Tentative `<`s are not groups.
+    fn report_unclosed(&mut self, source_unit: SourceUnitId, open: &[(Key, StaticTok)],
+        at: Key, diagnostics: &mut Vec<AnyDiagnostic>)
+    {
+        let groups = open.iter().filter(|(_, delimiter)| *delimiter != StaticTok::LessThan);
+        for (idx, (open, delimiter)) in groups.enumerate() {
+            self.unclosed.push(*open);
+            if idx >= MAX_REPORTED_UNCLOSED { continue; }
+            let diagnostic = diagnostic::UnclosedDelimiter::new(source_unit, *open, *delimiter,
+                at);
+            diagnostics.push(AnyDiagnostic::UnclosedDelimiter(diagnostic));
+        }
+    }
+
+    /// Returns the key of the delimiter closing the group opened at `open`, or `None` if `open`
+    /// is not the key of a paired opening delimiter.
+    pub fn close_of(&self, open: Key) -> Option<Key> {
+        let idx = self.groups.binary_search_by_key(&open, |(open, _)| *open).ok()?;
+        return Some(self.groups[idx].1);
+    }
+
+    /// Returns whether `open` is the key of an opening delimiter which is never closed. Such a
+    /// delimiter has already been reported.
+    pub fn is_unclosed(&self, open: Key) -> bool {
+        return self.unclosed.binary_search(&open).is_ok();
+    }
+
+    /// Returns whether a group, or tentative `<`, is open before the token at `key`. Only if
+    /// none is do the tokens before `key` and those from `key` on pair independently.
+    pub fn is_enclosed(&self, key: Key) -> bool {
+        let idx = self.enclosed.partition_point(|range| range.start < key);
+        return idx > 0 && key < self.enclosed[idx - 1].end;
+    }
+
+    /// Returns whether a group, or tentative `<`, is left open at the end of the paired tokens.
+    pub fn is_left_open(&self) -> bool { return self.left_open; }
+}
+
+/// The most groups reported of those left open at the same point. The groups nested more
+/// deeply are most likely left open as a consequence of the outer ones, and are recorded as
+/// unclosed silently.
+const MAX_REPORTED_UNCLOSED: usize = 3;
+
+/// Forgets the tentative `<`s at the top of the stack, which turned out to be comparisons.
+fn forget_angles(open: &mut Vec<(Key, StaticTok)>) {
+    while let Some((_, StaticTok::LessThan)) = open.last() { open.pop(); }
+}
+
+fn opener_of(close: StaticTok) -> StaticTok {
+    return match close {
+        StaticTok::CloseParen => StaticTok::OpenParen,
+        StaticTok::CloseSquare => StaticTok::OpenSquare,
+        StaticTok::CloseCurly => StaticTok::OpenCurly,
+        _ => unreachable!(),
+    };
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_tok_trees {
+    use crate::diagnostic::AnyDiagnostic;
+    use crate::tok::lex::lex;
+    use crate::tok::tokbuf::{Key, TokBuf, TokCursor};
+    use crate::util::str_interner::StrInterner;
+    use super::TokTrees;
+
+    /// Returns the byte offset of the token at `key`.
+    fn offset(tokbuf: &TokBuf, key: Key) -> usize {
+        return tokbuf.byte_range(key).unwrap().start;
+    }
+
+    /// Byte offsets of the opening delimiter of a group, and of its closer or of the position at
+    /// which it was reported unclosed.
+    type Offsets = Vec<(usize, usize)>;
+
+    /// Returns the offsets of every group, and of every unclosed opener.
+    fn pairs(source_text: &str) -> (Offsets, Offsets) {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(source_text.as_bytes(), &str_interner);
+        let mut diagnostics: Vec<AnyDiagnostic> = Vec::new();
+        let trees = TokTrees::build(&tokbuf, 0, &mut diagnostics);
+        let groups = trees.groups.iter()
+            .map(|(open, close)| (offset(&tokbuf, *open), offset(&tokbuf, *close))).collect();
+        let unclosed = diagnostics.iter().map(|diagnostic| match diagnostic {
+            AnyDiagnostic::UnclosedDelimiter(diag) => {
+                assert!(trees.is_unclosed(diag.open()));
+                let at = tokbuf.byte_range(diag.at())
+                    .map_or(source_text.len(), |range| range.start);
+                (offset(&tokbuf, diag.open()), at)
+            },
+            _ => panic!()
+        }).collect();
+        return (groups, unclosed);
+    }
+
+    #[test]
+    fn test_balanced() {
+        assert_eq!(pairs("f(a[1], {b})"), (vec![(1, 11), (3, 5), (8, 10)], vec![]));
+    }
+
+    #[test]
+    fn test_angles() {
+        // Generic arguments are paired, comparisons are not.
+        assert_eq!(pairs("List<Map<K, V>>"), (vec![(4, 14), (8, 13)], vec![]));
+        assert_eq!(pairs("{ a < b; c > d }"), (vec![(0, 15)], vec![]));
+        assert_eq!(pairs("(a < b)"), (vec![(0, 6)], vec![]));
+    }
+
+    #[test]
+    fn test_unclosed() {
+        // The `(` is closed by neither the `}` nor the end of the buffer.
+        assert_eq!(pairs("{ f(a }"), (vec![(0, 6)], vec![(3, 6)]));
+        assert_eq!(pairs("{ [a] "), (vec![(2, 4)], vec![(0, 6)]));
+        // A stray closer is not reported.
+        assert_eq!(pairs("a) {}"), (vec![(3, 4)], vec![]));
+        assert_eq!(pairs("( {a)} )"), (vec![(0, 7), (2, 5)], vec![]));
+    }
+
+    #[test]
+    fn test_enclosed() {
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(b"a (b) c {d <e\n", &str_interner);
+        let trees = TokTrees::build(&tokbuf, 0, &mut Vec::new());
+        let mut enclosed: Vec<usize> = Vec::new();
+        let mut cursor = TokCursor::new(&tokbuf);
+        while cursor.has_next() {
+            if trees.is_enclosed(cursor.at()) { enclosed.push(offset(&tokbuf, cursor.at())); }
+            cursor.advance();
+        }
+        // A closer is within its group, and the `{` is left open.
+        assert_eq!(enclosed, [3, 4, 9, 10, 11, 12, 13]);
+        assert!(trees.is_left_open());
+    }
+
+    #[test]
+    fn test_unclosed_nested() {
+        // Only the outermost groups left open at the same point are reported.
+        let source_text = format!("proc f(): int {}", "{".repeat(200_000));
+        let (groups, unclosed) = pairs(&source_text);
+        assert_eq!(groups, [(6, 7)]);
+        assert_eq!(unclosed, [(14, source_text.len()), (15, source_text.len()),
+            (16, source_text.len())]);
+        // The ones nested more deeply are still known to be unclosed.
+        let str_interner = StrInterner::default();
+        let tokbuf = lex(source_text.as_bytes(), &str_interner);
+        let trees = TokTrees::build(&tokbuf, 0, &mut Vec::new());
+        assert_eq!(trees.unclosed.len(), 200_000);
+    }
+}
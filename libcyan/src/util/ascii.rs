@@ -1,7 +1,8 @@
 use std::ops::Range;
 
 pub const fn is_alphabetic_ch(ch: u8) -> bool {
-    return 65 <= ch && ch < 123;
+    return (UPPERCASE.start <= ch && ch < UPPERCASE.end)
+        || (LOWERCASE.start <= ch && ch < LOWERCASE.end);
 }
 
 pub const fn is_numeric_ch(ch: u8) -> bool {
@@ -13,6 +14,35 @@ pub const fn is_alphanumeric_ch(ch: u8) -> bool {
 }
 
 
+/// Returns a printable representation of `ch` suitable for placing between double quotes.
+/// Quotes, backslashes, and non-printable characters are escaped. The inverse of [`unescape`].
+pub fn escape(ch: u8) -> std::ascii::EscapeDefault { return ch.escape_ascii(); }
+
+/// Reverses [`escape`] for the string `s`, or returns `None` if `s` contains a malformed
+/// escape sequence.
+pub fn unescape(s: &[u8]) -> Option<Vec<u8>> {
+    let mut unescaped: Vec<u8> = Vec::with_capacity(s.len());
+    let mut iter = s.iter().copied();
+    while let Some(ch) = iter.next() {
+        if ch != BACKSLASH {
+            unescaped.push(ch);
+            continue;
+        }
+        let escaped = match iter.next()? {
+            b'n' => LINEBREAK,
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'x' => {
+                let digits = [iter.next()?, iter.next()?];
+                u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok()?
+            },
+            other => other
+        };
+        unescaped.push(escaped);
+    }
+    return Some(unescaped);
+}
+
 pub const UNDERSCORE: u8 = 95;
 pub const DOUBLE_QUOTE: u8 = 34;
 pub const BACKSLASH: u8 = 92;
@@ -22,4 +52,5 @@ pub const LINEBREAK: u8 = 10;
 
 // Ranges
 pub const DIGITS: Range<u8> = 48..58;
-pub const ALPHABET: Range<u8> = 65..123;
+pub const UPPERCASE: Range<u8> = 65..91;
+pub const LOWERCASE: Range<u8> = 97..123;
@@ -10,9 +10,21 @@ impl Truncate<u8> for u32 {
 pub fn fast_hash(s: &[u8]) -> usize {
     let mut hash: usize = 0;
     for i in 0..s.len() {
-        hash += 31usize.wrapping_pow((s.len() - i + 1) as u32)
-            .wrapping_mul(usize::from(s[i]));
-        
+        hash = hash.wrapping_add(31usize.wrapping_pow((s.len() - i + 1) as u32)
+            .wrapping_mul(usize::from(s[i])));
+    }
+    return hash;
+}
+
+/// 64-bit FNV-1a. Unlike [`fast_hash`] this is stable across platforms and distributes well
+/// enough to be used as a checksum for on-disk data.
+pub fn fnv1a_64(s: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let mut hash = OFFSET_BASIS;
+    for byte in s {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(PRIME);
     }
     return hash;
 }
@@ -1,112 +1,385 @@
-use std::alloc::{handle_alloc_error, GlobalAlloc, Layout};
+use std::alloc::{handle_alloc_error, Layout};
 use std::marker::PhantomData;
 use std::num::NonZeroU32;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 // -- Handle -------------------------------------------------------------------------------------
 
+/// A 32-bit reference to a `T` inside of a [`BumpAllocator`].
+///
+/// The low [`OFFSET_BITS`] bits of the key are the byte offset of the value in the arena, as if
+/// its chunks were contiguous. The high bits are the tag of the arena which produced the handle.
+/// Resolving a handle against an arena with another tag is rejected. Tags are assigned to arenas
+/// in turn, so only a handle of an arena created [`TAG_COUNT`] arenas apart goes undetected.
 #[repr(transparent)]
 pub struct Handle<T> {
     key: NonZeroU32,
     pd: PhantomData<T>
 }
 
+impl<T> Handle<T> {
+    fn tag(self) -> u32 { return self.key.get() >> OFFSET_BITS; }
+    fn offset(self) -> u32 { return self.key.get() & ((1 << OFFSET_BITS) - 1); }
+}
+
 impl<T> Clone for Handle<T> {
-    fn clone(&self) -> Self { return *self; }
+    fn clone(&self) -> Self { *self }
 }
 impl<T> Copy for Handle<T> {}
 
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool { return self.key == other.key; }
+}
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({}:{:#x})", self.tag(), self.offset())
+    }
+}
+
+/// The number of bits of a handle's key which hold the offset of the value. The remaining bits
+/// hold the tag of the arena, so an arena holds at most 256 MiB.
+const OFFSET_BITS: u32 = 28;
+
+/// The number of distinct arena tags. The tag zero is not used, so that keys are never zero.
+const TAG_COUNT: u32 = (1 << (32 - OFFSET_BITS)) - 1;
+
 // -- BumpAllocator ------------------------------------------------------------------------------
 
-pub struct BumpAllocator<const ALIGN: usize> {
+/// An arena of values of heterogeneous types which are never dropped individually.
+///
+/// Values are moved into the most recently allocated chunk of memory. When a value does not fit
+/// into the remainder of that chunk, a new chunk at least twice as large is allocated. Chunks
+/// are not freed before the arena is dropped, and do not move while values are borrowed from
+/// it. Only [`BumpAllocator::shrink_to_fit`], which borrows the arena exclusively, may move the
+/// last chunk.
+///
+/// Values may be of any size and alignment, but must not need to be dropped, and must be `Send`
+/// so that the arena may be moved to another thread along with them.
+pub struct BumpAllocator {
+    chunks: Vec<Chunk>,
+
+    /// The offset of the first unused byte in the last chunk.
+    pos: usize,
+
+    /// Distinguishes this arena from the arenas created shortly before and after it, see
+    /// [`Handle`].
+    tag: u32
+}
+
+struct Chunk {
+    ptr: NonNull<u8>,
     layout: Layout,
-    ptr: *mut u8,
-    pos: usize
+
+    /// The offset of the chunk's first byte, as if the chunks of the arena were contiguous.
+    start: u32
 }
 
-impl<const ALIGN: usize> BumpAllocator<ALIGN> {    
-    /// Allocates a new [`BumpAllocator`] arena with capacity `size` via the system allocator.
-    pub fn new(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, ALIGN).unwrap();
-        let ptr = unsafe { std::alloc::System.alloc(layout) };
-        assert_ne!(ptr, std::ptr::null_mut());
-        return Self { layout, ptr, pos: 0 };
+/// The size in bytes of the first chunk of an arena created by [`BumpAllocator::new`].
+const MIN_CHUNK_SIZE: usize = 1024;
+
+/// The minimum alignment of every chunk.
+const MIN_CHUNK_ALIGN: usize = 16;
+
+/// The number of arenas created so far, from which each arena's tag is derived.
+static ARENA_COUNT: AtomicU32 = AtomicU32::new(0);
+
+impl BumpAllocator {
+    /// Creates an empty arena. No memory is allocated until the first value is moved in.
+    pub fn new() -> Self {
+        let arena_no = ARENA_COUNT.fetch_add(1, Ordering::Relaxed);
+        let tag = arena_no % TAG_COUNT + 1;
+        return Self { chunks: Vec::new(), pos: 0, tag };
+    }
+
+    /// Creates an arena whose first chunk can hold at least `size` bytes.
+    pub fn with_capacity(size: usize) -> Self {
+        let mut arena = Self::new();
+        arena.push_chunk(Layout::from_size_align(size.max(1), MIN_CHUNK_ALIGN).unwrap());
+        return arena;
     }
-    
+
     /// Moves `value` into the arena and returns a reference to it in the form of a [`Handle`].
-    /// Moves the cursor forward to the next unused byte.
     ///
-    /// This procedure will panic if `value` does not fit into the remaining free space in the arena.
-    pub fn bump<T>(&mut self, value: T) -> Handle<T> {
+    /// A new chunk is allocated if `value` does not fit into the remainder of the current one.
+    pub fn bump<T: Send + 'static>(&mut self, value: T) -> Handle<T> {
         const {
-            assert!(align_of::<T>() == ALIGN);
             assert!(!std::mem::needs_drop::<T>());
         }
-        assert!(self.layout.size().saturating_sub(self.pos) >= size_of::<T>());
-        let key = self.pos.checked_add(1).unwrap();
-        let key = u32::try_from(key).ok().and_then(NonZeroU32::new).unwrap();
-        let handle = Handle { key, pd: PhantomData };
+        let layout = Layout::new::<T>();
+        let offset = match self.fit(layout) {
+            Some(offset) => offset,
+            None => {
+                let last_size = self.chunks.last().map_or(0, |chunk| chunk.layout.size());
+                let size = usize::max(MIN_CHUNK_SIZE, last_size * 2).max(layout.size());
+                let align = usize::max(MIN_CHUNK_ALIGN, layout.align());
+                self.push_chunk(Layout::from_size_align(size, align).unwrap());
+                self.fit(layout).unwrap()
+            }
+        };
+        let chunk = self.chunks.last().unwrap();
+        // Safety: `fit` guarantees that `offset` is suitably aligned for `T` and that
+        // `size_of::<T>()` bytes beyond it lie within the chunk.
         unsafe {
-            let ptr = self.ptr.add(self.pos);
-            *(self.ptr as *mut T) = value;
+            let ptr = chunk.ptr.as_ptr().add(offset);
+            (ptr as *mut T).write(value);
         }
-        self.pos += size_of::<T>();
-        return handle;
+        self.pos = offset + layout.size();
+        // `push_chunk` guarantees that every offset within the chunk fits into `OFFSET_BITS`.
+        let offset = chunk.start + u32::try_from(offset).unwrap();
+        let key = NonZeroU32::new((self.tag << OFFSET_BITS) | offset).unwrap();
+        return Handle { key, pd: PhantomData };
     }
 
-    /// Given a handle to an object in the allocation, returns a constant pointer to that object.
+    /// Given a handle to an object in the arena, returns a reference to that object.
     ///
-    /// Safety: This procedure is unsafe because invoking it with a foreign handle may
-    /// produce an inconsistently typed pointer (pointer is type *T but T is not at the address).
-    /// Moreover, this procedure can be misued to produce multiple exclusive references to the
-    /// same memory address.
-    pub unsafe fn get<T>(&self, handle: Handle<T>) -> *const T {
-        return self.get_mut(handle);
-    }
-    
-    /// Given a handle to an object in the allocation, returns a mutable pointer to that object.
+    /// Panics if `handle` was not produced by this arena.
+    pub fn get<T: 'static>(&self, handle: Handle<T>) -> &T {
+        let ptr = self.resolve(handle);
+        // Safety: `resolve` verified that `handle` was produced by this arena, which moved a `T`
+        // to `ptr`.
+        return unsafe { &*ptr };
+    }
+
+    /// Given a handle to an object in the arena, returns a mutable reference to that object.
     ///
-    /// Safety: This procedure is unsafe because invoking it with a foreign handle may
-    /// produce an inconsistently typed pointer (pointer is type *T but T is not at the address).
-    /// Moreover, this procedure can be misued to produce multiple exclusive references to the
-    /// same memory address.
-    pub unsafe fn get_mut<T>(&self, handle: Handle<T>) -> *mut T {
-        const {
-            assert!(align_of::<T>() == ALIGN);
-        }
-        let offset = usize::try_from(handle.key.get() - 1).unwrap();
-        assert!(offset < self.layout.size());
-        let ptr = self.ptr.add(offset);
-        return std::mem::transmute(ptr);
+    /// Panics if `handle` was not produced by this arena.
+    pub fn get_mut<T: 'static>(&mut self, handle: Handle<T>) -> &mut T {
+        let ptr = self.resolve(handle);
+        // Safety: `resolve` verified that `handle` was produced by this arena, which moved a `T`
+        // to `ptr`, and `self` is borrowed exclusively, so no other reference into the arena
+        // exists.
+        return unsafe { &mut *ptr };
+    }
+
+    /// Returns an iterator over the values of the linked list beginning at `head`.
+    pub fn iter_ll<T: 'static>(&self, head: Option<Handle<LLNode<T>>>) -> LLIter<'_, T> {
+        return LLIter { mem: self, next: head };
     }
 
+    /// Returns the unused memory at the end of the last chunk to the global allocator. The last
+    /// chunk may move in the process, but handles into it remain valid.
     pub fn shrink_to_fit(&mut self) {
-        let new_layout = Layout::from_size_align(self.pos, self.layout.align()).unwrap();
-        let ptr = unsafe { std::alloc::System.realloc(self.ptr, self.layout, self.pos) };
-        if ptr.is_null() { handle_alloc_error(new_layout); };
-        self.layout = new_layout;
-        self.ptr = ptr;
+        let Some(last) = self.chunks.last_mut() else { return; };
+        // A zero-sized reallocation is not permitted by `GlobalAlloc`.
+        if self.pos == 0 || self.pos == last.layout.size() { return; }
+        let new_layout = Layout::from_size_align(self.pos, last.layout.align()).unwrap();
+        // Safety: `last.ptr` was allocated with `last.layout`, and `self.pos` is non-zero.
+        let ptr = unsafe { std::alloc::realloc(last.ptr.as_ptr(), last.layout, self.pos) };
+        let Some(ptr) = NonNull::new(ptr) else { handle_alloc_error(new_layout); };
+        *last = Chunk { ptr, layout: new_layout, start: last.start };
+    }
+
+    /// Returns the offset in the last chunk at which a value with `layout` can be placed, or
+    /// `None` if the last chunk cannot accommodate it.
+    fn fit(&self, layout: Layout) -> Option<usize> {
+        let chunk = self.chunks.last()?;
+        if chunk.layout.align() < layout.align() { return None; }
+        // The chunk itself is aligned to at least `layout.align()`, so aligning the offset
+        // suffices to align the address.
+        let offset = self.pos.checked_next_multiple_of(layout.align())?;
+        if offset.checked_add(layout.size())? > chunk.layout.size() { return None; }
+        return Some(offset);
+    }
+
+    fn push_chunk(&mut self, layout: Layout) {
+        let start = self.chunks.last().map_or(0, |chunk| {
+            chunk.start + u32::try_from(chunk.layout.size()).unwrap()
+        });
+        let end = usize::try_from(start).unwrap().checked_add(layout.size());
+        assert!(end.is_some_and(|end| end <= 1 << OFFSET_BITS),
+            "arena exhausted its handle space");
+        // Safety: `layout` has a non-zero size.
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        let Some(ptr) = NonNull::new(ptr) else { handle_alloc_error(layout); };
+        self.chunks.push(Chunk { ptr, layout, start });
+        self.pos = 0;
+    }
+
+    /// Returns a pointer to the `T` referred to by `handle`, after verifying that `handle` was
+    /// produced by this arena.
+    fn resolve<T: 'static>(&self, handle: Handle<T>) -> *mut T {
+        assert!(handle.tag() == self.tag, "handle does not belong to this arena");
+        let offset = handle.offset();
+        let chunk_idx = self.chunks.partition_point(|chunk| chunk.start <= offset)
+            .checked_sub(1)
+            .expect("handle does not belong to this arena");
+        let chunk = &self.chunks[chunk_idx];
+        let offset = offset_in_chunk(chunk, offset);
+        // Catches most handles of another arena with the same tag, though not all of them.
+        let used = if chunk_idx + 1 == self.chunks.len() { self.pos } else { chunk.layout.size() };
+        let in_bounds = offset.checked_add(size_of::<T>()).is_some_and(|end| end <= used);
+        assert!(in_bounds && offset.is_multiple_of(align_of::<T>()),
+            "handle does not belong to this arena");
+        return unsafe { chunk.ptr.as_ptr().add(offset) as *mut T };
     }
 }
 
-impl<const ALIGN: usize> Drop for BumpAllocator<ALIGN> {
+/// Returns the offset within `chunk` of the byte at the arena offset `offset`.
+fn offset_in_chunk(chunk: &Chunk, offset: u32) -> usize {
+    return usize::try_from(offset - chunk.start).unwrap();
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self { return Self::new(); }
+}
+
+// Safety: the arena owns its chunks exclusively, and every value in them is `Send`.
+unsafe impl Send for BumpAllocator {}
+
+impl Drop for BumpAllocator {
     fn drop(&mut self) {
-        unsafe {
-            std::alloc::System.dealloc(self.ptr, self.layout);
+        for chunk in &self.chunks {
+            unsafe {
+                std::alloc::dealloc(chunk.ptr.as_ptr(), chunk.layout);
+            }
         }
     }
 }
 
 // -- Linked List Support ------------------------------------------------------------------------
 
+#[derive(Clone, Copy)]
 pub struct LLNode<T> {
     pub value: T,
     pub next: Option<Handle<Self>>
 }
 
-pub fn extend_ll<const ALIGN: usize, T>(mem: &mut BumpAllocator<ALIGN>,
-    tail: &mut &mut Option<Handle<LLNode<T>>>, value: T) 
-{
-    let handle = mem.bump(LLNode { value, next: None });
-    **tail = Some(handle);
-    *tail = unsafe { &mut (*mem.get_mut(handle)).next };
+/// Appends values to a linked list allocated in a [`BumpAllocator`].
+pub struct LLBuilder<T> {
+    head: Option<Handle<LLNode<T>>>,
+    tail: Option<Handle<LLNode<T>>>
+}
+
+impl<T: 'static> LLBuilder<T> {
+    pub fn new() -> Self { return Self { head: None, tail: None }; }
+
+    pub fn push(&mut self, mem: &mut BumpAllocator, value: T) where T: Send {
+        let handle = mem.bump(LLNode { value, next: None });
+        match self.tail {
+            Some(tail) => mem.get_mut(tail).next = Some(handle),
+            None => self.head = Some(handle)
+        }
+        self.tail = Some(handle);
+    }
+
+    /// Returns the first node of the list, or `None` if nothing was pushed.
+    pub fn head(&self) -> Option<Handle<LLNode<T>>> { return self.head; }
+
+    /// Returns the last node of the list, or `None` if nothing was pushed.
+    pub fn tail(&self) -> Option<Handle<LLNode<T>>> { return self.tail; }
+}
+
+impl<T: 'static> Default for LLBuilder<T> {
+    fn default() -> Self { return Self::new(); }
+}
+
+pub struct LLIter<'a, T> {
+    mem: &'a BumpAllocator,
+    next: Option<Handle<LLNode<T>>>
+}
+
+impl<'a, T: 'static> Iterator for LLIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.mem.get(self.next?);
+        self.next = node.next;
+        return Some(&node.value);
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+// These tests exercise the unsafe code in this module and are expected to pass under Miri:
+// `cargo +nightly miri test bump_allocator`.
+#[cfg(test)]
+mod test_bump_allocator {
+    use super::{BumpAllocator, Handle, LLBuilder, MIN_CHUNK_SIZE};
+
+    #[test]
+    fn test_bump_and_get() {
+        let mut mem = BumpAllocator::new();
+        let a = mem.bump(1u32);
+        let b = mem.bump([2u16, 3u16]);
+        *mem.get_mut(a) += 10;
+        assert_eq!(*mem.get(a), 11);
+        assert_eq!(*mem.get(b), [2, 3]);
+    }
+
+    #[test]
+    fn test_mixed_alignments() {
+        #[repr(align(64))]
+        struct Overaligned(u8);
+
+        let mut mem = BumpAllocator::new();
+        let a = mem.bump(1u8);
+        let b = mem.bump(2u64);
+        let c = mem.bump(Overaligned(3));
+        let d = mem.bump(());
+        let e = mem.bump(4u16);
+        assert_eq!(*mem.get(a), 1);
+        assert_eq!(*mem.get(b), 2);
+        assert_eq!(mem.get(c).0, 3);
+        assert_eq!((mem.get(c) as *const Overaligned).addr() % 64, 0);
+        assert_eq!(*mem.get(d), ());
+        assert_eq!(*mem.get(e), 4);
+    }
+
+    #[test]
+    fn test_growth() {
+        let mut mem = BumpAllocator::with_capacity(8);
+        let handles: Vec<Handle<u64>> = (0..1000).map(|value| mem.bump(value)).collect();
+        let big = mem.bump([7u8; MIN_CHUNK_SIZE * 3]);
+        let small = mem.bump(5u8);
+        mem.shrink_to_fit();
+        for (value, handle) in handles.iter().enumerate() {
+            assert_eq!(*mem.get(*handle), value as u64);
+        }
+        assert!(mem.get(big).iter().all(|byte| *byte == 7));
+        assert_eq!(*mem.get(small), 5);
+    }
+
+    #[test]
+    fn test_shrink_empty() {
+        let mut mem = BumpAllocator::new();
+        mem.shrink_to_fit();
+        let mut mem = BumpAllocator::with_capacity(64);
+        mem.shrink_to_fit();
+        let a = mem.bump(1u32);
+        mem.shrink_to_fit();
+        assert_eq!(*mem.get(a), 1);
+    }
+
+    #[test]
+    fn test_linked_list() {
+        let mut mem = BumpAllocator::new();
+        let mut ll: LLBuilder<u32> = LLBuilder::new();
+        assert!(mem.iter_ll(ll.head()).next().is_none());
+        for value in 0..4 { ll.push(&mut mem, value); }
+        let values: Vec<u32> = mem.iter_ll(ll.head()).copied().collect();
+        assert_eq!(values, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_handle_size() {
+        assert_eq!(size_of::<Handle<u64>>(), 4);
+        assert_eq!(size_of::<Option<Handle<u64>>>(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "handle does not belong to this arena")]
+    fn test_foreign_handle() {
+        let mut mem_a = BumpAllocator::new();
+        // Other tests may create arenas concurrently, so the tags are not necessarily
+        // consecutive.
+        let mut mem_b = BumpAllocator::new();
+        while mem_b.tag == mem_a.tag { mem_b = BumpAllocator::new(); }
+        mem_a.bump(1u32);
+        // The handle has the same offset as the allocation in `mem_a`.
+        let handle = mem_b.bump(2u32);
+        mem_a.get(handle);
+    }
 }
@@ -0,0 +1,40 @@
+//! Support for golden-file tests.
+//!
+//! A golden test renders each input file in a directory and compares the rendering with an
+//! expected-output file beside it. Setting the environment variable `CYAN_BLESS=1` overwrites
+//! the expected-output files with the current renderings instead.
+
+use std::path::{Path, PathBuf};
+
+pub const BLESS_VAR: &'static str = "CYAN_BLESS";
+
+/// Renders every file in `dir` (relative to the crate root) with extension `input_ext` and
+/// compares the result with the file of the same stem with extension `output_ext`.
+pub fn check_dir(dir: &str, input_ext: &str, output_ext: &str, render: impl Fn(&[u8]) -> String) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let bless = std::env::var(BLESS_VAR).is_ok_and(|value| value == "1");
+
+    let mut inputs: Vec<PathBuf> = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == input_ext))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no golden inputs in {}", dir.display());
+
+    let mut failures: Vec<String> = Vec::new();
+    for input_path in inputs {
+        let actual = render(&std::fs::read(&input_path).unwrap());
+        let output_path = input_path.with_extension(output_ext);
+        if bless {
+            std::fs::write(&output_path, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&output_path).unwrap_or_default();
+        if expected != actual {
+            failures.push(format!("{}\n--- expected\n{}--- actual\n{}", output_path.display(),
+                expected, actual));
+        }
+    }
+    assert!(failures.is_empty(), "golden outputs differ (rerun with {}=1 to bless):\n\n{}",
+        BLESS_VAR, failures.join("\n"));
+}
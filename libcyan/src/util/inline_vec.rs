@@ -29,6 +29,11 @@ impl<T, const SIZE: usize> InlineVec<T, SIZE> {
         slot.write(value);
         self.len += 1;
     }
+
+    pub fn as_slice(&self) -> &[T] {
+        // The first `len` elements are initialized.
+        return unsafe { std::slice::from_raw_parts(self.arr.as_ptr().cast::<T>(), self.len) };
+    }
 }
 
 impl<T, const SIZE: usize> Drop for InlineVec<T, SIZE> {
@@ -5,16 +5,3 @@ macro_rules! assert_matches {
 }
 
 pub(crate) use assert_matches;
-
-pub const fn max_of_usizes<const K: usize>(arr: [usize; K]) -> usize {
-    assert!(K > 0);
-    let mut i: usize = 0;
-    let mut max: usize = usize::MIN;
-    while i < arr.len() {
-        if arr[i] > max {
-            max = arr[i];
-        }
-        i += 1;
-    }
-    return max;
-}
@@ -6,3 +6,6 @@ pub mod bits;
 pub mod str_list;
 pub mod str_interner;
 pub mod inline_vec;
+pub mod side_table;
+#[cfg(test)]
+pub mod golden;
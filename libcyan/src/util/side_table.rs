@@ -0,0 +1,103 @@
+use std::marker::PhantomData;
+
+/// A key which densely numbers a set of objects beginning at zero, such as [`NodeId`].
+///
+/// [`NodeId`]: crate::parse::ast::NodeId
+pub trait DenseKey: Copy {
+    fn index(self) -> usize;
+    fn from_index(index: usize) -> Self;
+}
+
+/// A map from the dense keys of `K` to values of type `V`, backed by a vector.
+///
+/// A side table lets a pass associate its results with objects it does not own, for instance
+/// the resolved type of each expression in an AST.
+pub struct SideTable<K: DenseKey, V> {
+    values: Vec<Option<V>>,
+    pd: PhantomData<K>
+}
+
+impl<K: DenseKey, V> SideTable<K, V> {
+    pub fn new() -> Self { return Self::with_capacity(0); }
+
+    /// Creates an empty side table with room for the keys `0..capacity`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut values: Vec<Option<V>> = Vec::with_capacity(capacity);
+        values.resize_with(capacity, || None);
+        return Self { values, pd: PhantomData };
+    }
+
+    /// Associates `value` with `key`, returning the value previously associated with `key`.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let idx = key.index();
+        if idx >= self.values.len() { self.values.resize_with(idx + 1, || None); }
+        return self.values[idx].replace(value);
+    }
+
+    pub fn get(&self, key: K) -> Option<&V> {
+        return self.values.get(key.index())?.as_ref();
+    }
+
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        return self.values.get_mut(key.index())?.as_mut();
+    }
+
+    /// Returns the value associated with `key`, first associating `f()` with `key` if there is
+    /// no such value.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> &mut V {
+        let idx = key.index();
+        if idx >= self.values.len() { self.values.resize_with(idx + 1, || None); }
+        return self.values[idx].get_or_insert_with(f);
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        return self.values.get_mut(key.index())?.take();
+    }
+
+    pub fn contains_key(&self, key: K) -> bool { return self.get(key).is_some(); }
+
+    /// Returns the entries of the table in ascending order of key.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
+        return self.values.iter().enumerate()
+            .filter_map(|(idx, value)| Some((K::from_index(idx), value.as_ref()?)));
+    }
+}
+
+impl<K: DenseKey, V> Default for SideTable<K, V> {
+    fn default() -> Self { return Self::new(); }
+}
+
+impl<K: DenseKey, V> std::ops::Index<K> for SideTable<K, V> {
+    type Output = V;
+
+    fn index(&self, key: K) -> &V {
+        return self.get(key).expect("no value is associated with the key");
+    }
+}
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_side_table {
+    use super::{DenseKey, SideTable};
+
+    impl DenseKey for u32 {
+        fn index(self) -> usize { return usize::try_from(self).unwrap(); }
+        fn from_index(index: usize) -> Self { return u32::try_from(index).unwrap(); }
+    }
+
+    #[test]
+    fn test_side_table() {
+        let mut table: SideTable<u32, &'static str> = SideTable::with_capacity(2);
+        assert!(table.get(0).is_none());
+        assert!(table.get(10).is_none());
+        assert_eq!(table.insert(1, "one"), None);
+        assert_eq!(table.insert(5, "five"), None);
+        assert_eq!(table.insert(1, "uno"), Some("one"));
+        assert_eq!(table[1], "uno");
+        assert!(!table.contains_key(3));
+        assert_eq!(table.iter().collect::<Vec<_>>(), [(1, &"uno"), (5, &"five")]);
+        assert_eq!(table.remove(5), Some("five"));
+        assert!(table.get(5).is_none());
+    }
+}
@@ -79,12 +79,9 @@ fn insert(table: &mut Table, str_list: &StrList, new_str_list_key: StrListKey) {
 
 fn grow_table(table: &mut Table, str_list: &StrList) {
     let new_capacity = usize::max(1, table.arr.len()) * 2;
-    let mut new_table = Table::default();
-    new_table.arr = vec![None; new_capacity];
-    for entry in &table.arr {
-        if let Some(str_list_key) = entry {
-            insert(&mut new_table, str_list, *str_list_key);
-        }
+    let mut new_table = Table { arr: vec![None; new_capacity], occupancy: 0 };
+    for str_list_key in table.arr.iter().flatten() {
+        insert(&mut new_table, str_list, *str_list_key);
     }
     *table = new_table;
 }
@@ -7,38 +7,70 @@ use std::num::NonZeroU32;
 /// Since `StrList` elements are stored contiguously in memory, iterating over all the bytes of
 /// all the elements in the list in order has far better locality than the default `Vec<String>`. 
 ///
-/// The other use-case for [`StrList`] is as a sort-of typed bump-allocator. The internal
-/// `Vec<u8>` redoubles its capacity when it is exhausted, leaving ample unused space for
-/// future strings. This can significantly reduce the number of allocations needed when compared to
-/// `Vec::new()`-ing each time a new string appears.
+/// The other use-case for [`StrList`] is as a sort-of typed bump-allocator. Strings are appended
+/// to chunks which are never reallocated, so a string returned by [`StrList::get`] stays in place
+/// while other strings are pushed, possibly by other threads. When a string does not fit into the
+/// last chunk, a new chunk at least twice as large is allocated. This can significantly reduce the
+/// number of allocations needed when compared to `Vec::new()`-ing each time a new string appears.
 #[derive(Default, Debug)]
-pub struct StrList { state: RwLock<Vec<u8>> }
+pub struct StrList { state: RwLock<Chunks> }
 
 pub type StrListKey = NonZeroU32;
 
+#[derive(Default, Debug)]
+struct Chunks {
+    /// Each chunk is filled up to its capacity at most, so its buffer never moves.
+    chunks: Vec<Vec<u8>>,
+
+    /// The offset of the first byte of each chunk from the first byte of the list, as if the
+    /// chunks were contiguous. A key is one more than the offset of the string's header.
+    starts: Vec<usize>
+}
+
+/// The capacity in bytes of the first chunk of a list.
+const MIN_CHUNK_SIZE: usize = 256;
+
 impl StrList {
     pub fn push(&self, str: &[u8]) -> StrListKey {
-        let mut arr = self.state.write().unwrap();
-        let key = u32::try_from(arr.len() + 1).ok().and_then(NonZeroU32::new).unwrap();
-        arr.extend_from_slice(&str.len().to_ne_bytes());
-        arr.extend_from_slice(str);
+        let state = &mut *self.state.write().unwrap();
+        let size = size_of::<usize>() + str.len();
+        let fits = state.chunks.last().is_some_and(|last| last.capacity() - last.len() >= size);
+        if !fits {
+            let (start, capacity) = match state.chunks.last() {
+                Some(last) => (state.starts.last().unwrap() + last.len(), last.capacity() * 2),
+                None => (0, 0)
+            };
+            state.chunks.push(Vec::with_capacity(usize::max(MIN_CHUNK_SIZE, capacity).max(size)));
+            state.starts.push(start);
+        }
+        let last = state.chunks.last_mut().unwrap();
+        let offset = state.starts.last().unwrap() + last.len();
+        let key = u32::try_from(offset + 1).ok().and_then(NonZeroU32::new).unwrap();
+        last.extend_from_slice(&str.len().to_ne_bytes());
+        last.extend_from_slice(str);
         return key;
     }
 
     pub fn get(&self, key: StrListKey) -> &[u8] {
-        let idx = usize::try_from(key.get() - 1).unwrap();
-        let arr = self.state.read().unwrap();
+        let offset = usize::try_from(key.get() - 1).unwrap();
+        let state = self.state.read().unwrap();
+        let chunk_idx = state.starts.partition_point(|start| *start <= offset) - 1;
+        let chunk = &state.chunks[chunk_idx];
+        let idx = offset - state.starts[chunk_idx];
         let content_begin_idx = idx + size_of::<usize>();
         let mut header = [0u8; size_of::<usize>()];
-        header.copy_from_slice(&arr[idx..content_begin_idx]);
+        header.copy_from_slice(&chunk[idx..content_begin_idx]);
         let len = usize::from_ne_bytes(header);
-        let s = &arr[content_begin_idx..(content_begin_idx + len)];
-        return unsafe { std::mem::transmute(s) };
+        let s = &chunk[content_begin_idx..(content_begin_idx + len)];
+        // Safety: the bytes of a pushed string are never moved nor modified before the list is
+        // dropped or mutably borrowed, because chunks are never filled beyond their capacity.
+        return unsafe { std::mem::transmute::<&[u8], &[u8]>(s) };
     }
 
-    pub fn shrink_to_fit(&self) {
-        let mut arr = self.state.write().unwrap();
-        arr.shrink_to_fit();
+    /// Returns the unused capacity of the last chunk to the global allocator.
+    pub fn shrink_to_fit(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        if let Some(last) = state.chunks.last_mut() { last.shrink_to_fit(); }
     }
 }
 
@@ -80,3 +112,25 @@ impl<'a> StrRef<'a> {
         }
     }
 }
+
+// -- Tests --------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test_str_list {
+    use super::StrList;
+
+    #[test]
+    fn test_push_get() {
+        let list = StrList::default();
+        let first_key = list.push(b"first");
+        let first = list.get(first_key);
+        // Enough strings to fill several chunks, one larger than any chunk before it.
+        let keys: Vec<_> = (0..200).map(|i| list.push(i.to_string().repeat(i).as_bytes()))
+            .collect();
+        assert_eq!(first, b"first");
+        assert_eq!(list.get(first_key), b"first");
+        for (i, key) in keys.into_iter().enumerate() {
+            assert_eq!(list.get(key), i.to_string().repeat(i).as_bytes());
+        }
+    }
+}